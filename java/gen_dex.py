@@ -0,0 +1,424 @@
+#!/usr/bin/env python3
+"""Assemble support.dex, containing the Java support classes in this directory.
+
+The crate embeds the resulting file and loads it with InMemoryDexClassLoader
+when the app does not include the Java sources, so that apps written purely in
+Rust can use the callback-based features. The classes are tiny, so they are
+assembled here directly instead of requiring the Android SDK to build them.
+Keep the bytecode below in sync with the Java sources.
+
+Usage: python3 java/gen_dex.py
+"""
+
+import hashlib
+import os
+import struct
+import zlib
+
+ACC_PUBLIC = 0x1
+ACC_PRIVATE = 0x2
+ACC_STATIC = 0x8
+ACC_FINAL = 0x10
+ACC_NATIVE = 0x100
+ACC_CONSTRUCTOR = 0x10000
+
+NATIVE_CALLBACK = ("nativeCallback", "V", ["J", "I", "Ljava/lang/Object;"])
+
+
+def native_callback(class_name):
+    return (class_name, *NATIVE_CALLBACK)
+
+
+def constructor(class_name, super_name):
+    # registers: v0 = this, v1-v2 = handle
+    return {
+        "name": "<init>",
+        "ret": "V",
+        "params": ["J"],
+        "access": ACC_CONSTRUCTOR,
+        "registers": 3,
+        "ins": 3,
+        "outs": 1,
+        "insns": [
+            # invoke-direct {v0}, super.<init>()V
+            0x1070, ("method", (super_name, "<init>", "V", [])), 0x0000,
+            # iput-wide v1, v0, handle
+            0x015A, ("field", (class_name, "handle", "J")),
+            # return-void
+            0x000E,
+        ],
+    }
+
+
+def forwarder(class_name, name, params, value, obj):
+    # registers: v0-v1 = handle, v2 = null or 0, v3 = this, v4... = params
+    return {
+        "name": name,
+        "ret": "V",
+        "params": params,
+        "access": ACC_PUBLIC,
+        "registers": 4 + len(params),
+        "ins": 1 + len(params),
+        "outs": 4,
+        "insns": [
+            # iget-wide v0, v3, handle
+            0x3053, ("field", (class_name, "handle", "J")),
+            # const/4 v2, 0
+            0x0212,
+            # invoke-static {v0, v1, value, object}, nativeCallback
+            0x4071, ("method", native_callback(class_name)),
+            0 | (1 << 4) | (value << 8) | (obj << 12),
+            # return-void
+            0x000E,
+        ],
+    }
+
+
+def notifier(class_name, name, value):
+    # registers: v0-v1 = handle, v2 = value, v3 = null, v4 = this
+    return {
+        "name": name,
+        "ret": "V",
+        "params": [],
+        "access": ACC_PUBLIC,
+        "registers": 5,
+        "ins": 1,
+        "outs": 4,
+        "insns": [
+            # iget-wide v0, v4, handle
+            0x4053, ("field", (class_name, "handle", "J")),
+            # const/4 v2, value
+            0x0212 | (value << 12),
+            # const/4 v3, 0
+            0x0312,
+            # invoke-static {v0, v1, v2, v3}, nativeCallback
+            0x4071, ("method", native_callback(class_name)),
+            0 | (1 << 4) | (2 << 8) | (3 << 12),
+            # return-void
+            0x000E,
+        ],
+    }
+
+
+def support_class(name, super_name, interfaces, *methods):
+    return {
+        "name": name,
+        "super": super_name,
+        "interfaces": interfaces,
+        "access": ACC_FINAL,
+        "fields": [("handle", "J", ACC_PRIVATE | ACC_FINAL)],
+        "methods": [
+            constructor(name, super_name),
+            *methods,
+            {
+                "name": NATIVE_CALLBACK[0],
+                "ret": NATIVE_CALLBACK[1],
+                "params": NATIVE_CALLBACK[2],
+                "access": ACC_PRIVATE | ACC_STATIC | ACC_NATIVE,
+            },
+        ],
+    }
+
+
+INTENT_RECEIVER = "Lrs/android_wakelock/IntentReceiver;"
+NATIVE_RUNNABLE = "Lrs/android_wakelock/NativeRunnable;"
+THERMAL_STATUS_LISTENER = "Lrs/android_wakelock/ThermalStatusListener;"
+KEYGUARD_DISMISS_CALLBACK = "Lrs/android_wakelock/KeyguardDismissCallback;"
+
+CLASSES = [
+    # onReceive(Context, Intent): nativeCallback(handle, 0, intent)
+    support_class(
+        INTENT_RECEIVER,
+        "Landroid/content/BroadcastReceiver;",
+        [],
+        forwarder(
+            INTENT_RECEIVER,
+            "onReceive",
+            ["Landroid/content/Context;", "Landroid/content/Intent;"],
+            2,
+            5,
+        ),
+    ),
+    # run(): nativeCallback(handle, 0, null)
+    support_class(
+        NATIVE_RUNNABLE,
+        "Ljava/lang/Object;",
+        ["Ljava/lang/Runnable;"],
+        forwarder(NATIVE_RUNNABLE, "run", [], 2, 2),
+    ),
+    # onThermalStatusChanged(int): nativeCallback(handle, status, null)
+    support_class(
+        THERMAL_STATUS_LISTENER,
+        "Ljava/lang/Object;",
+        ["Landroid/os/PowerManager$OnThermalStatusChangedListener;"],
+        forwarder(THERMAL_STATUS_LISTENER, "onThermalStatusChanged", ["I"], 4, 2),
+    ),
+    # onDismissSucceeded(), onDismissCancelled(), onDismissError():
+    # nativeCallback(handle, 0, 1, or 2, null)
+    support_class(
+        KEYGUARD_DISMISS_CALLBACK,
+        "Landroid/app/KeyguardManager$KeyguardDismissCallback;",
+        [],
+        notifier(KEYGUARD_DISMISS_CALLBACK, "onDismissSucceeded", 0),
+        notifier(KEYGUARD_DISMISS_CALLBACK, "onDismissCancelled", 1),
+        notifier(KEYGUARD_DISMISS_CALLBACK, "onDismissError", 2),
+    ),
+]
+
+
+def shorty(ret, params):
+    return "".join("L" if t[0] in "L[" else t for t in [ret] + params)
+
+
+def uleb128(value):
+    out = bytearray()
+    while True:
+        byte = value & 0x7F
+        value >>= 7
+        if value:
+            out.append(byte | 0x80)
+        else:
+            out.append(byte)
+            return bytes(out)
+
+
+def align(buf, n=4):
+    while len(buf) % n:
+        buf.append(0)
+
+
+def assemble(classes):
+    # Collect every referenced item.
+    fields = set()
+    methods = set()
+
+    for cls in classes:
+        for name, type_, _ in cls["fields"]:
+            fields.add((cls["name"], name, type_))
+        for method in cls["methods"]:
+            methods.add((cls["name"], method["name"], method["ret"], tuple(method["params"])))
+            for insn in method.get("insns", []):
+                if isinstance(insn, tuple):
+                    kind, ref = insn
+                    if kind == "field":
+                        fields.add(ref)
+                    else:
+                        methods.add((ref[0], ref[1], ref[2], tuple(ref[3])))
+
+    protos = {(m[2], m[3]) for m in methods}
+    types = set()
+    for cls in classes:
+        types.update([cls["name"], cls["super"], *cls["interfaces"]])
+    for class_name, _, type_ in fields:
+        types.update([class_name, type_])
+    for class_name, _, ret, params in methods:
+        types.update([class_name, ret, *params])
+
+    strings = set(types)
+    strings.update(name for _, name, _ in fields)
+    strings.update(name for _, name, _, _ in methods)
+    strings.update(shorty(ret, list(params)) for ret, params in protos)
+
+    # Sort everything in the order required by the format. All strings are
+    # ASCII, so sorting by code point matches the required UTF-16 order.
+    strings = sorted(strings)
+    string_idx = {s: i for i, s in enumerate(strings)}
+    types = sorted(types, key=lambda t: string_idx[t])
+    type_idx = {t: i for i, t in enumerate(types)}
+    protos = sorted(protos, key=lambda p: (type_idx[p[0]], [type_idx[t] for t in p[1]]))
+    proto_idx = {p: i for i, p in enumerate(protos)}
+    fields = sorted(fields, key=lambda f: (type_idx[f[0]], string_idx[f[1]], type_idx[f[2]]))
+    field_idx = {f: i for i, f in enumerate(fields)}
+    methods = sorted(
+        methods, key=lambda m: (type_idx[m[0]], string_idx[m[1]], proto_idx[(m[2], m[3])])
+    )
+    method_idx = {m: i for i, m in enumerate(methods)}
+
+    header_size = 0x70
+    string_ids_off = header_size
+    type_ids_off = string_ids_off + 4 * len(strings)
+    proto_ids_off = type_ids_off + 4 * len(types)
+    field_ids_off = proto_ids_off + 12 * len(protos)
+    method_ids_off = field_ids_off + 8 * len(fields)
+    class_defs_off = method_ids_off + 8 * len(methods)
+    data_off = class_defs_off + 32 * len(classes)
+
+    data = bytearray()
+
+    def offset():
+        return data_off + len(data)
+
+    # Code items.
+    code_offs = {}
+    code_items_off = offset()
+    code_count = 0
+    for cls in classes:
+        for method in cls["methods"]:
+            if "insns" not in method:
+                continue
+            insns = []
+            for insn in method["insns"]:
+                if isinstance(insn, tuple):
+                    kind, ref = insn
+                    if kind == "field":
+                        insns.append(field_idx[ref])
+                    else:
+                        insns.append(method_idx[(ref[0], ref[1], ref[2], tuple(ref[3]))])
+                else:
+                    insns.append(insn)
+            align(data)
+            code_offs[(cls["name"], method["name"])] = offset()
+            data += struct.pack(
+                "<HHHHII",
+                method["registers"],
+                method["ins"],
+                method["outs"],
+                0,
+                0,
+                len(insns),
+            )
+            data += struct.pack("<%dH" % len(insns), *insns)
+            code_count += 1
+
+    # Type lists, for proto parameters and interfaces.
+    align(data)
+    type_lists_off = offset()
+    type_list_offs = {}
+    lists = [p[1] for p in protos if p[1]] + [tuple(c["interfaces"]) for c in classes if c["interfaces"]]
+    for type_list in lists:
+        if type_list in type_list_offs:
+            continue
+        align(data)
+        type_list_offs[type_list] = offset()
+        data += struct.pack("<I", len(type_list))
+        data += struct.pack("<%dH" % len(type_list), *[type_idx[t] for t in type_list])
+
+    # String data.
+    align(data)
+    string_data_off = offset()
+    string_offs = []
+    for s in strings:
+        string_offs.append(offset())
+        data += uleb128(len(s)) + s.encode("ascii") + b"\0"
+
+    # Class data.
+    class_data_off = offset()
+    class_data_offs = []
+    for cls in classes:
+        class_data_offs.append(offset())
+        direct = []
+        virtual = []
+        for method in cls["methods"]:
+            entry = (
+                method_idx[(cls["name"], method["name"], method["ret"], tuple(method["params"]))],
+                method["access"],
+                code_offs.get((cls["name"], method["name"]), 0),
+            )
+            is_direct = method["access"] & (ACC_STATIC | ACC_PRIVATE | ACC_CONSTRUCTOR)
+            (direct if is_direct else virtual).append(entry)
+        instance_fields = sorted(
+            (field_idx[(cls["name"], name, type_)], access) for name, type_, access in cls["fields"]
+        )
+        data += uleb128(0) + uleb128(len(instance_fields)) + uleb128(len(direct)) + uleb128(len(virtual))
+        previous = 0
+        for idx, access in instance_fields:
+            data += uleb128(idx - previous) + uleb128(access)
+            previous = idx
+        for entries in (sorted(direct), sorted(virtual)):
+            previous = 0
+            for idx, access, code_off in entries:
+                data += uleb128(idx - previous) + uleb128(access) + uleb128(code_off)
+                previous = idx
+
+    # Map list.
+    align(data)
+    map_off = offset()
+    sections = [
+        (0x0000, 1, 0),
+        (0x0001, len(strings), string_ids_off),
+        (0x0002, len(types), type_ids_off),
+        (0x0003, len(protos), proto_ids_off),
+        (0x0004, len(fields), field_ids_off),
+        (0x0005, len(methods), method_ids_off),
+        (0x0006, len(classes), class_defs_off),
+        (0x2001, code_count, code_items_off),
+        (0x1001, len(type_list_offs), type_lists_off),
+        (0x2002, len(strings), string_data_off),
+        (0x2000, len(classes), class_data_off),
+        (0x1000, 1, map_off),
+    ]
+    sections = [s for s in sections if s[1]]
+    data += struct.pack("<I", len(sections))
+    for type_, size, off in sections:
+        data += struct.pack("<HHII", type_, 0, size, off)
+
+    ids = bytearray()
+    for off in string_offs:
+        ids += struct.pack("<I", off)
+    for t in types:
+        ids += struct.pack("<I", string_idx[t])
+    for ret, params in protos:
+        ids += struct.pack(
+            "<III",
+            string_idx[shorty(ret, list(params))],
+            type_idx[ret],
+            type_list_offs.get(params, 0) if params else 0,
+        )
+    for class_name, name, type_ in fields:
+        ids += struct.pack("<HHI", type_idx[class_name], type_idx[type_], string_idx[name])
+    for class_name, name, ret, params in methods:
+        ids += struct.pack(
+            "<HHI", type_idx[class_name], proto_idx[(ret, params)], string_idx[name]
+        )
+    NO_INDEX = 0xFFFFFFFF
+    for cls, class_data in zip(classes, class_data_offs):
+        interfaces = tuple(cls["interfaces"])
+        ids += struct.pack(
+            "<IIIIIIII",
+            type_idx[cls["name"]],
+            cls["access"],
+            type_idx[cls["super"]],
+            type_list_offs[interfaces] if interfaces else 0,
+            NO_INDEX,
+            0,
+            class_data,
+            0,
+        )
+    assert header_size + len(ids) == data_off
+
+    file_size = data_off + len(data)
+    header = bytearray(b"dex\n035\0")
+    header += bytes(24)  # checksum and signature, filled in below
+    header += struct.pack(
+        "<IIIIII",
+        file_size,
+        header_size,
+        0x12345678,
+        0,
+        0,
+        map_off,
+    )
+    for size, off in [
+        (len(strings), string_ids_off),
+        (len(types), type_ids_off),
+        (len(protos), proto_ids_off),
+        (len(fields), field_ids_off),
+        (len(methods), method_ids_off),
+        (len(classes), class_defs_off),
+        (len(data), data_off),
+    ]:
+        header += struct.pack("<II", size, off)
+    assert len(header) == header_size
+
+    dex = header + ids + data
+    dex[12:32] = hashlib.sha1(dex[32:]).digest()
+    dex[8:12] = struct.pack("<I", zlib.adler32(bytes(dex[12:])))
+
+    return bytes(dex)
+
+
+if __name__ == "__main__":
+    path = os.path.join(os.path.dirname(os.path.abspath(__file__)), "support.dex")
+
+    with open(path, "wb") as file:
+        file.write(assemble(CLASSES))
@@ -0,0 +1,23 @@
+package rs.android_wakelock;
+
+import android.content.BroadcastReceiver;
+import android.content.Context;
+import android.content.Intent;
+
+/**
+ * Forwards received broadcast intents to Rust.
+ */
+final class IntentReceiver extends BroadcastReceiver {
+    private final long handle;
+
+    IntentReceiver(long handle) {
+        this.handle = handle;
+    }
+
+    @Override
+    public void onReceive(Context context, Intent intent) {
+        nativeCallback(handle, 0, intent);
+    }
+
+    private static native void nativeCallback(long handle, int value, Object object);
+}
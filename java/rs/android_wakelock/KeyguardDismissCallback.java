@@ -0,0 +1,31 @@
+package rs.android_wakelock;
+
+import android.app.KeyguardManager;
+
+/**
+ * Forwards the outcome of a keyguard dismissal request to Rust.
+ */
+final class KeyguardDismissCallback extends KeyguardManager.KeyguardDismissCallback {
+    private final long handle;
+
+    KeyguardDismissCallback(long handle) {
+        this.handle = handle;
+    }
+
+    @Override
+    public void onDismissSucceeded() {
+        nativeCallback(handle, 0, null);
+    }
+
+    @Override
+    public void onDismissCancelled() {
+        nativeCallback(handle, 1, null);
+    }
+
+    @Override
+    public void onDismissError() {
+        nativeCallback(handle, 2, null);
+    }
+
+    private static native void nativeCallback(long handle, int value, Object object);
+}
@@ -0,0 +1,19 @@
+package rs.android_wakelock;
+
+/**
+ * Runs a Rust closure, for posting to a {@code Handler}.
+ */
+final class NativeRunnable implements Runnable {
+    private final long handle;
+
+    NativeRunnable(long handle) {
+        this.handle = handle;
+    }
+
+    @Override
+    public void run() {
+        nativeCallback(handle, 0, null);
+    }
+
+    private static native void nativeCallback(long handle, int value, Object object);
+}
@@ -0,0 +1,21 @@
+package rs.android_wakelock;
+
+import android.os.PowerManager;
+
+/**
+ * Forwards thermal status changes to Rust.
+ */
+final class ThermalStatusListener implements PowerManager.OnThermalStatusChangedListener {
+    private final long handle;
+
+    ThermalStatusListener(long handle) {
+        this.handle = handle;
+    }
+
+    @Override
+    public void onThermalStatusChanged(int status) {
+        nativeCallback(handle, status, null);
+    }
+
+    private static native void nativeCallback(long handle, int value, Object object);
+}
@@ -0,0 +1,227 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::{Duration, Instant},
+};
+
+use crate::{hook, timer, worker, Guard, Level, Result, WakeLock};
+
+/// A wake lock that keeps the screen on while the app is active, and
+/// downgrades to only keeping the CPU awake after a period of inactivity.
+///
+/// This is useful for apps such as media players or navigation apps that want
+/// the screen to stay on while the user is interacting with them, but still
+/// need to keep working in the background once the user stops. The app signals
+/// activity with [`AdaptiveGuard::activity`]; once no activity has been
+/// signaled for the configured period, the screen wake lock is released and
+/// the partial wake lock is acquired in its place. Signaling activity again
+/// switches back to the screen wake lock.
+///
+/// When switching between the two, the new wake lock is always acquired before
+/// the old one is released, so the device is kept awake throughout. Switching
+/// after inactivity happens on the crate's background thread, and errors doing
+/// so are passed to the hook set with [`set_error_hook`][crate::set_error_hook].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use android_wakelock::{AdaptiveWakeLock, Level, WakeLock};
+///
+/// #[allow(deprecated)]
+/// let screen = WakeLock::builder("myapp:playback-screen")
+///     .level(Level::ScreenDim)
+///     .build()?;
+/// let partial = android_wakelock::partial("myapp:playback")?;
+///
+/// let wake_lock = AdaptiveWakeLock::new(screen, partial, Duration::from_secs(30));
+/// let guard = wake_lock.acquire()?;
+///
+/// // Whenever the user interacts with the app...
+/// guard.activity()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct AdaptiveWakeLock {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    screen: WakeLock,
+    partial: WakeLock,
+    inactivity: Duration,
+}
+
+impl AdaptiveWakeLock {
+    /// Create a new adaptive wake lock that holds `screen` while the app is
+    /// active, and `partial` once no activity has been signaled for the given
+    /// inactivity period.
+    pub fn new(screen: WakeLock, partial: WakeLock, inactivity: Duration) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                screen,
+                partial,
+                inactivity,
+            }),
+        }
+    }
+
+    /// Acquire the screen wake lock, starting the inactivity period.
+    ///
+    /// Returns an [`AdaptiveGuard`] which releases whichever wake lock is
+    /// currently held when dropped.
+    #[track_caller]
+    pub fn acquire(&self) -> Result<AdaptiveGuard> {
+        let guard = self.shared.screen.acquire()?;
+        let state = Arc::new(Mutex::new(State {
+            guard: Some(guard),
+            downgraded: false,
+            last_activity: Instant::now(),
+            timer_pending: true,
+        }));
+
+        schedule_inactivity_check(
+            Arc::downgrade(&state),
+            self.shared.clone(),
+            Instant::now() + self.shared.inactivity,
+        );
+
+        Ok(AdaptiveGuard {
+            shared: self.shared.clone(),
+            state,
+        })
+    }
+}
+
+/// A guard for an acquired [`AdaptiveWakeLock`].
+///
+/// Whichever wake lock is currently held is released when the guard is
+/// dropped.
+pub struct AdaptiveGuard {
+    shared: Arc<Shared>,
+    state: Arc<Mutex<State>>,
+}
+
+/// State shared with the inactivity timer.
+struct State {
+    /// The guard of the wake lock currently held.
+    guard: Option<Guard>,
+
+    /// Whether the partial wake lock is held instead of the screen one.
+    downgraded: bool,
+
+    last_activity: Instant,
+    timer_pending: bool,
+}
+
+impl AdaptiveGuard {
+    /// Signal that the app is active, restarting the inactivity period.
+    ///
+    /// If the wake lock was already downgraded because of inactivity, the
+    /// screen wake lock is acquired again before the partial one is released.
+    pub fn activity(&self) -> Result<()> {
+        let mut state = lock(&self.state);
+
+        if state.downgraded {
+            let guard = self.shared.screen.acquire()?;
+
+            state.guard = Some(guard);
+            state.downgraded = false;
+
+            debug!(
+                "upgraded adaptive wake lock \"{}\" after activity",
+                self.shared.screen.tag()
+            );
+        }
+
+        state.last_activity = Instant::now();
+
+        if !state.timer_pending {
+            state.timer_pending = true;
+            schedule_inactivity_check(
+                Arc::downgrade(&self.state),
+                self.shared.clone(),
+                state.last_activity + self.shared.inactivity,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Get the level of the wake lock currently held.
+    pub fn level(&self) -> Level {
+        if lock(&self.state).downgraded {
+            self.shared.partial.level()
+        } else {
+            self.shared.screen.level()
+        }
+    }
+
+    /// Returns true if the wake lock has been downgraded to the partial wake
+    /// lock because of inactivity.
+    pub fn is_downgraded(&self) -> bool {
+        lock(&self.state).downgraded
+    }
+}
+
+impl fmt::Debug for AdaptiveGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = lock(&self.state);
+
+        f.debug_struct("AdaptiveGuard")
+            .field("guard", &state.guard)
+            .field("downgraded", &state.downgraded)
+            .finish()
+    }
+}
+
+impl Drop for AdaptiveGuard {
+    fn drop(&mut self) {
+        let guard = lock(&self.state).guard.take();
+
+        drop(guard);
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Downgrade to the partial wake lock if no activity has been signaled for the
+/// inactivity period, otherwise check again later.
+fn schedule_inactivity_check(state: Weak<Mutex<State>>, shared: Arc<Shared>, deadline: Instant) {
+    timer::schedule(deadline, move || {
+        // Perform the switch on the worker thread, since it involves JNI calls.
+        worker::execute(move || {
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+
+            let mut locked = lock(&state);
+            let deadline = locked.last_activity + shared.inactivity;
+
+            if locked.downgraded || locked.guard.is_none() {
+                locked.timer_pending = false;
+            } else if deadline <= Instant::now() {
+                locked.timer_pending = false;
+
+                match shared.partial.acquire() {
+                    Ok(guard) => {
+                        locked.guard = Some(guard);
+                        locked.downgraded = true;
+
+                        debug!(
+                            "downgraded adaptive wake lock \"{}\" after inactivity",
+                            shared.screen.tag()
+                        );
+                    }
+                    Err(e) => hook::report_error(shared.partial.tag(), &e),
+                }
+            } else {
+                drop(locked);
+                schedule_inactivity_check(Arc::downgrade(&state), shared, deadline);
+            }
+        });
+    });
+}
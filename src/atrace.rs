@@ -0,0 +1,180 @@
+//! Marking wake lock holds in system traces with ATrace.
+//!
+//! The ATrace functions are looked up at runtime, since the async variants
+//! needed for holds that span threads and counters were only added in API
+//! level 29.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    ffi::{c_char, c_void, CStr, CString},
+    mem,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+type IsEnabled = unsafe extern "C" fn() -> bool;
+type AsyncSection = unsafe extern "C" fn(*const c_char, i32);
+type SetCounter = unsafe extern "C" fn(*const c_char, i64);
+
+/// Cookies distinguishing concurrent sections with the same name.
+static NEXT_COOKIE: AtomicI32 = AtomicI32::new(1);
+
+/// Number of guards currently holding wake locks, by tag, along with the name
+/// of the counter they are published as.
+static GUARDS: Mutex<Option<HashMap<String, (CString, i64)>>> = Mutex::new(None);
+
+struct Api {
+    is_enabled: IsEnabled,
+    begin_async_section: AsyncSection,
+    end_async_section: AsyncSection,
+    set_counter: SetCounter,
+}
+
+/// Get the ATrace functions, or `None` if they are not available.
+fn api() -> Option<&'static Api> {
+    static API: OnceLock<Option<Api>> = OnceLock::new();
+
+    API.get_or_init(|| unsafe { load() }).as_ref()
+}
+
+unsafe fn load() -> Option<Api> {
+    let lib = libc::dlopen(c"libandroid.so".as_ptr(), libc::RTLD_NOW);
+
+    if lib.is_null() {
+        return None;
+    }
+
+    match (
+        symbol(lib, c"ATrace_isEnabled"),
+        symbol(lib, c"ATrace_beginAsyncSection"),
+        symbol(lib, c"ATrace_endAsyncSection"),
+        symbol(lib, c"ATrace_setCounter"),
+    ) {
+        (
+            Some(is_enabled),
+            Some(begin_async_section),
+            Some(end_async_section),
+            Some(set_counter),
+        ) => Some(Api {
+            is_enabled: mem::transmute::<*mut c_void, IsEnabled>(is_enabled),
+            begin_async_section: mem::transmute::<*mut c_void, AsyncSection>(begin_async_section),
+            end_async_section: mem::transmute::<*mut c_void, AsyncSection>(end_async_section),
+            set_counter: mem::transmute::<*mut c_void, SetCounter>(set_counter),
+        }),
+        _ => {
+            debug!("ATrace async sections and counters are not available");
+            None
+        }
+    }
+}
+
+unsafe fn symbol(lib: *mut c_void, name: &CStr) -> Option<*mut c_void> {
+    let symbol = libc::dlsym(lib, name.as_ptr());
+
+    (!symbol.is_null()).then_some(symbol)
+}
+
+/// Marks the time a guard holds a wake lock in system traces, until dropped.
+///
+/// While tracing is enabled, the hold is shown as an async section, and the
+/// number of guards holding wake locks with the same tag is published as a
+/// counter.
+#[derive(Debug)]
+pub(crate) struct Hold {
+    tag: String,
+    section: Option<Section>,
+}
+
+impl Hold {
+    /// Begin marking a hold of the wake lock with the given tag, or return
+    /// `None` if ATrace is not available.
+    pub(crate) fn begin(tag: &str) -> Option<Self> {
+        if cfg!(feature = "no-instrumentation") {
+            return None;
+        }
+
+        let api = api()?;
+
+        // Counted even while tracing is disabled, so that the counter is
+        // correct once it is enabled.
+        update_guards(api, tag, 1);
+
+        Some(Self {
+            tag: tag.to_owned(),
+            section: Section::begin(api, tag),
+        })
+    }
+}
+
+impl Drop for Hold {
+    fn drop(&mut self) {
+        // End the section before the count drops, so that both line up.
+        self.section.take();
+
+        if let Some(api) = api() {
+            update_guards(api, &self.tag, -1);
+        }
+    }
+}
+
+/// Add to the number of guards holding wake locks with the given tag, and
+/// publish the new count if tracing is enabled.
+fn update_guards(api: &Api, tag: &str, delta: i64) {
+    let mut guards = GUARDS.lock().unwrap_or_else(|e| e.into_inner());
+    let guards = guards.get_or_insert_with(HashMap::new);
+
+    let (name, count) = match guards.entry(tag.to_owned()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => match CString::new(format!("WakeLock guards:{}", tag)) {
+            Ok(name) => entry.insert((name, 0)),
+            Err(_) => return,
+        },
+    };
+
+    *count += delta;
+
+    if unsafe { (api.is_enabled)() } {
+        unsafe {
+            (api.set_counter)(name.as_ptr(), *count);
+        }
+    }
+}
+
+/// An async trace section covering the time a guard holds a wake lock, which
+/// ends when dropped.
+#[derive(Debug)]
+struct Section {
+    name: CString,
+    cookie: i32,
+}
+
+impl Section {
+    /// Begin a section for the wake lock with the given tag, unless tracing is
+    /// not currently enabled.
+    fn begin(api: &Api, tag: &str) -> Option<Self> {
+        if !unsafe { (api.is_enabled)() } {
+            return None;
+        }
+
+        let name = CString::new(format!("WakeLock:{}", tag)).ok()?;
+        let cookie = NEXT_COOKIE.fetch_add(1, Ordering::Relaxed);
+
+        unsafe {
+            (api.begin_async_section)(name.as_ptr(), cookie);
+        }
+
+        Some(Self { name, cookie })
+    }
+}
+
+impl Drop for Section {
+    fn drop(&mut self) {
+        if let Some(api) = api() {
+            unsafe {
+                (api.end_async_section)(self.name.as_ptr(), self.cookie);
+            }
+        }
+    }
+}
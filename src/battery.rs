@@ -0,0 +1,226 @@
+//! Queries about the device's battery.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use android_wakelock::battery;
+//!
+//! if battery::level()? < 15 && !battery::is_charging()? {
+//!     // Skip optional work to save the remaining battery...
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use jni::{
+    objects::{JObject, JValue},
+    JNIEnv,
+};
+
+use crate::{
+    catch_exceptions, consts::ACTION_BATTERY_CHANGED, java_vm, system_service, Error, ErrorKind,
+    Result, LOCAL_FRAME_CAPACITY,
+};
+
+/// `BatteryManager.BATTERY_PROPERTY_CAPACITY`
+const BATTERY_PROPERTY_CAPACITY: i32 = 4;
+
+/// The charging status of the battery.
+///
+/// New statuses may be added in future Android versions, so code matching on
+/// this should include a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ChargingStatus {
+    /// The status is unknown.
+    Unknown,
+
+    /// The battery is charging.
+    Charging,
+
+    /// The battery is discharging.
+    Discharging,
+
+    /// The device is plugged in, but the battery is not charging.
+    NotCharging,
+
+    /// The battery is full.
+    Full,
+}
+
+impl ChargingStatus {
+    fn from_raw(status: i32) -> Self {
+        match status {
+            2 => Self::Charging,
+            3 => Self::Discharging,
+            4 => Self::NotCharging,
+            5 => Self::Full,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// The type of power source the device is plugged into.
+///
+/// New types may be added in future Android versions, so code matching on this
+/// should include a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PlugType {
+    /// An AC charger.
+    Ac,
+
+    /// A USB port.
+    Usb,
+
+    /// A wireless charger.
+    Wireless,
+
+    /// A dock.
+    Dock,
+
+    /// A power source not known to this crate.
+    Unknown,
+}
+
+impl PlugType {
+    fn from_raw(plugged: i32) -> Option<Self> {
+        match plugged {
+            0 => None,
+            1 => Some(Self::Ac),
+            2 => Some(Self::Usb),
+            4 => Some(Self::Wireless),
+            8 => Some(Self::Dock),
+            _ => Some(Self::Unknown),
+        }
+    }
+}
+
+/// Get the remaining battery capacity as a percentage from 0 to 100.
+///
+/// This uses `BatteryManager.BATTERY_PROPERTY_CAPACITY` where available, and
+/// the most recent `ACTION_BATTERY_CHANGED` broadcast otherwise.
+pub fn level() -> Result<u8> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    // Local references are freed right away, in case the thread stays
+    // attached, such as when polled from the crate's background thread.
+    env.with_local_frame(LOCAL_FRAME_CAPACITY, level_with)
+}
+
+fn level_with(env: &mut JNIEnv<'_>) -> Result<u8> {
+    let battery_manager = system_service(env, "batterymanager")?;
+
+    let capacity = catch_exceptions(env, |env| {
+        env.call_method(
+            &battery_manager,
+            "getIntProperty",
+            "(I)I",
+            &[JValue::from(BATTERY_PROPERTY_CAPACITY)],
+        )?
+        .i()
+    });
+
+    match capacity {
+        // Integer.MIN_VALUE is returned if the property is not supported.
+        Ok(capacity) if (0..=100).contains(&capacity) => return Ok(capacity as u8),
+        Ok(_) => {}
+        Err(e) if e.is_method_not_found() => {}
+        Err(e) => return Err(e),
+    }
+
+    let intent = battery_changed_intent(env)?;
+    let level = int_extra(env, &intent, "level", -1)?;
+    let scale = int_extra(env, &intent, "scale", -1)?;
+
+    if level < 0 || scale <= 0 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "battery level is not available on this device",
+        ));
+    }
+
+    Ok((level * 100 / scale).clamp(0, 100) as u8)
+}
+
+/// Get the charging status of the battery.
+pub fn status() -> Result<ChargingStatus> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    env.with_local_frame(LOCAL_FRAME_CAPACITY, |env| {
+        let intent = battery_changed_intent(env)?;
+
+        Ok(ChargingStatus::from_raw(int_extra(
+            env, &intent, "status", 1,
+        )?))
+    })
+}
+
+/// Returns true if the battery is currently charging or full while plugged in.
+pub fn is_charging() -> Result<bool> {
+    Ok(matches!(
+        status()?,
+        ChargingStatus::Charging | ChargingStatus::Full
+    ))
+}
+
+/// Get the type of power source the device is plugged into, or `None` if it is
+/// running on battery.
+pub fn plug_type() -> Result<Option<PlugType>> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    env.with_local_frame(LOCAL_FRAME_CAPACITY, |env| {
+        let intent = battery_changed_intent(env)?;
+
+        Ok(PlugType::from_raw(int_extra(env, &intent, "plugged", 0)?))
+    })
+}
+
+/// Get the most recent sticky `ACTION_BATTERY_CHANGED` broadcast.
+fn battery_changed_intent<'local>(env: &mut JNIEnv<'local>) -> Result<JObject<'local>> {
+    let ctx = ndk_context::android_context();
+    let action = env.new_string(ACTION_BATTERY_CHANGED)?;
+
+    let intent = catch_exceptions(env, |env| {
+        let filter = env.new_object(
+            "android/content/IntentFilter",
+            "(Ljava/lang/String;)V",
+            &[JValue::from(&action)],
+        )?;
+
+        // Registering a null receiver returns the current sticky broadcast
+        // without actually registering anything.
+        env.call_method(
+            unsafe { JObject::from_raw(ctx.context().cast()) },
+            "registerReceiver",
+            "(Landroid/content/BroadcastReceiver;Landroid/content/IntentFilter;)Landroid/content/Intent;",
+            &[JValue::from(&JObject::null()), JValue::from(&filter)],
+        )?
+        .l()
+    })?;
+
+    if intent.is_null() {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "battery information is not available on this device",
+        ));
+    }
+
+    Ok(intent)
+}
+
+fn int_extra(env: &mut JNIEnv<'_>, intent: &JObject<'_>, name: &str, default: i32) -> Result<i32> {
+    let name = env.new_string(name)?;
+
+    catch_exceptions(env, |env| {
+        env.call_method(
+            intent,
+            "getIntExtra",
+            "(Ljava/lang/String;I)I",
+            &[JValue::from(&name), JValue::from(default)],
+        )?
+        .i()
+    })
+}
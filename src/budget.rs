@@ -0,0 +1,145 @@
+//! Limits on the number of guards that may be held at once for a wake lock.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+/// A counting semaphore limiting how many guards may be outstanding at once.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    max: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    used: usize,
+
+    /// The waker of each pending [`Acquire`], by its ID.
+    waiters: Vec<(u64, Waker)>,
+
+    /// ID assigned to the next pending [`Acquire`].
+    next_waiter: u64,
+}
+
+impl Budget {
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            max,
+            state: Mutex::new(State {
+                used: 0,
+                waiters: Vec::new(),
+                next_waiter: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Take a permit if any are available.
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        let mut state = self.lock();
+
+        if state.used < self.max {
+            state.used += 1;
+            Some(Permit(self.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Wait until a permit is available and take it.
+    pub(crate) fn acquire(self: &Arc<Self>) -> Acquire {
+        Acquire {
+            budget: self.clone(),
+            waiter: None,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A share of a [`Budget`], returned to the budget when dropped.
+#[derive(Debug)]
+pub(crate) struct Permit(Arc<Budget>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+
+        state.used -= 1;
+
+        // Waiters remove their own entry once they get a permit or are
+        // dropped, so waking one that then loses the race is harmless.
+        for (_, waker) in &state.waiters {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+/// Future returned by [`Budget::acquire`].
+#[derive(Debug)]
+pub(crate) struct Acquire {
+    budget: Arc<Budget>,
+
+    /// ID of the waker registered in the budget, once pending.
+    waiter: Option<u64>,
+}
+
+impl Future for Acquire {
+    type Output = Permit;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit> {
+        let this = self.get_mut();
+        let mut state = this.budget.lock();
+
+        if state.used < this.budget.max {
+            state.used += 1;
+
+            if let Some(id) = this.waiter.take() {
+                state.waiters.retain(|(waiter, _)| *waiter != id);
+            }
+
+            return Poll::Ready(Permit(this.budget.clone()));
+        }
+
+        // Keep a single waker per future, however often it is polled.
+        match this.waiter {
+            Some(id) => {
+                if let Some((_, waker)) = state.waiters.iter_mut().find(|(waiter, _)| *waiter == id)
+                {
+                    if !waker.will_wake(cx.waker()) {
+                        *waker = cx.waker().clone();
+                    }
+                }
+            }
+            None => {
+                let id = state.next_waiter;
+
+                state.next_waiter += 1;
+                state.waiters.push((id, cx.waker().clone()));
+                this.waiter = Some(id);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        if let Some(id) = self.waiter {
+            self.budget
+                .lock()
+                .waiters
+                .retain(|(waiter, _)| *waiter != id);
+        }
+    }
+}
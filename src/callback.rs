@@ -0,0 +1,329 @@
+//! Dispatching callbacks from Java back into Rust.
+//!
+//! Platform listener interfaces and receivers can only be implemented in Java.
+//! The crate ships small Java support classes in its `java` directory that
+//! implement them, and which forward every callback to a single native method:
+//!
+//! ```java
+//! private static native void nativeCallback(long handle, int value, Object object);
+//! ```
+//!
+//! The `handle` identifies a Rust closure registered with [`register`], which
+//! is called with the remaining arguments.
+//!
+//! Apps written purely in Rust have nowhere to put Java sources, so the classes
+//! are also embedded in the crate as a precompiled DEX file, assembled by
+//! `java/gen_dex.py`. If the app does not include a class itself, it is loaded
+//! from the embedded DEX with `InMemoryDexClassLoader`, which requires API
+//! level 26 or higher.
+
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use jni::{
+    objects::{GlobalRef, JClass, JObject, JValue},
+    sys::{jint, jlong},
+    JNIEnv, JavaVM, NativeMethod,
+};
+
+use crate::{catch_exceptions, hook, sys, Error, ErrorKind, Result};
+
+const RECEIVER_CLASS: &str = "rs/android_wakelock/IntentReceiver";
+
+/// The Java support classes, compiled to DEX.
+static SUPPORT_DEX: &[u8] = include_bytes!("../java/support.dex");
+
+/// Class loader for the embedded support classes, once created.
+static DEX_CLASS_LOADER: Mutex<Option<GlobalRef>> = Mutex::new(None);
+
+/// A callback invoked from Java with an integer value and an optional object.
+pub(crate) type Callback = Arc<dyn Fn(&mut JNIEnv<'_>, i32, &JObject<'_>) + Send + Sync>;
+
+static CALLBACKS: Mutex<Option<HashMap<i64, Callback>>> = Mutex::new(None);
+static NEXT_HANDLE: AtomicI64 = AtomicI64::new(1);
+
+fn callbacks() -> MutexGuard<'static, Option<HashMap<i64, Callback>>> {
+    CALLBACKS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Register a callback, returning the handle to pass to the Java support
+/// class.
+pub(crate) fn register<F>(f: F) -> i64
+where
+    F: Fn(&mut JNIEnv<'_>, i32, &JObject<'_>) + Send + Sync + 'static,
+{
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+
+    callbacks()
+        .get_or_insert_with(HashMap::new)
+        .insert(handle, Arc::new(f));
+
+    handle
+}
+
+/// Unregister a callback. Callbacks from Java arriving afterwards are ignored.
+pub(crate) fn unregister(handle: i64) {
+    if let Some(callbacks) = callbacks().as_mut() {
+        callbacks.remove(&handle);
+    }
+}
+
+/// Load one of the crate's Java support classes with the app's class loader,
+/// or from the embedded DEX if the app does not include it, and bind its
+/// native callback method.
+///
+/// Classes must be loaded with the app's class loader, since `FindClass` only
+/// sees system classes when called from a thread created in native code.
+pub(crate) fn load_class<'local>(env: &mut JNIEnv<'local>, name: &str) -> Result<JClass<'local>> {
+    let ctx = ndk_context::android_context();
+    let binary_name = env.new_string(name.replace('/', "."))?;
+
+    let class = catch_exceptions(env, |env| {
+        let class_loader = env
+            .call_method(
+                unsafe { JObject::from_raw(ctx.context().cast()) },
+                "getClassLoader",
+                "()Ljava/lang/ClassLoader;",
+                &[],
+            )?
+            .l()?;
+
+        env.call_method(
+            &class_loader,
+            "loadClass",
+            "(Ljava/lang/String;)Ljava/lang/Class;",
+            &[JValue::from(&binary_name)],
+        )?
+        .l()
+    });
+
+    let class = match class {
+        Ok(class) => class,
+        Err(e) if e.exception_class() == Some("java.lang.ClassNotFoundException") => {
+            debug!("{} not included in the app, loading embedded class", name);
+
+            load_embedded_class(env, name, &binary_name)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    let class = JClass::from(class);
+
+    catch_exceptions(env, |env| {
+        env.register_native_methods(
+            &class,
+            &[NativeMethod {
+                name: "nativeCallback".into(),
+                sig: "(JILjava/lang/Object;)V".into(),
+                fn_ptr: native_callback as *mut c_void,
+            }],
+        )
+    })?;
+
+    Ok(class)
+}
+
+/// Load one of the support classes from the embedded DEX.
+fn load_embedded_class<'local>(
+    env: &mut JNIEnv<'local>,
+    name: &str,
+    binary_name: &JObject<'_>,
+) -> Result<JObject<'local>> {
+    if sys::api_level_with(env)? < 26 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "Java support class {} not found, and loading it from memory requires API level 26; include the Java sources from the android-wakelock crate in the app",
+                name
+            ),
+        ));
+    }
+
+    let class_loader = dex_class_loader(env)?;
+
+    catch_exceptions(env, |env| {
+        env.call_method(
+            &class_loader,
+            "loadClass",
+            "(Ljava/lang/String;)Ljava/lang/Class;",
+            &[JValue::from(binary_name)],
+        )?
+        .l()
+    })
+}
+
+/// Get the class loader for the embedded DEX, creating it the first time.
+fn dex_class_loader(env: &mut JNIEnv<'_>) -> Result<GlobalRef> {
+    let mut class_loader = DEX_CLASS_LOADER.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some(class_loader) = class_loader.as_ref() {
+        return Ok(class_loader.clone());
+    }
+
+    let ctx = ndk_context::android_context();
+    let dex = env.byte_array_from_slice(SUPPORT_DEX)?;
+
+    let loader = catch_exceptions(env, |env| {
+        let parent = env
+            .call_method(
+                unsafe { JObject::from_raw(ctx.context().cast()) },
+                "getClassLoader",
+                "()Ljava/lang/ClassLoader;",
+                &[],
+            )?
+            .l()?;
+        let buffer = env
+            .call_static_method(
+                "java/nio/ByteBuffer",
+                "wrap",
+                "([B)Ljava/nio/ByteBuffer;",
+                &[JValue::from(&dex)],
+            )?
+            .l()?;
+
+        let loader = env.new_object(
+            "dalvik/system/InMemoryDexClassLoader",
+            "(Ljava/nio/ByteBuffer;Ljava/lang/ClassLoader;)V",
+            &[JValue::from(&buffer), JValue::from(&parent)],
+        )?;
+
+        env.new_global_ref(loader)
+    })?;
+
+    debug!("loaded embedded support classes");
+
+    Ok(class_loader.insert(loader).clone())
+}
+
+extern "system" fn native_callback(
+    mut env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    handle: jlong,
+    value: jint,
+    object: JObject<'_>,
+) {
+    let callback = callbacks()
+        .as_ref()
+        .and_then(|callbacks| callbacks.get(&handle).cloned());
+
+    if let Some(callback) = callback {
+        // Unwinding into Java is not allowed.
+        if let Err(payload) =
+            panic::catch_unwind(AssertUnwindSafe(|| callback(&mut env, value, &object)))
+        {
+            hook::report_panic("", "a callback from Java", payload);
+        }
+    }
+}
+
+/// A broadcast receiver registered with the app's context, which calls a Rust
+/// closure with every intent it receives.
+///
+/// The receiver is unregistered again when dropped.
+pub(crate) struct IntentReceiver {
+    receiver: GlobalRef,
+    handle: i64,
+    vm: JavaVM,
+
+    /// Name of the receiver's owner, for reporting errors.
+    name: &'static str,
+}
+
+impl IntentReceiver {
+    /// Register a receiver for broadcast intents with any of the given
+    /// actions.
+    pub(crate) fn register<F>(
+        env: &mut JNIEnv<'_>,
+        name: &'static str,
+        actions: &[&str],
+        f: F,
+    ) -> Result<Self>
+    where
+        F: Fn(&mut JNIEnv<'_>, &JObject<'_>) + Send + Sync + 'static,
+    {
+        let vm = env.get_java_vm()?;
+        let class = load_class(env, RECEIVER_CLASS)?;
+        let handle = register(move |env, _, intent| f(env, intent));
+        let ctx = ndk_context::android_context();
+
+        let receiver = catch_exceptions(env, |env| {
+            let receiver = env.new_object(&class, "(J)V", &[JValue::from(handle)])?;
+            let filter = env.new_object("android/content/IntentFilter", "()V", &[])?;
+
+            for action in actions {
+                let action = env.new_string(action)?;
+
+                env.call_method(
+                    &filter,
+                    "addAction",
+                    "(Ljava/lang/String;)V",
+                    &[JValue::from(&action)],
+                )?;
+            }
+
+            env.call_method(
+                unsafe { JObject::from_raw(ctx.context().cast()) },
+                "registerReceiver",
+                "(Landroid/content/BroadcastReceiver;Landroid/content/IntentFilter;)Landroid/content/Intent;",
+                &[JValue::from(&receiver), JValue::from(&filter)],
+            )?;
+
+            env.new_global_ref(receiver)
+        });
+
+        match receiver {
+            Ok(receiver) => Ok(Self {
+                receiver,
+                handle,
+                vm,
+                name,
+            }),
+            Err(e) => {
+                unregister(handle);
+                Err(e)
+            }
+        }
+    }
+
+    fn unregister_receiver(&self) -> Result<()> {
+        let mut env = self.vm.attach_current_thread()?;
+        let ctx = ndk_context::android_context();
+
+        catch_exceptions(&mut env, |env| {
+            env.call_method(
+                unsafe { JObject::from_raw(ctx.context().cast()) },
+                "unregisterReceiver",
+                "(Landroid/content/BroadcastReceiver;)V",
+                &[JValue::from(&self.receiver)],
+            )?
+            .v()
+        })
+    }
+}
+
+impl fmt::Debug for IntentReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IntentReceiver")
+            .field("receiver", &self.receiver)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl Drop for IntentReceiver {
+    fn drop(&mut self) {
+        unregister(self.handle);
+
+        if let Err(e) = self.unregister_receiver() {
+            hook::report_error(self.name, &e);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+use crate::{java_vm, level_supported, power, power_manager, sys, Level, Result};
+
+/// Every wake lock level, in the order they are reported in.
+#[allow(deprecated)]
+const LEVELS: [Level; 5] = [
+    Level::Partial,
+    Level::Full,
+    Level::ScreenBright,
+    Level::ScreenDim,
+    Level::ProximityScreenOff,
+];
+
+/// A report of the power management features available on the device,
+/// returned by [`capabilities`].
+///
+/// The report is meant to be logged or attached to bug reports when
+/// diagnosing device-specific behavior, and formats as a single line with
+/// [`Display`][fmt::Display].
+#[derive(Clone, Debug)]
+pub struct PowerCapabilities {
+    api_level: i32,
+    manufacturer: String,
+    model: String,
+    supported_levels: Vec<Level>,
+    sustained_performance_mode: bool,
+}
+
+impl PowerCapabilities {
+    /// Get the API level of the Android version the device is running.
+    pub fn api_level(&self) -> i32 {
+        self.api_level
+    }
+
+    /// Get the manufacturer of the device.
+    pub fn manufacturer(&self) -> &str {
+        &self.manufacturer
+    }
+
+    /// Get the model of the device.
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Get the wake lock levels supported by the device.
+    pub fn supported_levels(&self) -> &[Level] {
+        &self.supported_levels
+    }
+
+    /// Returns true if the given wake lock level is supported by the device.
+    pub fn supports_level(&self, level: Level) -> bool {
+        self.supported_levels.contains(&level)
+    }
+
+    /// Returns true if the device has Doze, available since API level 23.
+    pub fn has_doze(&self) -> bool {
+        self.api_level >= 23
+    }
+
+    /// Returns true if the thermal status APIs in [`thermal`][crate::thermal]
+    /// are available, since API level 29.
+    pub fn has_thermal_status(&self) -> bool {
+        self.api_level >= 29
+    }
+
+    /// Returns true if
+    /// [`thermal::thermal_headroom`][crate::thermal::thermal_headroom] is
+    /// available, since API level 30. The device may still not provide
+    /// headroom predictions.
+    pub fn has_thermal_headroom(&self) -> bool {
+        self.api_level >= 30
+    }
+
+    /// Returns true if the device has Low Power Standby, available since API
+    /// level 33.
+    pub fn has_low_power_standby(&self) -> bool {
+        self.api_level >= 33
+    }
+
+    /// Returns true if the device supports sustained performance mode.
+    pub fn supports_sustained_performance_mode(&self) -> bool {
+        self.sustained_performance_mode
+    }
+}
+
+impl fmt::Display for PowerCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} (API {}), levels {:?}, doze: {}, thermal status: {}, thermal headroom: {}, \
+             low power standby: {}, sustained performance: {}",
+            self.manufacturer,
+            self.model,
+            self.api_level,
+            self.supported_levels,
+            self.has_doze(),
+            self.has_thermal_status(),
+            self.has_thermal_headroom(),
+            self.has_low_power_standby(),
+            self.sustained_performance_mode,
+        )
+    }
+}
+
+/// Probe the power management features available on the device.
+///
+/// This collects the API level, device model, supported wake lock levels, and
+/// which of the newer power APIs used by this crate exist into a single
+/// report, which is useful to log once at startup when supporting apps across
+/// many device models.
+///
+/// # Examples
+///
+/// ```no_run
+/// let capabilities = android_wakelock::capabilities()?;
+///
+/// log::info!("power capabilities: {}", capabilities);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn capabilities() -> Result<PowerCapabilities> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let power_manager = power_manager(&mut env)?;
+
+    let mut supported_levels = Vec::with_capacity(LEVELS.len());
+
+    for level in LEVELS {
+        if level_supported(&mut env, &power_manager, level)? {
+            supported_levels.push(level);
+        }
+    }
+
+    Ok(PowerCapabilities {
+        api_level: sys::api_level_with(&mut env)?,
+        manufacturer: sys::manufacturer()?.to_owned(),
+        model: sys::model()?.to_owned(),
+        supported_levels,
+        sustained_performance_mode: power::sustained_performance_mode_supported(
+            &mut env,
+            &power_manager,
+        )?,
+    })
+}
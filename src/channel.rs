@@ -0,0 +1,305 @@
+//! A multi-producer, single-consumer channel that keeps the device awake while
+//! messages are pending.
+//!
+//! See [`awake_channel`] for details.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{hook, Guard, WakeLock};
+
+/// Create a new channel that holds the given wake lock whenever the channel
+/// contains messages that have not been received yet.
+///
+/// The wake lock is acquired when a message is sent to an empty channel, and
+/// released when the last pending message is received. This is useful for
+/// worker queues, where the device should be kept awake until all submitted
+/// work has been picked up. If the receiver also needs the device to stay
+/// awake while processing a message, it should acquire a guard of its own
+/// before receiving.
+///
+/// Errors acquiring or releasing the wake lock are passed to the hook set with
+/// [`set_error_hook`][crate::set_error_hook]; messages are delivered
+/// regardless.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::thread;
+///
+/// let wake_lock = android_wakelock::partial("myapp:jobs")?;
+/// let (sender, receiver) = android_wakelock::awake_channel(&wake_lock);
+///
+/// let worker = thread::spawn(move || {
+///     while let Ok(job) = receiver.recv() {
+///         println!("processing {}", job);
+///     }
+/// });
+///
+/// sender.send("job 1")?;
+/// sender.send("job 2")?;
+/// drop(sender);
+///
+/// worker.join().unwrap();
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn awake_channel<T>(wake_lock: &WakeLock) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        wake_lock: WakeLock {
+            inner: wake_lock.inner.clone(),
+        },
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            guard: None,
+            acquiring: false,
+            senders: 1,
+            receiver_alive: true,
+        }),
+        available: Condvar::new(),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// The sending half of a channel created with [`awake_channel`].
+///
+/// Senders can be cloned to send messages from multiple threads.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Send a message, acquiring the wake lock if the channel was empty.
+    ///
+    /// Returns an error containing the message if the receiver has been
+    /// dropped.
+    pub fn send(&self, message: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+
+        if !state.receiver_alive {
+            return Err(SendError(message));
+        }
+
+        // Only one sender acquires the wake lock at a time, and does so
+        // without holding the lock so that the receiver is not blocked on JNI.
+        let acquire = state.guard.is_none() && !state.acquiring;
+        state.acquiring |= acquire;
+
+        state.queue.push_back(message);
+        self.shared.available.notify_one();
+        drop(state);
+
+        if acquire {
+            let result = self.shared.wake_lock.acquire();
+            let mut state = self.shared.lock();
+
+            state.acquiring = false;
+
+            match result {
+                // The messages may have been received in the meantime.
+                Ok(guard) if state.receiver_alive && !state.queue.is_empty() => {
+                    state.guard = Some(guard);
+                }
+                Ok(guard) => {
+                    drop(state);
+                    self.shared.release(Some(guard));
+                }
+                Err(e) => hook::report_error(&self.shared.wake_lock.inner.tag, &e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+
+        state.senders -= 1;
+
+        if state.senders == 0 {
+            self.shared.available.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// The receiving half of a channel created with [`awake_channel`].
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Block until a message is available, releasing the wake lock if it was
+    /// the last pending message.
+    ///
+    /// Returns an error if all senders have been dropped and no messages are
+    /// pending.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.lock();
+
+        loop {
+            if let Some((message, guard)) = self.shared.pop(&mut state) {
+                drop(state);
+                self.shared.release(guard);
+                return Ok(message);
+            }
+
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+
+            state = self.shared.available.wait(state).unwrap();
+        }
+    }
+
+    /// Receive a pending message without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+
+        match self.shared.pop(&mut state) {
+            Some((message, guard)) => {
+                drop(state);
+                self.shared.release(guard);
+                Ok(message)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Block until a message is available or the given timeout elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+
+        loop {
+            if let Some((message, guard)) = self.shared.pop(&mut state) {
+                drop(state);
+                self.shared.release(guard);
+                return Ok(message);
+            }
+
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+
+            state = self
+                .shared
+                .available
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Returns the number of messages pending in the channel.
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    /// Returns true if there are no messages pending in the channel.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+
+        state.receiver_alive = false;
+        state.queue.clear();
+
+        let guard = state.guard.take();
+
+        drop(state);
+        self.shared.release(guard);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+struct Shared<T> {
+    wake_lock: WakeLock,
+    state: Mutex<State<T>>,
+    available: Condvar,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+
+    /// Held whenever the queue is non-empty.
+    guard: Option<Guard>,
+
+    /// Whether a sender is currently acquiring the guard.
+    acquiring: bool,
+
+    senders: usize,
+    receiver_alive: bool,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Take the next message from the queue, along with the guard to release
+    /// once the lock is dropped if the queue is now empty.
+    fn pop(&self, state: &mut State<T>) -> Option<(T, Option<Guard>)> {
+        let message = state.queue.pop_front()?;
+        let guard = if state.queue.is_empty() {
+            state.guard.take()
+        } else {
+            None
+        };
+
+        Some((message, guard))
+    }
+
+    fn release(&self, guard: Option<Guard>) {
+        if let Some(guard) = guard {
+            if let Err(e) = guard.release() {
+                hook::report_error(&self.wake_lock.inner.tag, &e);
+            }
+        }
+    }
+}
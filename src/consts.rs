@@ -0,0 +1,76 @@
+//! Raw values of the Android constants used by this crate.
+//!
+//! These are the same values as in the Android SDK, and are useful for code
+//! that registers its own receivers or calls `PowerManager` directly, for
+//! example from Java. Most code should use the typed equivalents instead, such
+//! as [`Level`][crate::Level] and [`PowerEvent`][crate::events::PowerEvent].
+
+/// `PowerManager.PARTIAL_WAKE_LOCK`
+pub const PARTIAL_WAKE_LOCK: i32 = 0x00000001;
+
+/// `PowerManager.SCREEN_DIM_WAKE_LOCK`
+pub const SCREEN_DIM_WAKE_LOCK: i32 = 0x00000006;
+
+/// `PowerManager.SCREEN_BRIGHT_WAKE_LOCK`
+pub const SCREEN_BRIGHT_WAKE_LOCK: i32 = 0x0000000a;
+
+/// `PowerManager.FULL_WAKE_LOCK`
+pub const FULL_WAKE_LOCK: i32 = 0x0000001a;
+
+/// `PowerManager.PROXIMITY_SCREEN_OFF_WAKE_LOCK`
+pub const PROXIMITY_SCREEN_OFF_WAKE_LOCK: i32 = 0x00000020;
+
+/// `PowerManager.ACQUIRE_CAUSES_WAKEUP`
+pub const ACQUIRE_CAUSES_WAKEUP: i32 = 0x10000000;
+
+/// `PowerManager.ON_AFTER_RELEASE`
+pub const ON_AFTER_RELEASE: i32 = 0x20000000;
+
+/// `PowerManager.RELEASE_FLAG_WAIT_FOR_NO_PROXIMITY`
+pub const RELEASE_FLAG_WAIT_FOR_NO_PROXIMITY: i32 = 0x00000001;
+
+/// `WindowManager.LayoutParams.FLAG_KEEP_SCREEN_ON`
+pub const FLAG_KEEP_SCREEN_ON: i32 = 0x00000080;
+
+/// `WindowManager.LayoutParams.BRIGHTNESS_OVERRIDE_FULL`
+pub const BRIGHTNESS_OVERRIDE_FULL: f32 = 1.0;
+
+/// `PowerManager.ACTION_POWER_SAVE_MODE_CHANGED`
+pub const ACTION_POWER_SAVE_MODE_CHANGED: &str = "android.os.action.POWER_SAVE_MODE_CHANGED";
+
+/// `PowerManager.ACTION_DEVICE_IDLE_MODE_CHANGED`
+pub const ACTION_DEVICE_IDLE_MODE_CHANGED: &str = "android.os.action.DEVICE_IDLE_MODE_CHANGED";
+
+/// `PowerManager.ACTION_DEVICE_LIGHT_IDLE_MODE_CHANGED`, added in API level
+/// 33.
+pub const ACTION_DEVICE_LIGHT_IDLE_MODE_CHANGED: &str =
+    "android.os.action.LIGHT_DEVICE_IDLE_MODE_CHANGED";
+
+/// `PowerManager.ACTION_LOW_POWER_STANDBY_ENABLED_CHANGED`, added in API
+/// level 33.
+pub const ACTION_LOW_POWER_STANDBY_ENABLED_CHANGED: &str =
+    "android.os.action.LOW_POWER_STANDBY_ENABLED_CHANGED";
+
+/// `Intent.ACTION_SCREEN_ON`
+pub const ACTION_SCREEN_ON: &str = "android.intent.action.SCREEN_ON";
+
+/// `Intent.ACTION_SCREEN_OFF`
+pub const ACTION_SCREEN_OFF: &str = "android.intent.action.SCREEN_OFF";
+
+/// `Intent.ACTION_BATTERY_CHANGED`
+pub const ACTION_BATTERY_CHANGED: &str = "android.intent.action.BATTERY_CHANGED";
+
+/// `Intent.ACTION_BATTERY_LOW`
+pub const ACTION_BATTERY_LOW: &str = "android.intent.action.BATTERY_LOW";
+
+/// `Intent.ACTION_BATTERY_OKAY`
+pub const ACTION_BATTERY_OKAY: &str = "android.intent.action.BATTERY_OKAY";
+
+/// `Intent.ACTION_POWER_CONNECTED`
+pub const ACTION_POWER_CONNECTED: &str = "android.intent.action.ACTION_POWER_CONNECTED";
+
+/// `Intent.ACTION_POWER_DISCONNECTED`
+pub const ACTION_POWER_DISCONNECTED: &str = "android.intent.action.ACTION_POWER_DISCONNECTED";
+
+/// `Intent.ACTION_SHUTDOWN`
+pub const ACTION_SHUTDOWN: &str = "android.intent.action.ACTION_SHUTDOWN";
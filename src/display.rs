@@ -0,0 +1,181 @@
+//! Querying the state of the device's displays.
+//!
+//! Whether a screen is actually on is often useful to know before deciding
+//! which wake lock level to use, for example to avoid acquiring a screen-level
+//! wake lock while the screen is already off. The functions in this module wrap
+//! `DisplayManager.getDisplays()` and `Display.getState()` for this purpose.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use android_wakelock::display::{self, DisplayState};
+//!
+//! if display::default_display_state()? == DisplayState::On {
+//!     // The screen is on...
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use jni::{
+    objects::{JObject, JObjectArray},
+    JNIEnv,
+};
+
+use crate::{catch_exceptions, java_string, java_vm, system_service, Result};
+
+/// `Display.DEFAULT_DISPLAY`
+const DEFAULT_DISPLAY: i32 = 0;
+
+/// Possible power states of a display.
+///
+/// New states may be added in future Android versions, so code matching on
+/// this should include a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DisplayState {
+    /// The display state is unknown.
+    Unknown,
+
+    /// The display is off.
+    Off,
+
+    /// The display is on.
+    On,
+
+    /// The display is dozing in a low power state; it is still on but is
+    /// optimized for showing system-provided content while the device is
+    /// non-interactive.
+    Doze,
+
+    /// The display is dozing in a suspended low power state; it is still on but
+    /// the CPU is not updating it.
+    DozeSuspend,
+
+    /// The display is on and optimized for VR mode.
+    Vr,
+
+    /// The display is in a suspended full power state; it is still on but the
+    /// CPU is not updating it.
+    OnSuspend,
+}
+
+impl DisplayState {
+    fn from_raw(state: i32) -> Self {
+        match state {
+            1 => Self::Off,
+            2 => Self::On,
+            3 => Self::Doze,
+            4 => Self::DozeSuspend,
+            5 => Self::Vr,
+            6 => Self::OnSuspend,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Returns true if the display is on in any way, including dozing.
+    pub fn is_on(self) -> bool {
+        !matches!(self, Self::Off | Self::Unknown)
+    }
+}
+
+/// Information about a logical display.
+#[derive(Clone, Debug)]
+pub struct Display {
+    id: i32,
+    name: Option<String>,
+    state: DisplayState,
+}
+
+impl Display {
+    /// Get the display ID. The default display has ID 0.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Get the name of the display, if it has one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Get the state of the display at the time it was queried.
+    pub fn state(&self) -> DisplayState {
+        self.state
+    }
+
+    /// Returns true if this is the default display.
+    pub fn is_default(&self) -> bool {
+        self.id == DEFAULT_DISPLAY
+    }
+}
+
+/// Get all logical displays of the device, including secondary displays, along
+/// with their current state.
+pub fn displays() -> Result<Vec<Display>> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let display_manager = system_service(&mut env, "display")?;
+
+    catch_exceptions(&mut env, |env| {
+        let displays = JObjectArray::from(
+            env.call_method(
+                &display_manager,
+                "getDisplays",
+                "()[Landroid/view/Display;",
+                &[],
+            )?
+            .l()?,
+        );
+        let len = env.get_array_length(&displays)?;
+        let mut result = Vec::with_capacity(len as usize);
+
+        for i in 0..len {
+            let display = env.get_object_array_element(&displays, i)?;
+
+            result.push(describe_display(env, &display)?);
+            env.delete_local_ref(display)?;
+        }
+
+        Ok(result)
+    })
+}
+
+/// Get the current state of the default display.
+pub fn default_display_state() -> Result<DisplayState> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let display_manager = system_service(&mut env, "display")?;
+
+    catch_exceptions(&mut env, |env| {
+        let display = env
+            .call_method(
+                &display_manager,
+                "getDisplay",
+                "(I)Landroid/view/Display;",
+                &[DEFAULT_DISPLAY.into()],
+            )?
+            .l()?;
+
+        if display.is_null() {
+            return Ok(DisplayState::Unknown);
+        }
+
+        Ok(DisplayState::from_raw(
+            env.call_method(&display, "getState", "()I", &[])?.i()?,
+        ))
+    })
+}
+
+fn describe_display(env: &mut JNIEnv<'_>, display: &JObject<'_>) -> jni::errors::Result<Display> {
+    let id = env.call_method(display, "getDisplayId", "()I", &[])?.i()?;
+    let name = env
+        .call_method(display, "getName", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let name = java_string(env, name)?;
+    let state = env.call_method(display, "getState", "()I", &[])?.i()?;
+
+    Ok(Display {
+        id,
+        name,
+        state: DisplayState::from_raw(state),
+    })
+}
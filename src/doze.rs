@@ -0,0 +1,206 @@
+//! Detecting Doze maintenance windows.
+//!
+//! While the device is in Doze, wake locks held by apps are ignored, so work
+//! protected by a wake lock does not actually keep the device awake. The
+//! system periodically exits Doze for a short maintenance window in which
+//! deferred background work may run and wake locks take effect again, before
+//! going back to idle. Background work that can wait is best aligned with
+//! these windows.
+//!
+//! The platform does not report maintenance windows directly. A
+//! [`DozeMonitor`] derives them from `ACTION_DEVICE_IDLE_MODE_CHANGED`
+//! broadcasts and `PowerManager.isDeviceIdleMode()`: the device is in a
+//! maintenance window if it has left idle mode without becoming interactive.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use android_wakelock::doze::DozeMonitor;
+//!
+//! let monitor = DozeMonitor::new()?;
+//! let wake_lock = android_wakelock::partial("myapp:sync")?;
+//!
+//! if monitor.wait_for_maintenance_window(Duration::from_secs(3600))? {
+//!     let _guard = wake_lock.acquire()?;
+//!     // Sync while the wake lock has effect...
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    callback::IntentReceiver, consts::ACTION_DEVICE_IDLE_MODE_CHANGED, hook, java_vm, power, sys,
+    Error, ErrorKind, Result,
+};
+
+/// The Doze state of the device, as seen by a [`DozeMonitor`].
+///
+/// New states may be added in the future, so code matching on this should
+/// include a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DozeState {
+    /// The device is not in Doze.
+    Active,
+
+    /// The device is in Doze, and wake locks currently have no effect.
+    Idle,
+
+    /// The device has temporarily left Doze for a maintenance window.
+    MaintenanceWindow,
+}
+
+/// Tracks Doze idle mode changes to detect maintenance windows.
+///
+/// This registers a receiver for `ACTION_DEVICE_IDLE_MODE_CHANGED`, which is
+/// unregistered again when the monitor is dropped. Only changes after the
+/// monitor was created are seen, so a maintenance window is first reported
+/// once the device has entered idle mode at least once since then.
+///
+/// Since broadcast receivers can only be implemented in Java, this uses the
+/// `rs.android_wakelock.IntentReceiver` class embedded in the crate. On API
+/// levels 23 to 25 it must be included in the app from the crate's `java`
+/// directory instead; otherwise an error of kind [`ErrorKind::Unsupported`] is
+/// returned. The same error is returned on devices older than API level 23,
+/// which do not have Doze.
+pub struct DozeMonitor {
+    shared: Arc<Shared>,
+
+    /// Unregistered when the monitor is dropped.
+    receiver: IntentReceiver,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+struct State {
+    /// Whether the device was idle at the last change.
+    idle: bool,
+
+    /// Whether the device has been idle since it was last interactive.
+    dozing: bool,
+}
+
+impl DozeMonitor {
+    /// Start monitoring idle mode changes.
+    pub fn new() -> Result<Self> {
+        let vm = java_vm()?;
+        let mut env = vm.attach_current_thread()?;
+
+        if sys::api_level_with(&mut env)? < 23 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Doze requires API level 23",
+            ));
+        }
+
+        let idle = power::device_idle_mode(&mut env)?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State { idle, dozing: idle }),
+            condvar: Condvar::new(),
+        });
+
+        let receiver = IntentReceiver::register(
+            &mut env,
+            "doze monitor",
+            &[ACTION_DEVICE_IDLE_MODE_CHANGED],
+            {
+                let shared = shared.clone();
+
+                move |env, _| match power::device_idle_mode(env) {
+                    Ok(idle) => {
+                        let mut state = shared.lock();
+
+                        state.idle = idle;
+                        state.dozing |= idle;
+                        shared.condvar.notify_all();
+
+                        debug!("device idle mode changed: {}", idle);
+                    }
+                    Err(e) => hook::report_error("doze monitor", &e),
+                }
+            },
+        )?;
+
+        Ok(Self { shared, receiver })
+    }
+
+    /// Get the current Doze state of the device.
+    pub fn state(&self) -> Result<DozeState> {
+        // Checking whether the device is interactive first means a window is
+        // never reported once the user has started using the device.
+        let interactive = power::is_interactive()?;
+        let mut state = self.shared.lock();
+
+        if interactive {
+            state.dozing = false;
+        }
+
+        Ok(if state.idle {
+            DozeState::Idle
+        } else if state.dozing {
+            DozeState::MaintenanceWindow
+        } else {
+            DozeState::Active
+        })
+    }
+
+    /// Returns true if the device is currently in a Doze maintenance window.
+    pub fn is_in_maintenance_window(&self) -> Result<bool> {
+        Ok(self.state()? == DozeState::MaintenanceWindow)
+    }
+
+    /// Wait until the device is in a maintenance window for at most the given
+    /// timeout.
+    ///
+    /// Returns false if no maintenance window started before the timeout.
+    pub fn wait_for_maintenance_window(&self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if self.is_in_maintenance_window()? {
+                return Ok(true);
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Ok(false);
+            }
+
+            let state = self.shared.lock();
+
+            // Only wait for the next change if none happened while checking.
+            if state.idle || !state.dozing {
+                drop(
+                    self.shared
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner()),
+                );
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for DozeMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DozeMonitor")
+            .field("receiver", &self.receiver)
+            .finish_non_exhaustive()
+    }
+}
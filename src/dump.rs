@@ -0,0 +1,196 @@
+//! Dumping the state of all wake locks as JSON, for debugging.
+
+use std::time::{Duration, Instant};
+
+use jni::objects::JValue;
+use serde::Serialize;
+
+use crate::{
+    catch_exceptions, java_vm, registry,
+    stats::{self, StatsSnapshot},
+    Inner, Result,
+};
+
+/// Logcat tag used by [`dump_to_logcat`], unless a log target is set.
+const LOGCAT_TAG: &str = "android_wakelock";
+
+#[derive(Serialize)]
+struct WakeLockDump<'a> {
+    tag: &'a str,
+    level: String,
+    backend: String,
+
+    /// Number of references to the underlying wake lock currently held.
+    references: usize,
+
+    acquires: u64,
+    releases: u64,
+    total_held_ms: u64,
+    longest_held_ms: u64,
+    guards: Vec<GuardDump>,
+}
+
+#[derive(Serialize)]
+struct GuardDump {
+    id: u64,
+    call_site: String,
+    held_ms: u64,
+    timeout_ms: Option<u64>,
+    timed_out: bool,
+    #[cfg(feature = "audit")]
+    backtrace: String,
+}
+
+/// Get a JSON description of every wake lock created by the crate that is
+/// still alive, for displaying in a debug screen or attaching to a bug report.
+///
+/// The result is an array with an object for each wake lock, in the order
+/// they were created, such as the following:
+///
+/// ```json
+/// [
+///   {
+///     "tag": "myapp:sync",
+///     "level": "Partial",
+///     "backend": "Framework",
+///     "references": 1,
+///     "acquires": 12,
+///     "releases": 11,
+///     "total_held_ms": 48210,
+///     "longest_held_ms": 9120,
+///     "guards": [
+///       {
+///         "id": 57,
+///         "call_site": "src/sync.rs:42:29",
+///         "held_ms": 1530,
+///         "timeout_ms": 60000,
+///         "timed_out": false
+///       }
+///     ]
+///   }
+/// ]
+/// ```
+///
+/// `acquires`, `releases`, `total_held_ms`, and `longest_held_ms` are the
+/// statistics of the wake lock's tag, as returned by [`stats::snapshot`], so
+/// they include other wake locks with the same tag. `guards` lists the guards
+/// currently holding the wake lock with their [ID][crate::Guard::id], where
+/// they were acquired and for how long, and the timeout they were acquired
+/// with, if any. With the `audit` feature, guards also include the `backtrace`
+/// of where they were acquired. New fields may be added in the future.
+///
+/// Requires the `dump` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// let wake_lock = android_wakelock::partial("myapp:sync")?;
+/// let _guard = wake_lock.acquire()?;
+///
+/// println!("{}", android_wakelock::dump());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn dump() -> String {
+    let now = Instant::now();
+    let wake_locks = registry::wake_locks();
+    let stats = stats::snapshot();
+    let dumps = wake_locks
+        .iter()
+        .map(|inner| dump_wake_lock(inner, &stats, now))
+        .collect::<Vec<_>>();
+
+    serde_json::to_string(&dumps).expect("wake lock dump is always serializable")
+}
+
+/// Write the same information as [`dump`] to logcat at info level, with one
+/// line for each wake lock.
+///
+/// This is meant to be triggered on demand, such as from a debug menu, so that
+/// testers can attach the output to bug reports. Messages are written with
+/// `android.util.Log` regardless of which logger, if any, the app uses, with
+/// the tag set with [`set_log_target`][crate::set_log_target], or
+/// `android_wakelock` by default. Logcat truncates very long lines, which can
+/// happen for wake locks with many guards.
+///
+/// Requires the `dump` feature.
+pub fn dump_to_logcat() -> Result<()> {
+    let now = Instant::now();
+    let wake_locks = registry::wake_locks();
+    let stats = stats::snapshot();
+
+    #[cfg(feature = "log")]
+    let tag = crate::logging::target().unwrap_or(LOGCAT_TAG);
+    #[cfg(not(feature = "log"))]
+    let tag = LOGCAT_TAG;
+
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let tag = env.new_string(tag)?;
+
+    let header = format!("dumping {} wake locks", wake_locks.len());
+    let lines = wake_locks.iter().map(|inner| {
+        serde_json::to_string(&dump_wake_lock(inner, &stats, now))
+            .expect("wake lock dump is always serializable")
+    });
+
+    for line in std::iter::once(header).chain(lines) {
+        let message = env.new_string(line)?;
+
+        catch_exceptions(&mut env, |env| {
+            env.call_static_method(
+                "android/util/Log",
+                "i",
+                "(Ljava/lang/String;Ljava/lang/String;)I",
+                &[JValue::from(&tag), JValue::from(&message)],
+            )?
+            .i()
+        })?;
+
+        env.delete_local_ref(message)?;
+    }
+
+    Ok(())
+}
+
+fn dump_wake_lock<'a>(inner: &'a Inner, stats: &StatsSnapshot, now: Instant) -> WakeLockDump<'a> {
+    let (level, references) = {
+        let object = inner.object();
+
+        (
+            object.config.level,
+            object.held + object.deadlines.iter().filter(|d| **d > now).count(),
+        )
+    };
+    let tag_stats = stats.tag(&inner.tag);
+
+    WakeLockDump {
+        tag: &inner.tag,
+        level: format!("{:?}", level),
+        backend: format!("{:?}", inner.backend),
+        references,
+        acquires: tag_stats.map_or(0, |stats| stats.acquires()),
+        releases: tag_stats.map_or(0, |stats| stats.releases()),
+        total_held_ms: tag_stats.map_or(0, |stats| millis(stats.total_held())),
+        longest_held_ms: tag_stats.map_or(0, |stats| millis(stats.longest_held())),
+        guards: inner
+            .guards
+            .snapshot()
+            .iter()
+            .map(|guard| GuardDump {
+                id: guard.id,
+                call_site: guard.caller.to_string(),
+                held_ms: millis(now.saturating_duration_since(guard.acquired_at)),
+                timeout_ms: guard
+                    .deadline
+                    .map(|deadline| millis(deadline.saturating_duration_since(guard.acquired_at))),
+                timed_out: guard.deadline.is_some_and(|deadline| deadline <= now),
+                #[cfg(feature = "audit")]
+                backtrace: guard.backtrace.to_string(),
+            })
+            .collect(),
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
@@ -0,0 +1,439 @@
+use std::fmt;
+
+/// An error returned by the wake lock API. A variety of errors can occur when
+/// calling Android APIs, such as JNI errors, or exceptions actually thrown by the
+/// API itself.
+///
+/// Errors are `Send + Sync + 'static`, so they can be returned across threads
+/// and converted into common error types such as `Box<dyn std::error::Error>`.
+#[derive(Debug)]
+pub struct Error {
+    repr: Repr,
+}
+
+// Keep errors `Send + Sync + 'static`, as documented above.
+const _: fn() = || {
+    fn assert<T: Send + Sync + 'static>() {}
+    assert::<Error>();
+};
+
+/// A general category of [`Error`], returned by [`Error::kind`].
+///
+/// New kinds may be added in the future, so code matching on this should
+/// include a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The app is missing a permission required by the operation, such as
+    /// `android.permission.WAKE_LOCK`.
+    PermissionDenied,
+
+    /// The operation or configuration is not supported on this device.
+    Unsupported,
+
+    /// The wake lock was configured with an invalid combination of options.
+    InvalidConfig,
+
+    /// The operation must be performed on a different thread, such as the
+    /// app's UI thread.
+    WrongThread,
+
+    /// The wake lock's hold budget has been reached, so no more guards can be
+    /// acquired until one is released.
+    BudgetExhausted,
+
+    /// The battery is below the threshold set with
+    /// [`Builder::auto_release_below_battery`][crate::Builder::auto_release_below_battery],
+    /// so the wake lock cannot be acquired until it recovers or the device is
+    /// charging.
+    BatteryLow,
+
+    /// Battery saver is on and the wake lock is configured with
+    /// [`Builder::respect_power_save_mode`][crate::Builder::respect_power_save_mode]
+    /// to not be acquired while it is.
+    PowerSaveActive,
+
+    /// An error occurred in the JNI layer, such as failing to attach the
+    /// current thread to the JVM.
+    Jni,
+
+    /// The wake lock was released more times than it was acquired. This can
+    /// happen legitimately when a guard acquired with
+    /// [`WakeLock::acquire_timeout`][crate::WakeLock::acquire_timeout] is
+    /// released after its timeout already expired.
+    UnderLocked,
+
+    /// The Java API threw an exception. The exception's class and message are
+    /// available from [`Error::exception_class`] and
+    /// [`Error::exception_message`].
+    JavaException,
+
+    /// Any other error.
+    Other,
+}
+
+#[derive(Debug)]
+enum Repr {
+    /// An error from the JNI layer.
+    Jni(jni::errors::Error),
+
+    /// An exception thrown by the Java API.
+    Exception {
+        class: Option<String>,
+        message: Option<String>,
+        #[cfg(feature = "verbose-errors")]
+        stack_trace: Option<String>,
+    },
+
+    /// Acquiring the wake lock was denied because the app is missing a
+    /// required permission.
+    MissingPermission {
+        /// Whether `TURN_SCREEN_ON` may also be required.
+        turn_screen_on: bool,
+
+        /// The original `SecurityException`.
+        exception: Box<Error>,
+    },
+
+    /// The wake lock was released more times than it was acquired.
+    UnderLocked(Box<Error>),
+
+    /// An exception thrown by unrelated Java code was still pending on the
+    /// current thread when calling into Java. The exception is only described
+    /// if it was cleared by a retry policy.
+    PendingException(Option<Box<Error>>),
+
+    /// An error originating from this crate.
+    Custom { kind: ErrorKind, message: String },
+}
+
+impl Error {
+    /// Create an error for a Java exception with the given class name and
+    /// message.
+    pub(crate) fn exception(class: Option<String>, message: Option<String>) -> Self {
+        Self {
+            repr: Repr::Exception {
+                class,
+                message,
+                #[cfg(feature = "verbose-errors")]
+                stack_trace: None,
+            },
+        }
+    }
+
+    /// Attach a Java stack trace to an exception error.
+    #[cfg(feature = "verbose-errors")]
+    pub(crate) fn with_stack_trace(mut self, trace: Option<String>) -> Self {
+        if let Repr::Exception { stack_trace, .. } = &mut self.repr {
+            *stack_trace = trace;
+        }
+
+        self
+    }
+
+    /// Wrap a `SecurityException` thrown when acquiring a wake lock with
+    /// advice on which permissions are required.
+    pub(crate) fn missing_permission(exception: Error, turn_screen_on: bool) -> Self {
+        Self {
+            repr: Repr::MissingPermission {
+                turn_screen_on,
+                exception: Box::new(exception),
+            },
+        }
+    }
+
+    /// Wrap a `RuntimeException` thrown when releasing an under-locked wake
+    /// lock.
+    pub(crate) fn under_locked(exception: Error) -> Self {
+        Self {
+            repr: Repr::UnderLocked(Box::new(exception)),
+        }
+    }
+
+    /// Create an error for an unrelated exception found pending before calling
+    /// into Java, along with a description of it if it was cleared.
+    pub(crate) fn pending_exception(exception: Option<Error>) -> Self {
+        Self {
+            repr: Repr::PendingException(exception.map(Box::new)),
+        }
+    }
+
+    /// Returns true if the error is likely to go away if the operation is
+    /// retried.
+    pub(crate) fn is_transient(&self) -> bool {
+        matches!(
+            self.repr,
+            Repr::Jni(jni::errors::Error::JniCall(_))
+                | Repr::Jni(jni::errors::Error::TryLock)
+                | Repr::PendingException(_)
+        )
+    }
+
+    /// Returns true if the error was caused by the current thread not being
+    /// attached to the JVM.
+    pub(crate) fn is_thread_detached(&self) -> bool {
+        matches!(
+            self.repr,
+            Repr::Jni(jni::errors::Error::JniCall(
+                jni::errors::JniError::ThreadDetached
+            ))
+        )
+    }
+
+    /// Returns true if the error was caused by a Java method not existing, such
+    /// as when calling an API not available on the current API level.
+    pub(crate) fn is_method_not_found(&self) -> bool {
+        match &self.repr {
+            Repr::Jni(jni::errors::Error::MethodNotFound { .. }) => true,
+            Repr::Exception { class, .. } => {
+                class.as_deref() == Some("java.lang.NoSuchMethodError")
+            }
+            _ => false,
+        }
+    }
+
+    /// Create an error of the given kind with a custom message.
+    pub(crate) fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            repr: Repr::Custom {
+                kind,
+                message: message.into(),
+            },
+        }
+    }
+
+    /// Returns the general category of this error.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::ErrorKind;
+    ///
+    /// let wake_lock = android_wakelock::partial("myapp:mytag")?;
+    ///
+    /// match wake_lock.acquire() {
+    ///     Ok(guard) => { /* ... */ }
+    ///     Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+    ///         eprintln!("{}", e);
+    ///     }
+    ///     Err(e) => return Err(e.into()),
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn kind(&self) -> ErrorKind {
+        match &self.repr {
+            Repr::Jni(_) | Repr::PendingException(_) => ErrorKind::Jni,
+            Repr::Exception { .. } => ErrorKind::JavaException,
+            Repr::MissingPermission { .. } => ErrorKind::PermissionDenied,
+            Repr::UnderLocked(_) => ErrorKind::UnderLocked,
+            Repr::Custom { kind, .. } => *kind,
+        }
+    }
+
+    /// Returns true if the wake lock could not be acquired because the app is
+    /// missing a required permission, such as `android.permission.WAKE_LOCK`.
+    ///
+    /// The error's message describes which permissions need to be declared in
+    /// the app's manifest.
+    pub fn is_missing_permission(&self) -> bool {
+        self.kind() == ErrorKind::PermissionDenied
+    }
+
+    /// If this error was caused by an exception thrown by the Java API, returns
+    /// the fully qualified class name of the exception, such as
+    /// `java.lang.SecurityException`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let wake_lock = android_wakelock::partial("myapp:mytag")?;
+    ///
+    /// match wake_lock.acquire() {
+    ///     Ok(guard) => { /* ... */ }
+    ///     Err(e) if e.exception_class() == Some("java.lang.SecurityException") => {
+    ///         eprintln!("missing permission: {}", e);
+    ///     }
+    ///     Err(e) => return Err(e.into()),
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn exception_class(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Exception { class, .. } => class.as_deref(),
+            Repr::MissingPermission { exception, .. } | Repr::UnderLocked(exception) => {
+                exception.exception_class()
+            }
+            _ => None,
+        }
+    }
+
+    /// If this error was caused by an exception thrown by the Java API, returns
+    /// the exception's message, if it had one.
+    pub fn exception_message(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Exception { message, .. } => message.as_deref(),
+            Repr::MissingPermission { exception, .. } | Repr::UnderLocked(exception) => {
+                exception.exception_message()
+            }
+            _ => None,
+        }
+    }
+
+    /// If this error was caused by an exception thrown by the Java API, returns
+    /// the full Java stack trace of the exception.
+    ///
+    /// The stack trace is also included when the error is displayed.
+    ///
+    /// Requires the `verbose-errors` feature.
+    #[cfg(feature = "verbose-errors")]
+    pub fn java_stack_trace(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Exception { stack_trace, .. } => stack_trace.as_deref(),
+            Repr::MissingPermission { exception, .. } | Repr::UnderLocked(exception) => {
+                exception.java_stack_trace()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repr {
+            Repr::Jni(e) => write!(f, "JNI error: {}", e),
+            // Use the same format as Java's `Throwable.toString`.
+            Repr::Exception {
+                class,
+                message,
+                #[cfg(feature = "verbose-errors")]
+                stack_trace,
+            } => {
+                f.write_str(class.as_deref().unwrap_or("Java exception"))?;
+
+                if let Some(message) = message {
+                    write!(f, ": {}", message)?;
+                }
+
+                // The first line of the stack trace repeats the exception
+                // class and message, which have already been written.
+                #[cfg(feature = "verbose-errors")]
+                if let Some(stack_trace) = stack_trace {
+                    for line in stack_trace.lines().skip(1) {
+                        write!(f, "\n{}", line)?;
+                    }
+                }
+
+                Ok(())
+            }
+            Repr::MissingPermission { turn_screen_on, .. } => {
+                f.write_str(
+                    "permission denied acquiring wake lock; add \
+                    `<uses-permission android:name=\"android.permission.WAKE_LOCK\"/>` \
+                    to your app's AndroidManifest.xml",
+                )?;
+
+                if *turn_screen_on {
+                    f.write_str(
+                        ", and since the wake lock causes the screen to turn on, also add \
+                        `<uses-permission android:name=\"android.permission.TURN_SCREEN_ON\"/>` \
+                        for apps targeting API level 34 or higher",
+                    )?;
+                }
+
+                Ok(())
+            }
+            Repr::UnderLocked(exception) => write!(
+                f,
+                "wake lock was released more times than it was acquired: {}",
+                exception
+            ),
+            Repr::PendingException(Some(exception)) => write!(
+                f,
+                "an unrelated Java exception was pending when calling into Java: {}",
+                exception
+            ),
+            Repr::PendingException(None) => {
+                f.write_str("an unrelated Java exception was pending when calling into Java")
+            }
+            Repr::Custom { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.repr {
+            Repr::Jni(e) => Some(e),
+            Repr::MissingPermission { exception, .. }
+            | Repr::UnderLocked(exception)
+            | Repr::PendingException(Some(exception)) => Some(exception),
+            _ => None,
+        }
+    }
+}
+
+impl From<jni::errors::Error> for Error {
+    fn from(error: jni::errors::Error) -> Self {
+        Self {
+            repr: Repr::Jni(error),
+        }
+    }
+}
+
+/// Rich diagnostics for rendering errors with [`miette`].
+///
+/// Requires the `diagnostics` feature.
+#[cfg(feature = "diagnostics")]
+impl miette::Diagnostic for Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let code = match &self.repr {
+            Repr::Jni(_) => "android_wakelock::jni",
+            Repr::Exception { .. } => "android_wakelock::java_exception",
+            Repr::MissingPermission { .. } => "android_wakelock::missing_permission",
+            Repr::UnderLocked(_) => "android_wakelock::under_locked",
+            Repr::PendingException(_) => "android_wakelock::pending_exception",
+            Repr::Custom { kind, .. } => match kind {
+                ErrorKind::PermissionDenied => "android_wakelock::permission_denied",
+                ErrorKind::Unsupported => "android_wakelock::unsupported",
+                ErrorKind::InvalidConfig => "android_wakelock::invalid_config",
+                ErrorKind::WrongThread => "android_wakelock::wrong_thread",
+                ErrorKind::BudgetExhausted => "android_wakelock::budget_exhausted",
+                ErrorKind::BatteryLow => "android_wakelock::battery_low",
+                ErrorKind::PowerSaveActive => "android_wakelock::power_save_active",
+                ErrorKind::UnderLocked => "android_wakelock::under_locked",
+                ErrorKind::Jni => "android_wakelock::jni",
+                ErrorKind::JavaException => "android_wakelock::java_exception",
+                ErrorKind::Other => "android_wakelock::error",
+            },
+        };
+
+        Some(Box::new(code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let help = match &self.repr {
+            Repr::MissingPermission {
+                turn_screen_on: false,
+                ..
+            } => "add `<uses-permission android:name=\"android.permission.WAKE_LOCK\"/>` to AndroidManifest.xml",
+            Repr::MissingPermission {
+                turn_screen_on: true,
+                ..
+            } => "add `<uses-permission android:name=\"android.permission.WAKE_LOCK\"/>` and `<uses-permission android:name=\"android.permission.TURN_SCREEN_ON\"/>` to AndroidManifest.xml",
+            Repr::PendingException(_) => "Java code called earlier on this thread threw an exception that was never handled; handle it before calling into this crate, or set a retry policy with `Builder::retry` to clear it",
+            Repr::Jni(jni::errors::Error::JniCall(_)) => "the current thread could not be attached to the JVM; consider setting a retry policy with `Builder::retry`",
+            _ => return None,
+        };
+
+        Some(Box::new(help))
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn miette::Diagnostic> {
+        match &self.repr {
+            Repr::MissingPermission { exception, .. }
+            | Repr::UnderLocked(exception)
+            | Repr::PendingException(Some(exception)) => Some(exception.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,610 @@
+//! Power-related events broadcast by the platform.
+//!
+//! Use [`subscribe`] to receive events as they happen, or one of the more
+//! specific functions such as [`screen_state`], [`power_save_mode`],
+//! [`device_idle_mode`], and [`battery`].
+//!
+//! # Examples
+//!
+//! ```
+//! use android_wakelock::events::PowerEvent;
+//!
+//! let event = PowerEvent::from_action("android.intent.action.SCREEN_OFF");
+//!
+//! assert_eq!(event, Some(PowerEvent::ScreenOff));
+//! assert_eq!(PowerEvent::ScreenOff.action(), "android.intent.action.SCREEN_OFF");
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "stream")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use jni::{objects::JObject, JNIEnv};
+
+use crate::{
+    callback::IntentReceiver, catch_exceptions, consts, hook, java_string, java_vm, power, sys,
+    Error, ErrorKind, Result,
+};
+
+/// A power-related event, identified by the action of the broadcast intent
+/// the platform sends for it.
+///
+/// New events may be added in the future, so code matching on this should
+/// include a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PowerEvent {
+    /// The screen turned on, from `Intent.ACTION_SCREEN_ON`.
+    ScreenOn,
+
+    /// The screen turned off, from `Intent.ACTION_SCREEN_OFF`.
+    ScreenOff,
+
+    /// Battery saver was turned on or off, from
+    /// `PowerManager.ACTION_POWER_SAVE_MODE_CHANGED`.
+    PowerSaveModeChanged,
+
+    /// The device entered or left Doze, from
+    /// `PowerManager.ACTION_DEVICE_IDLE_MODE_CHANGED`.
+    DeviceIdleModeChanged,
+
+    /// The device entered or left light Doze, from
+    /// `PowerManager.ACTION_DEVICE_LIGHT_IDLE_MODE_CHANGED`. Only sent on API
+    /// level 33 and higher.
+    DeviceLightIdleModeChanged,
+
+    /// Low Power Standby was enabled or disabled, from
+    /// `PowerManager.ACTION_LOW_POWER_STANDBY_ENABLED_CHANGED`. Only sent on
+    /// API level 33 and higher.
+    LowPowerStandbyEnabledChanged,
+
+    /// The battery is low, from `Intent.ACTION_BATTERY_LOW`.
+    BatteryLow,
+
+    /// The battery is no longer low, from `Intent.ACTION_BATTERY_OKAY`.
+    BatteryOkay,
+
+    /// External power was connected, from `Intent.ACTION_POWER_CONNECTED`.
+    PowerConnected,
+
+    /// External power was disconnected, from
+    /// `Intent.ACTION_POWER_DISCONNECTED`.
+    PowerDisconnected,
+
+    /// The device is shutting down, from `Intent.ACTION_SHUTDOWN`.
+    Shutdown,
+}
+
+impl PowerEvent {
+    /// All known events.
+    pub const ALL: &'static [PowerEvent] = &[
+        Self::ScreenOn,
+        Self::ScreenOff,
+        Self::PowerSaveModeChanged,
+        Self::DeviceIdleModeChanged,
+        Self::DeviceLightIdleModeChanged,
+        Self::LowPowerStandbyEnabledChanged,
+        Self::BatteryLow,
+        Self::BatteryOkay,
+        Self::PowerConnected,
+        Self::PowerDisconnected,
+        Self::Shutdown,
+    ];
+
+    /// Get the action of the broadcast intent for this event.
+    pub fn action(self) -> &'static str {
+        match self {
+            Self::ScreenOn => consts::ACTION_SCREEN_ON,
+            Self::ScreenOff => consts::ACTION_SCREEN_OFF,
+            Self::PowerSaveModeChanged => consts::ACTION_POWER_SAVE_MODE_CHANGED,
+            Self::DeviceIdleModeChanged => consts::ACTION_DEVICE_IDLE_MODE_CHANGED,
+            Self::DeviceLightIdleModeChanged => consts::ACTION_DEVICE_LIGHT_IDLE_MODE_CHANGED,
+            Self::LowPowerStandbyEnabledChanged => consts::ACTION_LOW_POWER_STANDBY_ENABLED_CHANGED,
+            Self::BatteryLow => consts::ACTION_BATTERY_LOW,
+            Self::BatteryOkay => consts::ACTION_BATTERY_OKAY,
+            Self::PowerConnected => consts::ACTION_POWER_CONNECTED,
+            Self::PowerDisconnected => consts::ACTION_POWER_DISCONNECTED,
+            Self::Shutdown => consts::ACTION_SHUTDOWN,
+        }
+    }
+
+    /// Get the event for the given broadcast intent action, if it is one of
+    /// the known events.
+    pub fn from_action(action: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|event| event.action() == action)
+    }
+}
+
+/// Subscribe to the given events.
+///
+/// This registers a broadcast receiver for the events' intent actions, which
+/// is unregistered again when the returned subscription is dropped. Events
+/// can be received by blocking on the subscription, or, with the `stream`
+/// feature, by using it as a [`Stream`][futures_core::Stream].
+///
+/// Since broadcast receivers can only be implemented in Java, this uses the
+/// `rs.android_wakelock.IntentReceiver` class embedded in the crate. On
+/// devices older than API level 26 it must be included in the app from the
+/// crate's `java` directory instead; otherwise an error of kind
+/// [`ErrorKind::Unsupported`][crate::ErrorKind::Unsupported] is returned.
+/// Broadcasts are delivered on the app's main thread, so its looper must be
+/// running for events to arrive.
+pub fn subscribe(events: &[PowerEvent]) -> Result<EventSubscription> {
+    let actions = events
+        .iter()
+        .map(|event| event.action())
+        .collect::<Vec<_>>();
+
+    subscribe_with(&actions, intent_event)
+}
+
+/// Subscribe to battery saver being turned on or off.
+///
+/// The subscription yields whether power save mode is on after each change,
+/// as returned by [`power::is_power_save_mode`][crate::power::is_power_save_mode],
+/// so that optional work and the wake locks held for it can be stopped as soon
+/// as battery saver is turned on. See [`subscribe`] for details.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::events;
+///
+/// let wake_lock = android_wakelock::partial("myapp:prefetch")?;
+/// let power_save_mode = events::power_save_mode()?;
+/// let mut guard = Some(wake_lock.acquire()?);
+///
+/// for enabled in power_save_mode.iter() {
+///     if enabled {
+///         // Prefetching is optional, so stop it while battery saver is on.
+///         guard = None;
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn power_save_mode() -> Result<EventSubscription<bool>> {
+    subscribe_with(&[consts::ACTION_POWER_SAVE_MODE_CHANGED], |env, _| {
+        power::power_save_mode(env).map(Some)
+    })
+}
+
+/// A change of the device's idle mode, also known as Doze, yielded by
+/// [`device_idle_mode`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IdleModeEvent {
+    /// The device entered idle mode. Wake locks held by apps have no effect
+    /// until it exits again.
+    Entered,
+
+    /// The device exited idle mode, either for a maintenance window or because
+    /// it is being used again.
+    Exited,
+}
+
+/// Subscribe to the device entering and exiting idle mode, also known as
+/// Doze.
+///
+/// This is notified by `PowerManager.ACTION_DEVICE_IDLE_MODE_CHANGED`, and
+/// yields whether the device entered or exited idle mode according to
+/// [`power::is_device_idle_mode`]. Use
+/// [`DozeMonitor`][crate::doze::DozeMonitor] to tell maintenance windows apart
+/// from the device becoming active. See [`subscribe`] for details.
+///
+/// Doze was added in API level 23; on older devices an error of kind
+/// [`ErrorKind::Unsupported`] is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::events::{self, IdleModeEvent};
+///
+/// let device_idle_mode = events::device_idle_mode()?;
+///
+/// for event in device_idle_mode.iter() {
+///     if event == IdleModeEvent::Exited {
+///         // Run deferred work while wake locks have effect again...
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn device_idle_mode() -> Result<EventSubscription<IdleModeEvent>> {
+    if sys::api_level()? < 23 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "Doze requires API level 23",
+        ));
+    }
+
+    subscribe_with(&[consts::ACTION_DEVICE_IDLE_MODE_CHANGED], |env, _| {
+        Ok(Some(if power::device_idle_mode(env)? {
+            IdleModeEvent::Entered
+        } else {
+            IdleModeEvent::Exited
+        }))
+    })
+}
+
+/// Subscribe to broadcasts with the given actions, converting received intents
+/// to events with the given function. Intents it returns `None` for are
+/// ignored.
+fn subscribe_with<T, F>(actions: &[&str], f: F) -> Result<EventSubscription<T>>
+where
+    T: fmt::Debug + Send + 'static,
+    F: Fn(&mut JNIEnv<'_>, &JObject<'_>) -> Result<Option<T>> + Send + Sync + 'static,
+{
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let shared = Shared::new();
+
+    let receiver = IntentReceiver::register(&mut env, "power event subscription", actions, {
+        let shared = shared.clone();
+
+        move |env, intent| match f(env, intent) {
+            Ok(Some(event)) => {
+                debug!("received power event {:?}", event);
+                shared.push(event);
+            }
+            Ok(None) => {}
+            Err(e) => hook::report_error("power event subscription", &e),
+        }
+    })?;
+
+    Ok(EventSubscription {
+        shared,
+        registration: Registration::Receiver(receiver),
+    })
+}
+
+/// Subscribe to battery and charging events.
+///
+/// The subscription yields [`PowerEvent::BatteryLow`],
+/// [`PowerEvent::BatteryOkay`], [`PowerEvent::PowerConnected`], and
+/// [`PowerEvent::PowerDisconnected`] events. Unlike [`subscribe`], all
+/// battery subscriptions share a single broadcast receiver with each other and
+/// with wake locks using
+/// [`Builder::auto_release_below_battery`][crate::Builder::auto_release_below_battery],
+/// which is registered while any of them exist.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::events::{self, PowerEvent};
+///
+/// let battery = events::battery()?;
+///
+/// for event in battery.iter() {
+///     match event {
+///         PowerEvent::PowerConnected => {
+///             // Start work that should only run while charging...
+///         }
+///         PowerEvent::PowerDisconnected | PowerEvent::BatteryLow => {
+///             // Stop it again...
+///         }
+///         _ => {}
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn battery() -> Result<EventSubscription> {
+    let shared = Shared::new();
+    let watch = watch_battery({
+        let shared = shared.clone();
+
+        move |event| shared.push(event)
+    })?;
+
+    Ok(EventSubscription {
+        shared,
+        registration: Registration::Battery(watch),
+    })
+}
+
+/// The events delivered by the shared battery receiver.
+const BATTERY_EVENTS: &[PowerEvent] = &[
+    PowerEvent::BatteryLow,
+    PowerEvent::BatteryOkay,
+    PowerEvent::PowerConnected,
+    PowerEvent::PowerDisconnected,
+];
+
+type BatteryCallback = Arc<dyn Fn(PowerEvent) + Send + Sync>;
+
+/// The shared battery receiver and the callbacks it delivers events to.
+struct BatteryHub {
+    receiver: Option<IntentReceiver>,
+    callbacks: Vec<(u64, BatteryCallback)>,
+    next_id: u64,
+}
+
+static BATTERY_HUB: Mutex<BatteryHub> = Mutex::new(BatteryHub {
+    receiver: None,
+    callbacks: Vec::new(),
+    next_id: 0,
+});
+
+fn battery_hub() -> MutexGuard<'static, BatteryHub> {
+    BATTERY_HUB.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Call the given function with every battery event until the returned watch
+/// is dropped, registering the shared battery receiver if necessary.
+pub(crate) fn watch_battery<F>(f: F) -> Result<BatteryWatch>
+where
+    F: Fn(PowerEvent) + Send + Sync + 'static,
+{
+    let mut hub = battery_hub();
+
+    if hub.receiver.is_none() {
+        let vm = java_vm()?;
+        let mut env = vm.attach_current_thread()?;
+        let actions = BATTERY_EVENTS
+            .iter()
+            .map(|event| event.action())
+            .collect::<Vec<_>>();
+
+        hub.receiver = Some(IntentReceiver::register(
+            &mut env,
+            "battery events",
+            &actions,
+            |env, intent| match intent_event(env, intent) {
+                Ok(Some(event)) => {
+                    debug!("received power event {:?}", event);
+
+                    // Do not hold the lock while calling out, so that callbacks
+                    // can subscribe or unsubscribe.
+                    let callbacks = battery_hub()
+                        .callbacks
+                        .iter()
+                        .map(|(_, callback)| callback.clone())
+                        .collect::<Vec<_>>();
+
+                    for callback in callbacks {
+                        callback(event);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => hook::report_error("battery events", &e),
+            },
+        )?);
+    }
+
+    let id = hub.next_id;
+
+    hub.next_id += 1;
+    hub.callbacks.push((id, Arc::new(f)));
+
+    Ok(BatteryWatch { id })
+}
+
+/// A registration with the shared battery receiver, returned by
+/// [`watch_battery`]. The receiver is unregistered when the last watch is
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct BatteryWatch {
+    id: u64,
+}
+
+impl Drop for BatteryWatch {
+    fn drop(&mut self) {
+        let receiver = {
+            let mut hub = battery_hub();
+
+            hub.callbacks.retain(|(id, _)| *id != self.id);
+
+            if hub.callbacks.is_empty() {
+                hub.receiver.take()
+            } else {
+                None
+            }
+        };
+
+        // Unregister outside of the lock, since it calls into Java.
+        drop(receiver);
+    }
+}
+
+/// Subscribe to the screen turning on and off.
+///
+/// The subscription yields [`PowerEvent::ScreenOn`] and
+/// [`PowerEvent::ScreenOff`] events. A common use is to acquire a partial wake
+/// lock when the screen turns off in the middle of a task that should not be
+/// interrupted. See [`subscribe`] for details.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::events::{self, PowerEvent};
+///
+/// let wake_lock = android_wakelock::partial("myapp:export")?;
+/// let screen_state = events::screen_state()?;
+/// let mut guard = None;
+///
+/// for event in screen_state.iter() {
+///     match event {
+///         // Keep the export running while the screen is off.
+///         PowerEvent::ScreenOff => guard = Some(wake_lock.acquire()?),
+///         _ => guard = None,
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn screen_state() -> Result<EventSubscription> {
+    subscribe(&[PowerEvent::ScreenOn, PowerEvent::ScreenOff])
+}
+
+/// Get the event for a received broadcast intent.
+fn intent_event(env: &mut JNIEnv<'_>, intent: &JObject<'_>) -> Result<Option<PowerEvent>> {
+    let action = catch_exceptions(env, |env| {
+        let action = env
+            .call_method(intent, "getAction", "()Ljava/lang/String;", &[])?
+            .l()?;
+
+        java_string(env, action)
+    })?;
+
+    Ok(action.as_deref().and_then(PowerEvent::from_action))
+}
+
+/// A subscription to power events, returned by [`subscribe`] and the other
+/// functions in this module.
+///
+/// Events are queued from the moment the subscription is created until they
+/// are received. With the `stream` feature, this also implements
+/// [`Stream`][futures_core::Stream], which never ends.
+pub struct EventSubscription<T = PowerEvent> {
+    shared: Arc<Shared<T>>,
+    registration: Registration,
+}
+
+/// How a subscription receives its events, unregistered when dropped.
+enum Registration {
+    /// A broadcast receiver of its own.
+    Receiver(IntentReceiver),
+
+    /// The shared battery receiver.
+    Battery(BatteryWatch),
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Receiver(receiver) => receiver.fmt(f),
+            Self::Battery(watch) => watch.fmt(f),
+        }
+    }
+}
+
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    condvar: Condvar,
+}
+
+struct Queue<T> {
+    events: VecDeque<T>,
+
+    /// The task waiting for the next event, if polled as a stream.
+    #[cfg(feature = "stream")]
+    waker: Option<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            queue: Mutex::new(Queue {
+                events: VecDeque::new(),
+                #[cfg(feature = "stream")]
+                waker: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, event: T) {
+        let mut queue = self.lock();
+
+        queue.events.push_back(event);
+        self.condvar.notify_one();
+
+        #[cfg(feature = "stream")]
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> EventSubscription<T> {
+    /// Wait for the next event.
+    pub fn recv(&self) -> T {
+        let mut queue = self.shared.lock();
+
+        loop {
+            if let Some(event) = queue.events.pop_front() {
+                return event;
+            }
+
+            queue = self
+                .shared
+                .condvar
+                .wait(queue)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Wait for the next event for at most the given timeout.
+    ///
+    /// Returns `None` if no event arrived before the timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.shared.lock();
+
+        loop {
+            if let Some(event) = queue.events.pop_front() {
+                return Some(event);
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                return None;
+            }
+
+            queue = self
+                .shared
+                .condvar
+                .wait_timeout(queue, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Get the next event if one has already arrived, without waiting.
+    pub fn try_recv(&self) -> Option<T> {
+        self.shared.lock().events.pop_front()
+    }
+
+    /// Returns an iterator that waits for events.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::repeat_with(|| self.recv())
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<T> futures_core::Stream for EventSubscription<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut queue = self.shared.lock();
+
+        match queue.events.pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for EventSubscription<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventSubscription")
+            .field("registration", &self.registration)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,185 @@
+//! Detecting wake locks released by the system while guards are still alive.
+
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{catch_exceptions, hook, is_held_with, timer, worker, Inner, Result};
+
+/// How often the wake lock is checked while it is held.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// References acquired with a timeout that expire within this duration are not
+/// counted, since the system may already have released them.
+const DEADLINE_SLACK: Duration = Duration::from_secs(1);
+
+/// Callback notified when the system has released the wake lock.
+type Callback = Arc<dyn Fn(&ForceReleased) + Send + Sync>;
+
+/// An event describing a wake lock that was released by the system while it
+/// was still held by guards, passed to the callback set with
+/// [`Builder::detect_forced_release`][crate::Builder::detect_forced_release].
+#[derive(Clone, Debug)]
+pub struct ForceReleased {
+    tag: String,
+    references: usize,
+}
+
+impl ForceReleased {
+    /// Get the tag of the wake lock that was released.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Get the number of references held by guards at the time the wake lock
+    /// was found to be released.
+    pub fn references(&self) -> usize {
+        self.references
+    }
+}
+
+/// Options set with
+/// [`Builder::detect_forced_release`][crate::Builder::detect_forced_release].
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub(crate) callback: Callback,
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config").finish_non_exhaustive()
+    }
+}
+
+/// Checks that the system still holds a wake lock while guards for it exist.
+#[derive(Debug)]
+pub(crate) struct Monitor {
+    config: Config,
+
+    /// Whether a check is currently scheduled.
+    running: AtomicBool,
+}
+
+impl Monitor {
+    pub(crate) fn new(config: Config) -> Self {
+        Self {
+            config,
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Start checking the wake lock periodically, unless already doing so.
+    pub(crate) fn start(inner: &Arc<Inner>) {
+        if let Some(monitor) = &inner.forced_release {
+            if !monitor.running.swap(true, Ordering::SeqCst) {
+                schedule(Arc::downgrade(inner));
+            }
+        }
+    }
+}
+
+fn schedule(inner: Weak<Inner>) {
+    timer::schedule(Instant::now() + POLL_INTERVAL, move || {
+        // Check on the worker thread, since it involves JNI calls.
+        worker::execute(move || {
+            if let Some(inner) = inner.upgrade() {
+                poll(&inner);
+            }
+        });
+    });
+}
+
+/// Check whether the wake lock was released by the system, otherwise check
+/// again later. Stops once the wake lock is no longer held by any guard.
+fn poll(inner: &Arc<Inner>) {
+    let Some(monitor) = &inner.forced_release else {
+        return;
+    };
+
+    let references = match inner.attach_worker().and_then(|_| check(inner, monitor)) {
+        Ok(Some(0)) => return,
+        Ok(Some(references)) => references,
+        Ok(None) => return schedule(Arc::downgrade(inner)),
+        Err(e) => {
+            hook::report_error(&inner.tag, &e);
+            return schedule(Arc::downgrade(inner));
+        }
+    };
+
+    warn!(
+        "wake lock \"{}\" was released by the system while {} references were held",
+        inner.tag, references
+    );
+
+    #[cfg(feature = "audit")]
+    inner.guards.warn_backtraces(&inner.tag);
+
+    let event = ForceReleased {
+        tag: inner.tag.clone(),
+        references,
+    };
+
+    // The callback runs on the worker thread, whose other jobs must not be
+    // affected if it panics.
+    if let Err(payload) =
+        panic::catch_unwind(AssertUnwindSafe(|| (monitor.config.callback)(&event)))
+    {
+        hook::report_panic(&inner.tag, "the forced release callback", payload);
+    }
+}
+
+/// Compare the references held by guards with whether the wake lock is
+/// actually held.
+///
+/// Returns the number of references the system released, which are then
+/// treated as revoked so that guards holding them no longer release anything,
+/// zero if no references are held anymore, or `None` if the wake lock is still
+/// held as expected. The monitor is stopped unless `None` is returned.
+fn check(inner: &Inner, monitor: &Monitor) -> Result<Option<usize>> {
+    // Runs in its own local frame, so that polling from the worker thread does
+    // not accumulate local references.
+    inner.with_env(|env| {
+        // Checked while holding the lock, so that a concurrent acquire either
+        // is seen here or sees the monitor as stopped and restarts it.
+        let mut object = inner.object();
+        let now = Instant::now();
+
+        object.deadlines.retain(|deadline| *deadline > now);
+
+        if object.held == 0 && object.deadlines.is_empty() {
+            monitor.running.store(false, Ordering::SeqCst);
+            return Ok(Some(0));
+        }
+
+        let references = object.held
+            + object
+                .deadlines
+                .iter()
+                .filter(|deadline| **deadline > now + DEADLINE_SLACK)
+                .count();
+
+        if references == 0 {
+            return Ok(None);
+        }
+
+        let wake_lock = object.wake_lock.java()?;
+        let is_held = catch_exceptions(env, |env| is_held_with(env, wake_lock))?;
+
+        if is_held {
+            return Ok(None);
+        }
+
+        object.revoked += object.held;
+        object.held = 0;
+        object.deadlines.clear();
+        monitor.running.store(false, Ordering::SeqCst);
+
+        Ok(Some(references))
+    })
+}
@@ -0,0 +1,98 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{worker::Task, Guard, Result, WakeLock};
+
+/// Extension trait for keeping the device awake while a future runs.
+///
+/// This trait is implemented for all futures and does not depend on any
+/// particular async runtime.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::WakeLockFutureExt;
+///
+/// # async fn sync_data() -> u32 { 0 }
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let wake_lock = android_wakelock::partial("myapp:sync")?;
+///
+/// let synced = sync_data().with_wake_lock(&wake_lock).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait WakeLockFutureExt: Future + Sized {
+    /// Hold the given wake lock while this future runs.
+    ///
+    /// The wake lock is acquired on the crate's background thread before the
+    /// future is first polled, and released once the future completes or when
+    /// the returned future is dropped. The returned future resolves to the
+    /// output of this future, or an error if the wake lock could not be
+    /// acquired.
+    #[track_caller]
+    fn with_wake_lock(self, wake_lock: &WakeLock) -> WithWakeLock<Self> {
+        WithWakeLock {
+            future: self,
+            acquire: Some(wake_lock.acquire_task()),
+            guard: None,
+        }
+    }
+}
+
+impl<F: Future> WakeLockFutureExt for F {}
+
+/// Future returned by [`WakeLockFutureExt::with_wake_lock`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WithWakeLock<F> {
+    future: F,
+
+    /// The pending acquire, if the wake lock has not been acquired yet.
+    acquire: Option<Task<Result<Guard>>>,
+
+    /// Guard held while the inner future is running.
+    guard: Option<Guard>,
+}
+
+impl<F: Future> Future for WithWakeLock<F> {
+    type Output = Result<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: The inner future is never moved out of `self`, and it is only
+        // ever accessed through a pinned reference. All other fields are
+        // `Unpin`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if let Some(acquire) = this.acquire.as_mut() {
+            let result = match Pin::new(acquire).poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            this.acquire = None;
+            this.guard = Some(result?);
+        }
+
+        // SAFETY: See above.
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        match future.poll(cx) {
+            Poll::Ready(output) => {
+                this.guard = None;
+                Poll::Ready(Ok(output))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F> fmt::Debug for WithWakeLock<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WithWakeLock")
+            .field("guard", &self.guard)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use crate::{wifi, wifi::WifiLock, Guard, Result, WakeLock};
+
+/// A set of locks that are acquired and released together.
+///
+/// Work often needs several locks at once, such as a partial wake lock to keep
+/// the CPU running and a Wi-Fi lock to keep the network up. Acquiring a group
+/// acquires each of its members in the order they were added, and if any of
+/// them fails, releases the ones already acquired again before returning the
+/// error, so that no lock is left held by accident. The returned
+/// [`GroupGuard`] releases all of them in the reverse order.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::{wifi::WifiLock, WakeLockGroup};
+///
+/// let group = WakeLockGroup::new()
+///     .with(android_wakelock::partial("myapp:upload")?)
+///     .with(WifiLock::builder("myapp:upload").build()?);
+///
+/// let _guard = group.acquire()?;
+/// // Upload while both the CPU and Wi-Fi are kept awake...
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct WakeLockGroup {
+    members: Vec<GroupMember>,
+}
+
+/// A lock that can be added to a [`WakeLockGroup`].
+///
+/// This is created from a [`WakeLock`] or a [`WifiLock`], either owned or in
+/// an `Arc` to share it with other code.
+#[derive(Clone, Debug)]
+pub struct GroupMember(Member);
+
+#[derive(Clone, Debug)]
+enum Member {
+    WakeLock(Arc<WakeLock>),
+    WifiLock(Arc<WifiLock>),
+}
+
+impl From<WakeLock> for GroupMember {
+    fn from(wake_lock: WakeLock) -> Self {
+        Arc::new(wake_lock).into()
+    }
+}
+
+impl From<Arc<WakeLock>> for GroupMember {
+    fn from(wake_lock: Arc<WakeLock>) -> Self {
+        Self(Member::WakeLock(wake_lock))
+    }
+}
+
+impl From<WifiLock> for GroupMember {
+    fn from(wifi_lock: WifiLock) -> Self {
+        Arc::new(wifi_lock).into()
+    }
+}
+
+impl From<Arc<WifiLock>> for GroupMember {
+    fn from(wifi_lock: Arc<WifiLock>) -> Self {
+        Self(Member::WifiLock(wifi_lock))
+    }
+}
+
+impl WakeLockGroup {
+    /// Create an empty group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a lock to the group.
+    pub fn with<M: Into<GroupMember>>(mut self, member: M) -> Self {
+        self.members.push(member.into());
+        self
+    }
+
+    /// Get the number of locks in the group.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns true if the group has no locks.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Acquire every lock in the group, in the order they were added.
+    ///
+    /// If acquiring any of them fails, the ones already acquired are released
+    /// again and the error is returned.
+    #[track_caller]
+    pub fn acquire(&self) -> Result<GroupGuard> {
+        let mut guard = GroupGuard {
+            guards: Vec::with_capacity(self.members.len()),
+        };
+
+        for member in &self.members {
+            // On error, dropping the partially filled guard rolls back the
+            // locks acquired so far.
+            guard.guards.push(match &member.0 {
+                Member::WakeLock(wake_lock) => MemberGuard::WakeLock(wake_lock.acquire()?),
+                Member::WifiLock(wifi_lock) => MemberGuard::WifiLock(wifi_lock.acquire()?),
+            });
+        }
+
+        Ok(guard)
+    }
+}
+
+/// A guard for an acquired [`WakeLockGroup`], releasing all of its locks when
+/// dropped.
+#[derive(Debug)]
+pub struct GroupGuard {
+    guards: Vec<MemberGuard>,
+}
+
+#[derive(Debug)]
+enum MemberGuard {
+    WakeLock(Guard),
+    WifiLock(wifi::Guard),
+}
+
+impl MemberGuard {
+    fn release(self) -> Result<()> {
+        match self {
+            Self::WakeLock(guard) => guard.release(),
+            Self::WifiLock(guard) => guard.release(),
+        }
+    }
+}
+
+impl GroupGuard {
+    /// Release all of the locks in the reverse order they were acquired,
+    /// returning the first error encountered. All locks are released even if
+    /// releasing one fails.
+    pub fn release(mut self) -> Result<()> {
+        let mut result = Ok(());
+
+        while let Some(guard) = self.guards.pop() {
+            if let Err(e) = guard.release() {
+                result = result.and(Err(e));
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        while let Some(guard) = self.guards.pop() {
+            drop(guard);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Logging acquire and release events in a stable format, for comparing with
+//! the battery statistics recorded by the system.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::ReleaseReason;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Log every acquire and release of a wake lock in a stable, machine-parsable
+/// format.
+///
+/// This is meant for chasing discrepancies between what the app thinks it
+/// holds and what the system recorded, by comparing the events with the
+/// output of `adb shell dumpsys batterystats --history` or a Battery
+/// Historian report. Disabled by default.
+///
+/// Events are logged at info level, to the target set with
+/// [`set_log_target`][crate::set_log_target] or `android_wakelock::history`
+/// by default, with one event per line in the following format:
+///
+/// ```text
+/// +wake_lock time=1700000000123 tag="myapp:sync" guard=7 timeout=60000
+/// -wake_lock time=1700000004567 tag="myapp:sync" guard=7 duration=4444 reason=released
+/// ```
+///
+/// `+wake_lock` lines are logged when a guard acquires the wake lock, and
+/// `-wake_lock` lines when it is released, like in the system's history.
+/// `guard` is the [ID][crate::Guard::id] of the guard, which matches up the two
+/// lines for the same guard. `time` is the wall clock time in milliseconds
+/// since the Unix epoch, and `duration` and `timeout` are in milliseconds.
+/// `timeout` is omitted for guards acquired without one. `reason` is one of
+/// `released`, `dropped`, or `timed_out`, for guards whose reference was
+/// released by the system once their timeout expired; for these, `time` is when
+/// the timeout expired. Tags are quoted, with any quotes and backslashes in
+/// them escaped with a backslash.
+///
+/// Requires the `log` feature, which is enabled by default.
+///
+/// # Examples
+///
+/// ```
+/// android_wakelock::set_history_log(true);
+/// ```
+pub fn set_history_log(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Log that the guard with the given ID acquired the wake lock with the given
+/// tag.
+pub(crate) fn acquired(tag: &str, guard: u64, timeout: Option<Duration>) {
+    if cfg!(feature = "no-instrumentation") || !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    match timeout {
+        Some(timeout) => info!(
+            "+wake_lock time={} tag={} guard={} timeout={}",
+            unix_millis(Instant::now()),
+            Quoted(tag),
+            guard,
+            timeout.as_millis()
+        ),
+        None => info!(
+            "+wake_lock time={} tag={} guard={}",
+            unix_millis(Instant::now()),
+            Quoted(tag),
+            guard
+        ),
+    }
+}
+
+/// Log that the guard with the given ID released the wake lock with the given
+/// tag at the given time, after holding it for the given duration.
+pub(crate) fn released(tag: &str, guard: u64, held: Duration, reason: ReleaseReason, at: Instant) {
+    if cfg!(feature = "no-instrumentation") || !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let reason = match reason {
+        ReleaseReason::Released => "released",
+        ReleaseReason::Dropped => "dropped",
+        ReleaseReason::TimedOut => "timed_out",
+    };
+
+    info!(
+        "-wake_lock time={} tag={} guard={} duration={} reason={}",
+        unix_millis(at),
+        Quoted(tag),
+        guard,
+        held.as_millis(),
+        reason
+    );
+}
+
+/// Convert a point in time to milliseconds since the Unix epoch.
+fn unix_millis(at: Instant) -> u128 {
+    let now = SystemTime::now();
+    let time = now
+        .checked_sub(Instant::now().saturating_duration_since(at))
+        .unwrap_or(now);
+
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+/// Formats a tag in quotes, escaping quotes and backslashes.
+struct Quoted<'a>(&'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+
+        for c in self.0.chars() {
+            if c == '"' || c == '\\' {
+                f.write_str("\\")?;
+            }
+
+            write!(f, "{}", c)?;
+        }
+
+        f.write_str("\"")
+    }
+}
@@ -0,0 +1,65 @@
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use crate::{Error, ErrorKind};
+
+type Hook = Arc<dyn Fn(&str, &Error) + Send + Sync>;
+
+static ERROR_HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+/// Set a process-wide hook to be called with errors that cannot be returned to
+/// the caller.
+///
+/// Some operations, such as releasing a wake lock on the crate's background
+/// thread when a [`Guard`][crate::Guard] is dropped, have no caller to return
+/// errors to. Such errors are passed to this hook along with the tag of the
+/// wake lock involved. If no hook is set, these errors are logged instead.
+///
+/// Panics in callbacks and jobs run on the crate's background threads are
+/// caught and reported to this hook as well, as errors of kind
+/// [`ErrorKind::Other`]. The tag is empty if no single wake lock is involved.
+///
+/// Setting a hook replaces any previously set hook.
+///
+/// # Examples
+///
+/// ```
+/// android_wakelock::set_error_hook(|tag, error| {
+///     eprintln!("wake lock \"{}\" failed: {}", tag, error);
+/// });
+/// ```
+pub fn set_error_hook<F>(hook: F)
+where
+    F: Fn(&str, &Error) + Send + Sync + 'static,
+{
+    *ERROR_HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(hook));
+}
+
+/// Report an error that cannot be returned to a caller.
+pub(crate) fn report_error(tag: &str, error: &Error) {
+    // Do not hold the lock while calling out, so that the hook may be replaced
+    // from within the hook.
+    let hook = ERROR_HOOK.read().unwrap_or_else(|e| e.into_inner()).clone();
+
+    match hook {
+        Some(hook) => hook(tag, error),
+        None => error!("error in wake lock \"{}\": {}", tag, error),
+    }
+}
+
+/// Report a panic caught while running the given code on one of the crate's
+/// background threads.
+pub(crate) fn report_panic(tag: &str, what: &str, payload: Box<dyn Any + Send>) {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+
+    report_error(
+        tag,
+        &Error::new(ErrorKind::Other, format!("{} panicked: {}", what, message)),
+    );
+}
@@ -0,0 +1,73 @@
+//! Reporting wake lock holds through the `metrics` crate.
+
+use std::{sync::Once, time::Duration};
+
+use crate::ReleaseReason;
+
+const ACQUIRES: &str = "android_wakelock_acquires_total";
+const RELEASES: &str = "android_wakelock_releases_total";
+const HELD: &str = "android_wakelock_held";
+const HELD_SECONDS: &str = "android_wakelock_held_seconds_total";
+
+/// Describe the metrics to the installed recorder the first time any of them
+/// is emitted.
+fn describe() {
+    static DESCRIBED: Once = Once::new();
+
+    DESCRIBED.call_once(|| {
+        metrics::describe_counter!(
+            ACQUIRES,
+            metrics::Unit::Count,
+            "Number of times a guard acquired the wake lock"
+        );
+        metrics::describe_counter!(
+            RELEASES,
+            metrics::Unit::Count,
+            "Number of times a guard released the wake lock"
+        );
+        metrics::describe_gauge!(
+            HELD,
+            metrics::Unit::Count,
+            "Number of guards currently holding the wake lock"
+        );
+        metrics::describe_gauge!(
+            HELD_SECONDS,
+            metrics::Unit::Seconds,
+            "Total time guards have held the wake lock"
+        );
+    });
+}
+
+/// Record that a guard acquired the wake lock with the given tag.
+pub(crate) fn acquired(tag: &str) {
+    if cfg!(feature = "no-instrumentation") {
+        return;
+    }
+
+    describe();
+
+    metrics::counter!(ACQUIRES, "tag" => tag.to_owned()).increment(1);
+    metrics::gauge!(HELD, "tag" => tag.to_owned()).increment(1.0);
+}
+
+/// Record that a guard released the wake lock with the given tag after
+/// holding it for the given duration.
+pub(crate) fn released(tag: &str, held: Duration, reason: ReleaseReason) {
+    if cfg!(feature = "no-instrumentation") {
+        return;
+    }
+
+    describe();
+
+    let reason = match reason {
+        ReleaseReason::Released => "released",
+        ReleaseReason::Dropped => "dropped",
+        ReleaseReason::TimedOut => "timed_out",
+    };
+
+    metrics::counter!(RELEASES, "tag" => tag.to_owned(), "reason" => reason).increment(1);
+    metrics::gauge!(HELD, "tag" => tag.to_owned()).decrement(1.0);
+    // Counters only support whole numbers, so accumulate fractional seconds
+    // in a gauge that only ever increases.
+    metrics::gauge!(HELD_SECONDS, "tag" => tag.to_owned()).increment(held.as_secs_f64());
+}
@@ -0,0 +1,164 @@
+use crate::{
+    screen::{self, ScreenOnGuard},
+    wifi::{self, WifiLock, WifiMode},
+    ApiFallback, Guard, Result, WakeLock,
+};
+
+/// Keeps a combination of the CPU, Wi-Fi, and the screen awake.
+///
+/// Most code does not care about which Android classes are involved in keeping
+/// something working, only which parts of the device need to stay awake. This
+/// composes the appropriate underlying locks: a [partial][crate::Level::Partial]
+/// [`WakeLock`] for the CPU, a [`WifiLock`] for Wi-Fi, and
+/// [`screen::keep_screen_on`] for the screen. Acquiring returns a single
+/// [`KeepAwakeGuard`] that releases all of them.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::KeepAwake;
+///
+/// let keep_awake = KeepAwake::builder("myapp:call")
+///     .cpu()
+///     .wifi_low_latency()
+///     .build()?;
+///
+/// let _guard = keep_awake.acquire()?;
+///
+/// // The CPU and Wi-Fi are kept awake until the guard is dropped.
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct KeepAwake {
+    cpu: Option<WakeLock>,
+    wifi: Option<WifiLock>,
+    screen: bool,
+}
+
+impl KeepAwake {
+    /// Create a new builder for keeping parts of the device awake. The tag is
+    /// used for all of the underlying locks.
+    pub fn builder<T: Into<String>>(tag: T) -> KeepAwakeBuilder {
+        KeepAwakeBuilder {
+            tag: tag.into(),
+            cpu: false,
+            wifi: None,
+            screen: false,
+        }
+    }
+
+    /// Keep the configured parts of the device awake until the returned guard
+    /// is dropped.
+    ///
+    /// The underlying locks are acquired in turn, and if any of them fails,
+    /// those already acquired are released again before returning the error.
+    /// If the screen is to be kept on, this must be called on the UI thread.
+    #[track_caller]
+    pub fn acquire(&self) -> Result<KeepAwakeGuard> {
+        // Not mapped with a function, so that the caller is tracked.
+        let cpu = match &self.cpu {
+            Some(cpu) => Some(cpu.acquire()?),
+            None => None,
+        };
+        let wifi = self.wifi.as_ref().map(WifiLock::acquire).transpose()?;
+        let screen = if self.screen {
+            Some(screen::keep_screen_on(&screen::current_activity())?)
+        } else {
+            None
+        };
+
+        Ok(KeepAwakeGuard { screen, wifi, cpu })
+    }
+}
+
+/// A builder for configuring a [`KeepAwake`].
+#[derive(Clone, Debug)]
+pub struct KeepAwakeBuilder {
+    tag: String,
+    cpu: bool,
+    wifi: Option<WifiMode>,
+    screen: bool,
+}
+
+impl KeepAwakeBuilder {
+    /// Keep the CPU running, using a partial wake lock.
+    pub fn cpu(mut self) -> Self {
+        self.cpu = true;
+        self
+    }
+
+    /// Keep Wi-Fi awake, using a [`WifiMode::Full`] Wi-Fi lock.
+    pub fn wifi(mut self) -> Self {
+        self.wifi = Some(WifiMode::Full);
+        self
+    }
+
+    /// Keep Wi-Fi awake with the lowest latency, using a
+    /// [`WifiMode::LowLatency`] Wi-Fi lock. On devices older than API level
+    /// 29, [`WifiMode::HighPerf`] is used instead.
+    pub fn wifi_low_latency(mut self) -> Self {
+        self.wifi = Some(WifiMode::LowLatency);
+        self
+    }
+
+    /// Keep the screen on, using `FLAG_KEEP_SCREEN_ON` on the window of the
+    /// activity returned by [`screen::current_activity`].
+    ///
+    /// Window flags can only be changed on the UI thread, so the
+    /// [`KeepAwake`] must then be acquired and its guards dropped on the UI
+    /// thread. See [`screen::keep_screen_on`].
+    pub fn screen(mut self) -> Self {
+        self.screen = true;
+        self
+    }
+
+    /// Create the underlying locks.
+    pub fn build(&self) -> Result<KeepAwake> {
+        let cpu = if self.cpu {
+            Some(WakeLock::builder(self.tag.clone()).build()?)
+        } else {
+            None
+        };
+
+        let wifi = self
+            .wifi
+            .map(|mode| {
+                WifiLock::builder(self.tag.clone())
+                    .mode(mode)
+                    .api_fallback(ApiFallback::Degrade)
+                    .build()
+            })
+            .transpose()?;
+
+        Ok(KeepAwake {
+            cpu,
+            wifi,
+            screen: self.screen,
+        })
+    }
+}
+
+/// A guard for an acquired [`KeepAwake`], releasing all of its underlying
+/// locks when dropped.
+#[derive(Debug)]
+pub struct KeepAwakeGuard {
+    // Fields are dropped in order, releasing in the reverse order of
+    // acquiring.
+    screen: Option<ScreenOnGuard>,
+    wifi: Option<wifi::Guard>,
+    cpu: Option<Guard>,
+}
+
+impl KeepAwakeGuard {
+    /// Release all of the underlying locks, returning the first error
+    /// encountered. All locks are released even if releasing one fails.
+    pub fn release(mut self) -> Result<()> {
+        let results = [
+            self.screen.take().map(ScreenOnGuard::clear),
+            self.wifi.take().map(wifi::Guard::release),
+            self.cpu.take().map(Guard::release),
+        ];
+
+        results.into_iter().flatten().collect()
+    }
+}
@@ -14,6 +14,14 @@
 //! This library should work with all Android API levels. It cannot be used on any
 //! other operating system, of course.
 //!
+//! Most of this library only requires JNI. Features that receive callbacks from
+//! the platform, such as [`thermal::subscribe`], [`run_on_main_looper`] and
+//! the [`events`] module, need small Java support classes that implement the
+//! platform's listener interfaces and callback classes. These are embedded in the crate and loaded
+//! from memory on API level 26 and higher, so no Java code is needed in the
+//! app. To use these features on older devices, compile the sources in the
+//! `java` directory of this crate into the app.
+//!
 //! # Creating wake locks
 //!
 //! The simplest way to create a wake lock is to use the [`partial`] function,
@@ -82,6 +90,19 @@
 //!
 //! # Ok::<(), Box<dyn std::error::Error>>(())
 //! ```
+//!
+//! # Instrumentation
+//!
+//! By default, the crate logs what it does through the `log` crate, collects
+//! [statistics][stats] about every wake lock, and notifies
+//! [observers][WakeLockObserver] as wake locks are acquired and released.
+//! Optional features add tracing spans, metrics, and system trace sections.
+//!
+//! For size or performance sensitive release builds, the `no-instrumentation`
+//! feature compiles all of this out, leaving only the calls to the platform.
+//! The APIs for configuring instrumentation remain available, but have no
+//! effect: observers are never notified, statistics stay empty, and nothing is
+//! logged.
 
 #![warn(
     future_incompatible,
@@ -92,20 +113,109 @@
     clippy::all
 )]
 
-use std::fmt;
+// Must come first, so that the log macros are available to all other modules.
+#[macro_use]
+mod logging;
+
+mod adaptive;
+#[cfg(feature = "atrace")]
+mod atrace;
+pub mod battery;
+mod budget;
+mod callback;
+mod capabilities;
+pub mod channel;
+pub mod consts;
+pub mod display;
+pub mod doze;
+#[cfg(feature = "dump")]
+mod dump;
+mod error;
+pub mod events;
+mod forced_release;
+mod future;
+mod group;
+#[cfg(feature = "log")]
+mod history;
+mod hook;
+#[cfg(feature = "metrics")]
+mod instrument;
+mod keep_awake;
+mod looper;
+mod low_battery;
+mod methods;
+mod observer;
+pub mod policies;
+pub mod power;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod registry;
+mod retry;
+pub mod runtime;
+pub mod screen;
+pub mod stats;
+#[cfg(feature = "stream")]
+mod stream;
+pub mod sys;
+#[cfg(feature = "sysfs-backend")]
+mod sysfs;
+mod tag;
+pub mod thermal;
+mod timer;
+mod tracker;
+pub mod wifi;
+mod work_source;
+mod worker;
+
+use std::{
+    fmt,
+    future::Future,
+    panic::Location,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc, Mutex, MutexGuard, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    budget::{Budget, Permit},
+    low_battery::Monitor,
+    observer::Observer,
+    stats::{ActiveGuard, ActiveGuards, JniCall},
+};
+
+#[cfg(feature = "dump")]
+pub use crate::dump::{dump, dump_to_logcat};
+pub use crate::runtime::ReleaseHandle;
+#[cfg(feature = "stream")]
+pub use crate::stream::{WakeLockStreamExt, WithWakeLockStream};
+pub use crate::{
+    adaptive::{AdaptiveGuard, AdaptiveWakeLock},
+    capabilities::{capabilities, PowerCapabilities},
+    channel::awake_channel,
+    error::{Error, ErrorKind},
+    forced_release::ForceReleased,
+    future::{WakeLockFutureExt, WithWakeLock},
+    group::{GroupGuard, GroupMember, WakeLockGroup},
+    hook::set_error_hook,
+    keep_awake::{KeepAwake, KeepAwakeBuilder, KeepAwakeGuard},
+    looper::{run_on_main_looper, MainLooperTask},
+    observer::{set_observer, ReleaseReason, WakeLockObserver},
+    retry::RetryPolicy,
+    stats::stats_for_tag,
+    tracker::{TaskTracker, Tracked},
+    work_source::WorkSource,
+};
+#[cfg(feature = "log")]
+pub use crate::{history::set_history_log, logging::set_log_target};
 
 use jni::{
     objects::{GlobalRef, JObject, JValue},
-    AttachGuard, JavaVM,
+    JNIEnv, JavaVM,
 };
 
-const ACQUIRE_CAUSES_WAKEUP: i32 = 0x10000000;
-const ON_AFTER_RELEASE: i32 = 0x20000000;
-
-/// An error returned by the wake lock API. A variety of errors can occur when
-/// calling Android APIs, such as JNI errors, or exceptions actually thrown by the
-/// API itself.
-pub type Error = Box<dyn std::error::Error>;
+use crate::consts::{ACQUIRE_CAUSES_WAKEUP, ON_AFTER_RELEASE, RELEASE_FLAG_WAIT_FOR_NO_PROXIMITY};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -187,6 +297,128 @@ pub enum Level {
     /// permission.
     #[deprecated]
     ScreenDim = 0x00000006,
+
+    /// Turns the screen off when the proximity sensor activates.
+    ///
+    /// If the proximity sensor detects that an object is nearby, the screen
+    /// turns off immediately. Shortly after the object moves away, the screen
+    /// turns on again. This is typically used by calling apps to turn the
+    /// screen off while the device is held against the user's ear.
+    ///
+    /// A proximity wake lock does not prevent the device from falling asleep
+    /// unlike [`Level::Full`], [`Level::ScreenBright`] and
+    /// [`Level::ScreenDim`]. If there is no user activity and no other wake
+    /// locks are held, then the device will fall asleep (and lock) as usual.
+    /// However, the device will not fall asleep while the screen has been
+    /// turned off by the proximity sensor because it effectively counts as
+    /// ongoing user activity.
+    ///
+    /// Not all devices have a proximity sensor. This level was added in API
+    /// level 21.
+    ProximityScreenOff = 0x00000020,
+}
+
+impl Level {
+    /// Returns true if this level is deprecated by the platform.
+    ///
+    /// Building a wake lock with a deprecated level logs a warning once per
+    /// level on devices running an API level where it is deprecated. Unlike
+    /// the `#[deprecated]` attribute, this also covers levels chosen at
+    /// runtime, such as from configuration.
+    pub fn is_deprecated(self) -> bool {
+        self.deprecated_since_api().is_some()
+    }
+
+    /// Get the API level this level was deprecated in, if it is deprecated.
+    #[allow(deprecated)]
+    pub fn deprecated_since_api(self) -> Option<i32> {
+        match self {
+            Self::Full | Self::ScreenDim => Some(17),
+            Self::ScreenBright => Some(15),
+            Self::Partial | Self::ProximityScreenOff => None,
+        }
+    }
+
+    /// Log a warning the first time a deprecated level is used on a device
+    /// where it is deprecated.
+    #[allow(deprecated)]
+    fn warn_if_deprecated(self, api_level: i32) {
+        static WARNED: AtomicU8 = AtomicU8::new(0);
+
+        let bit = match self {
+            Self::Full => 1,
+            Self::ScreenBright => 2,
+            Self::ScreenDim => 4,
+            Self::Partial | Self::ProximityScreenOff => return,
+        };
+
+        if self
+            .deprecated_since_api()
+            .is_some_and(|since| api_level >= since)
+            && WARNED.fetch_or(bit, Ordering::Relaxed) & bit == 0
+        {
+            warn!(
+                "wake lock level {:?} is deprecated since API level {}; use \
+                 `screen::keep_screen_on` (FLAG_KEEP_SCREEN_ON) to keep the screen on instead",
+                self,
+                self.deprecated_since_api().unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// What to do when a wake lock is configured with an option that is not
+/// supported, or that behaves differently, on the device's API level.
+///
+/// See [`Builder::api_fallback`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ApiFallback {
+    /// Fail to build the wake lock with an error of kind
+    /// [`ErrorKind::Unsupported`] explaining the problem. This is the default.
+    #[default]
+    Error,
+
+    /// Log a warning and build the wake lock without the unsupported option,
+    /// falling back to a [partial][Level::Partial] wake lock if the level
+    /// itself is not supported.
+    Degrade,
+}
+
+/// The mechanism used to hold a wake lock.
+///
+/// See [`Builder::backend`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Use `PowerManager.WakeLock` through JNI. This is the default.
+    #[default]
+    Framework,
+
+    /// Write to the kernel's `/sys/power/wake_lock` and
+    /// `/sys/power/wake_unlock` files directly, without involving the JVM.
+    ///
+    /// This is only usable by system components with write access to these
+    /// files, such as native AOSP daemons, and only supports partial wake
+    /// locks. Requires the `sysfs-backend` feature.
+    #[cfg(feature = "sysfs-backend")]
+    Sysfs,
+}
+
+/// What to do when acquiring a wake lock while battery saver is on.
+///
+/// See [`Builder::power_save_fallback`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PowerSaveFallback {
+    /// Fail to acquire the wake lock with an error of kind
+    /// [`ErrorKind::PowerSaveActive`]. This is the default.
+    #[default]
+    Error,
+
+    /// Return a guard without acquiring the wake lock, so that the work it
+    /// protects still runs but does not keep the device awake.
+    NoOp,
 }
 
 /// A builder for configuring and creating a wake lock.
@@ -196,9 +428,51 @@ pub struct Builder {
     level: Level,
     acquire_causes_wakeup: bool,
     on_after_release: bool,
+    extra_flags: i32,
+    release_in_background: bool,
+    hold_budget: Option<usize>,
+    retry: Option<RetryPolicy>,
+    default_timeout: Option<Duration>,
+    low_battery: Option<low_battery::Config>,
+    forced_release: Option<forced_release::Config>,
+    respect_power_save_mode: bool,
+    power_save_fallback: PowerSaveFallback,
+    backend: Backend,
+    observer: Option<Observer>,
+    work_source: Option<WorkSource>,
+    validate_tag: bool,
+    auto_prefix: bool,
+    api_fallback: ApiFallback,
 }
 
 impl Builder {
+    /// Create a builder with the same configuration as an existing wake lock.
+    ///
+    /// This is useful for creating a new wake lock that differs from an
+    /// existing one in only a few options, without having to keep track of how
+    /// the original was configured. Changes made to the wake lock after it was
+    /// built, such as with [`Guard::update_work_source`], are not included.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::{Builder, Level, WakeLock};
+    ///
+    /// let wake_lock = WakeLock::builder("myapp:mytag")
+    ///     .level(Level::ProximityScreenOff)
+    ///     .release_in_background(true)
+    ///     .build()?;
+    ///
+    /// // Same options, but a different level.
+    /// let fallback = Builder::from_lock(&wake_lock)
+    ///     .level(Level::Partial)
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_lock(wake_lock: &WakeLock) -> Self {
+        wake_lock.inner.object().config.clone()
+    }
+
     /// Set the wake lock level.
     ///
     /// Generally [`Level::Partial`] wake locks are preferred, and is the
@@ -229,7 +503,7 @@ impl Builder {
     /// **This option was deprecated in API level 33.** Most applications should
     /// use `R.attr.turnScreenOn` or `Activity.setTurnScreenOn(boolean)`
     /// instead, as this prevents the previous foreground app from being resumed
-    /// first when the screen turns on.
+    /// first when the screen turns on. See [`screen::set_turn_screen_on`].
     #[deprecated]
     pub fn acquire_causes_wakeup(mut self, acquire_causes_wakeup: bool) -> Self {
         self.acquire_causes_wakeup = acquire_causes_wakeup;
@@ -247,25 +521,384 @@ impl Builder {
         self
     }
 
+    /// Pass additional flag bits to `PowerManager.newWakeLock` that this crate
+    /// does not otherwise provide options for, such as vendor extensions or
+    /// constants added in newer platform versions.
+    ///
+    /// The given bits are combined with the level and any flags set by other
+    /// options. **They are passed through unchecked**: this crate does not
+    /// validate them, and passing bits the platform does not recognize may
+    /// cause building or acquiring the wake lock to fail, or have unintended
+    /// effects. Prefer the dedicated options where available.
+    ///
+    /// Calling this again replaces the extra flags set previously.
+    pub fn extra_flags(mut self, flags: i32) -> Self {
+        self.extra_flags = flags;
+        self
+    }
+
+    /// Always release the wake lock on the crate's background thread when a
+    /// guard is dropped, instead of on the thread dropping the guard.
+    ///
+    /// This is useful when guards may be dropped in contexts where blocking on
+    /// a JNI call is undesirable. Errors releasing the wake lock in the
+    /// background are passed to the hook set with [`set_error_hook`] instead
+    /// of causing a panic.
+    ///
+    /// Regardless of this option, if a guard is dropped on a thread that cannot
+    /// be attached to the JVM, the release is performed in the background.
+    pub fn release_in_background(mut self, release_in_background: bool) -> Self {
+        self.release_in_background = release_in_background;
+        self
+    }
+
+    /// Limit the number of guards that may be held at once for this wake lock.
+    ///
+    /// Once `max_guards` guards are outstanding, [`WakeLock::acquire`] and the
+    /// other acquire methods return an error instead of acquiring the wake
+    /// lock again, while [`WakeLock::acquire_shared`] waits until an existing
+    /// guard is released. This can be used to put an upper bound on how much
+    /// concurrent work is allowed to keep the device awake.
+    ///
+    /// `max_guards` must be at least 1, otherwise building the wake lock
+    /// fails with [`ErrorKind::InvalidConfig`]. By default there is no limit.
+    pub fn hold_budget(mut self, max_guards: usize) -> Self {
+        self.hold_budget = Some(max_guards);
+        self
+    }
+
+    /// Retry building, acquiring, and releasing the wake lock according to the
+    /// given policy when they fail with transient errors.
+    ///
+    /// See [`RetryPolicy`] for which errors are considered transient. By
+    /// default, operations are not retried.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Limit how long any single acquire of the wake lock may keep the device
+    /// awake.
+    ///
+    /// With this set, [`WakeLock::acquire`] and the other acquire methods
+    /// behave like [`WakeLock::acquire_timeout`] with the given timeout, and
+    /// timeouts longer than this passed to `acquire_timeout` are shortened to
+    /// it. This enforces an upper bound on how long the wake lock is held even
+    /// if a guard is accidentally kept around.
+    ///
+    /// By default there is no limit.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use android_wakelock::WakeLock;
+    ///
+    /// // Never hold the wake lock for more than 10 minutes at a time.
+    /// let wake_lock = WakeLock::builder("myapp:mytag")
+    ///     .default_timeout(Duration::from_secs(600))
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    /// Release the wake lock automatically when the battery drops below the
+    /// given percentage.
+    ///
+    /// While the wake lock is held, the battery level is checked once a
+    /// minute, and right away when the platform reports the battery as low or
+    /// the charger as disconnected (see [`events::battery`]). If it is below
+    /// `percent` and the device is not charging, every reference to the wake
+    /// lock is released, and guards holding them no longer release anything
+    /// when dropped. Until the battery recovers or the
+    /// device is plugged in, [`WakeLock::acquire`] and the other acquire
+    /// methods return an error of kind [`ErrorKind::BatteryLow`]. This protects
+    /// users from background work draining the last of the battery.
+    ///
+    /// Use [`on_low_battery`][Builder::on_low_battery] to be notified when the
+    /// wake lock is released this way. By default there is no threshold.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::WakeLock;
+    ///
+    /// let wake_lock = WakeLock::builder("myapp:sync")
+    ///     .auto_release_below_battery(10)
+    ///     .on_low_battery(|level| log::warn!("stopped syncing at {}%", level))
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn auto_release_below_battery(mut self, percent: u8) -> Self {
+        let callback = self.low_battery.take().and_then(|config| config.callback);
+
+        self.low_battery = Some(low_battery::Config {
+            threshold: percent,
+            callback,
+        });
+        self
+    }
+
+    /// Set a callback to be called with the battery level when the wake lock
+    /// is released because the battery is low.
+    ///
+    /// The callback is called on the crate's background thread, and only has
+    /// an effect together with
+    /// [`auto_release_below_battery`][Builder::auto_release_below_battery].
+    pub fn on_low_battery<F>(mut self, f: F) -> Self
+    where
+        F: Fn(u8) + Send + Sync + 'static,
+    {
+        let config = self.low_battery.get_or_insert(low_battery::Config {
+            threshold: 0,
+            callback: None,
+        });
+
+        config.callback = Some(Arc::new(f));
+        self
+    }
+
+    /// Detect when the system releases the wake lock while guards for it are
+    /// still alive, and call the given function when that happens.
+    ///
+    /// Screen-level wake locks such as [`Level::ScreenDim`] may be released
+    /// implicitly when the user presses the power button. While the wake lock
+    /// is held, whether it is actually still held is checked every few
+    /// seconds. If it is not, every reference held by guards is treated as
+    /// released, so that the guards no longer release anything when dropped
+    /// instead of failing because the wake lock is under-locked, and the
+    /// function is called with a [`ForceReleased`] event on the crate's
+    /// background thread.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::{Level, WakeLock};
+    ///
+    /// let wake_lock = WakeLock::builder("myapp:reading")
+    ///     .level(Level::ScreenDim)
+    ///     .detect_forced_release(|event| {
+    ///         log::info!("{} was released by the system", event.tag());
+    ///     })
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn detect_forced_release<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ForceReleased) + Send + Sync + 'static,
+    {
+        self.forced_release = Some(forced_release::Config {
+            callback: Arc::new(f),
+        });
+        self
+    }
+
+    /// Refuse to acquire the wake lock while battery saver is on.
+    ///
+    /// With this set, [`WakeLock::acquire`] and the other acquire methods
+    /// check [`power::is_power_save_mode`] first, and if battery saver is on
+    /// behave as configured with
+    /// [`power_save_fallback`][Builder::power_save_fallback]: by default by
+    /// returning an error of kind [`ErrorKind::PowerSaveActive`]. This keeps
+    /// the policy of not working against the user's choice to save battery in
+    /// one place, instead of checking before every acquire.
+    ///
+    /// By default, battery saver is not taken into account.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::{ErrorKind, WakeLock};
+    ///
+    /// let wake_lock = WakeLock::builder("myapp:prefetch")
+    ///     .respect_power_save_mode(true)
+    ///     .build()?;
+    ///
+    /// match wake_lock.acquire() {
+    ///     Ok(_guard) => { /* prefetch content... */ }
+    ///     Err(e) if e.kind() == ErrorKind::PowerSaveActive => {}
+    ///     Err(e) => return Err(e.into()),
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn respect_power_save_mode(mut self, respect_power_save_mode: bool) -> Self {
+        self.respect_power_save_mode = respect_power_save_mode;
+        self
+    }
+
+    /// Set what to do when acquiring the wake lock while battery saver is on,
+    /// if [`respect_power_save_mode`][Builder::respect_power_save_mode] is
+    /// set.
+    ///
+    /// By default, acquiring fails with an error. See [`PowerSaveFallback`]
+    /// for the alternatives.
+    pub fn power_save_fallback(mut self, fallback: PowerSaveFallback) -> Self {
+        self.power_save_fallback = fallback;
+        self
+    }
+
+    /// Set the mechanism used to hold the wake lock.
+    ///
+    /// By default, wake locks are held through the framework's
+    /// `PowerManager`. System components written in Rust that may not have a
+    /// JVM available can use [`Backend::Sysfs`] instead, with the
+    /// `sysfs-backend` feature. Guards, timeouts, and the other acquire
+    /// options work the same regardless of backend, but options that need the
+    /// framework, such as [`work_source`][Builder::work_source] or
+    /// [`auto_prefix`][Builder::auto_prefix], cause building to fail with an
+    /// error of kind [`ErrorKind::InvalidConfig`] with the sysfs backend.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[cfg(feature = "sysfs-backend")]
+    /// # {
+    /// use android_wakelock::{Backend, WakeLock};
+    ///
+    /// let wake_lock = WakeLock::builder("mydaemon:update")
+    ///     .backend(Backend::Sysfs)
+    ///     .build()?;
+    ///
+    /// let _guard = wake_lock.acquire()?;
+    /// # }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Set an observer to be notified about the lifecycle of this wake lock,
+    /// in addition to the process-wide observer set with [`set_observer`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use android_wakelock::{ReleaseReason, WakeLock, WakeLockObserver};
+    ///
+    /// struct SyncMetrics;
+    ///
+    /// impl WakeLockObserver for SyncMetrics {
+    ///     fn on_released(&self, _tag: &str, _guard: u64, held: Duration, _reason: ReleaseReason) {
+    ///         println!("sync kept the device awake for {:?}", held);
+    ///     }
+    /// }
+    ///
+    /// let wake_lock = WakeLock::builder("myapp:sync")
+    ///     .observer(SyncMetrics)
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn observer<O>(mut self, observer: O) -> Self
+    where
+        O: WakeLockObserver + 'static,
+    {
+        self.observer = Some(Observer(Arc::new(observer)));
+        self
+    }
+
+    /// Attribute the battery usage of the wake lock to the apps in the given
+    /// work source instead of the calling app.
+    ///
+    /// This is intended for system apps that hold wake locks on behalf of other
+    /// apps, and requires the `android.permission.UPDATE_DEVICE_STATS`
+    /// permission. See [`WorkSource`] for more information.
+    pub fn work_source(mut self, work_source: WorkSource) -> Self {
+        self.work_source = Some(work_source);
+        self
+    }
+
     /// Creates a new wake lock with the specified level and options.
+    ///
+    /// Returns an error of kind [`ErrorKind::InvalidConfig`] without calling
+    /// into the platform if the tag is invalid (see
+    /// [`skip_tag_validation`][Builder::skip_tag_validation]) or the options
+    /// are known to be invalid, such as using
+    /// [`acquire_causes_wakeup`][Builder::acquire_causes_wakeup] or
+    /// [`on_after_release`][Builder::on_after_release] with
+    /// [`Level::Partial`].
     pub fn build(&self) -> Result<WakeLock> {
-        let ctx = ndk_context::android_context();
-        let vm = unsafe { JavaVM::from_raw(ctx.vm().cast()) }?;
-        let mut env = vm.attach_current_thread()?;
+        self.validate()?;
 
-        // Fetch the PowerManager system service.
-        let power_manager_service_id = env.new_string("power")?;
-        let power_manager = catch_exceptions(&mut env, |env| {
-            env.call_method(
-                unsafe { JObject::from_raw(ctx.context().cast()) },
-                "getSystemService",
-                "(Ljava/lang/String;)Ljava/lang/Object;",
-                &[JValue::from(&power_manager_service_id)],
-            )?
-            .l()
-        })?;
+        match &self.retry {
+            Some(policy) => policy.run(|| self.build_once()),
+            None => self.build_once(),
+        }
+    }
 
-        let name = env.new_string(&self.tag)?;
+    /// Set what to do when an option is not supported on the device's API
+    /// level.
+    ///
+    /// The API level is checked when the wake lock is built. Currently the
+    /// following are detected:
+    ///
+    /// - A level that the device does not support, such as
+    ///   [`Level::ProximityScreenOff`] on devices without a proximity sensor.
+    /// - [`acquire_causes_wakeup`][Builder::acquire_causes_wakeup] on API
+    ///   level 34 and higher without the `android.permission.TURN_SCREEN_ON`
+    ///   permission, where the platform ignores the flag.
+    ///
+    /// By default, building fails with an error in these cases. See
+    /// [`ApiFallback`] for the alternatives.
+    pub fn api_fallback(mut self, fallback: ApiFallback) -> Self {
+        self.api_fallback = fallback;
+        self
+    }
+
+    /// Prefix the tag with the app's package name, as in
+    /// `com.example.app:mytag`.
+    ///
+    /// This follows the recommended convention of prefixing tags with a unique
+    /// namespace for the app, without having to look up the package name
+    /// manually. The package name is looked up with `Context.getPackageName()`
+    /// when the wake lock is built. Tags that already start with the prefix are
+    /// left as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::WakeLock;
+    ///
+    /// // Creates a wake lock with a tag like "com.example.app:sync".
+    /// let wake_lock = WakeLock::builder("sync").auto_prefix().build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn auto_prefix(mut self) -> Self {
+        self.auto_prefix = true;
+        self
+    }
+
+    /// Skip checking the tag against the recommended naming conventions when
+    /// building.
+    ///
+    /// By default, [`build`][Builder::build] rejects empty tags and tags
+    /// starting with `*`, which are reserved for the platform's own wake locks
+    /// such as `*alarm*` and `*job*`. It also logs a warning for tags that are
+    /// very long, or that look like they contain a timestamp or an email
+    /// address. See [`WakeLock::builder`] for the conventions tags should
+    /// follow.
+    pub fn skip_tag_validation(mut self) -> Self {
+        self.validate_tag = false;
+        self
+    }
+
+    /// Get the tag the wake lock will be created with.
+    pub fn get_tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Get the level the wake lock will be created with.
+    pub fn get_level(&self) -> Level {
+        self.level
+    }
+
+    /// Get the raw flags that will be passed to `PowerManager.newWakeLock`,
+    /// including the level and any [extra flags][Builder::extra_flags].
+    pub fn get_flags(&self) -> i32 {
         let mut flags = self.level as i32;
 
         if self.acquire_causes_wakeup {
@@ -276,8 +909,205 @@ impl Builder {
             flags |= ON_AFTER_RELEASE;
         }
 
+        flags | self.extra_flags
+    }
+
+    /// Check for invalid combinations of options before creating the wake lock.
+    fn validate(&self) -> Result<()> {
+        if self.validate_tag {
+            tag::validate(&self.tag)?;
+        }
+
+        // No guard could ever be acquired, and waiting for one would never end.
+        if self.hold_budget == Some(0) {
+            return Err(Error::new(
+                ErrorKind::InvalidConfig,
+                format!(
+                    "hold budget of wake lock \"{}\" must be at least 1",
+                    self.tag
+                ),
+            ));
+        }
+
+        if self.level == Level::Partial {
+            let invalid = match (self.acquire_causes_wakeup, self.on_after_release) {
+                (true, true) => Some("acquire_causes_wakeup and on_after_release"),
+                (true, false) => Some("acquire_causes_wakeup"),
+                (false, true) => Some("on_after_release"),
+                (false, false) => None,
+            };
+
+            if let Some(invalid) = invalid {
+                return Err(Error::new(
+                    ErrorKind::InvalidConfig,
+                    format!(
+                        "{} cannot be used with a partial wake lock (tag \"{}\")",
+                        invalid, self.tag
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn build_once(&self) -> Result<WakeLock> {
+        #[cfg(feature = "sysfs-backend")]
+        if self.backend == Backend::Sysfs {
+            self.validate_sysfs()?;
+
+            return Ok(self.clone().into_wake_lock(Handle::Sysfs, None));
+        }
+
+        let vm = java_vm()?;
+        let mut env = vm.attach_current_thread()?;
+        let config = self.resolve(&mut env)?;
+        let wake_lock = config.create(&mut env)?;
+
+        drop(env);
+
+        Ok(config.into_wake_lock(Handle::Java(wake_lock), Some(vm)))
+    }
+
+    /// Check for options that cannot be used with the sysfs backend.
+    #[cfg(feature = "sysfs-backend")]
+    fn validate_sysfs(&self) -> Result<()> {
+        let invalid = [
+            (self.level != Level::Partial, "levels other than partial"),
+            (
+                self.acquire_causes_wakeup || self.on_after_release || self.extra_flags != 0,
+                "flags",
+            ),
+            (self.work_source.is_some(), "work_source"),
+            (self.auto_prefix, "auto_prefix"),
+            (self.low_battery.is_some(), "auto_release_below_battery"),
+            (self.forced_release.is_some(), "detect_forced_release"),
+            (self.respect_power_save_mode, "respect_power_save_mode"),
+        ];
+
+        match invalid.iter().find(|(invalid, _)| *invalid) {
+            Some((_, option)) => Err(Error::new(
+                ErrorKind::InvalidConfig,
+                format!(
+                    "{} cannot be used with the sysfs backend (tag \"{}\")",
+                    option, self.tag
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Create the wake lock for a resolved configuration and its underlying
+    /// handle.
+    fn into_wake_lock(self, wake_lock: Handle, vm: Option<JavaVM>) -> WakeLock {
+        let config = self;
+        let inner = Arc::new(Inner {
+            object: Mutex::new(Object {
+                wake_lock,
+                flags: config.get_flags(),
+                held: 0,
+                revoked: 0,
+                deadlines: Vec::new(),
+                config: config.clone(),
+            }),
+            vm,
+            tag: config.tag,
+            release_in_background: config.release_in_background,
+            budget: config.hold_budget.map(|max| Arc::new(Budget::new(max))),
+            retry: config.retry,
+            default_timeout: config.default_timeout,
+            low_battery: config
+                .low_battery
+                .clone()
+                .filter(|config| config.threshold > 0)
+                .map(Monitor::new),
+            forced_release: config
+                .forced_release
+                .clone()
+                .map(forced_release::Monitor::new),
+            power_save: config
+                .respect_power_save_mode
+                .then_some(config.power_save_fallback),
+            backend: config.backend,
+            observer: config.observer.clone(),
+            guards: ActiveGuards::default(),
+        });
+
+        registry::register(&inner);
+
+        WakeLock { inner }
+    }
+
+    /// Resolve options that depend on the app into a configuration that can be
+    /// used to create the wake lock directly.
+    fn resolve(&self, env: &mut JNIEnv<'_>) -> Result<Builder> {
+        let mut config = self.clone();
+
+        if config.auto_prefix {
+            let prefix = format!("{}:", package_name(env)?);
+
+            if !config.tag.starts_with(&prefix) {
+                config.tag.insert_str(0, &prefix);
+            }
+
+            config.auto_prefix = false;
+        }
+
+        let api_level = sys::api_level_with(env)?;
+
+        #[allow(deprecated)]
+        if config.acquire_causes_wakeup
+            && api_level >= 34
+            && !has_permission(env, "android.permission.TURN_SCREEN_ON")?
+        {
+            config.unsupported(
+                "acquire_causes_wakeup requires the TURN_SCREEN_ON permission on API level 34 and higher",
+                |config| config.acquire_causes_wakeup = false,
+            )?;
+        }
+
+        let power_manager = power_manager(env)?;
+
+        if !level_supported(env, &power_manager, config.level)? {
+            config.unsupported(
+                &format!(
+                    "wake lock level {:?} is not supported on this device",
+                    config.level
+                ),
+                |config| {
+                    config.level = Level::Partial;
+                    config.acquire_causes_wakeup = false;
+                    config.on_after_release = false;
+                },
+            )?;
+        }
+
+        Ok(config)
+    }
+
+    /// Handle an unsupported option according to the API fallback, using the
+    /// given function to remove the option if degrading.
+    fn unsupported(&mut self, reason: &str, degrade: impl FnOnce(&mut Self)) -> Result<()> {
+        match self.api_fallback {
+            ApiFallback::Error => Err(Error::new(ErrorKind::Unsupported, reason)),
+            ApiFallback::Degrade => {
+                warn!("{} (tag \"{}\"), falling back", reason, self.tag);
+                degrade(self);
+                Ok(())
+            }
+        }
+    }
+
+    /// Create the underlying Java object for this configuration.
+    fn create(&self, env: &mut JNIEnv<'_>) -> Result<GlobalRef> {
+        self.level.warn_if_deprecated(sys::api_level_with(env)?);
+
+        let power_manager = power_manager(env)?;
+        let name = env.new_string(&self.tag)?;
+        let flags = self.get_flags();
+
         // Create the wake lock.
-        let result = catch_exceptions(&mut env, |env| {
+        let result = catch_exceptions(env, |env| {
             env.call_method(
                 &power_manager,
                 "newWakeLock",
@@ -288,13 +1118,11 @@ impl Builder {
 
         let wake_lock = env.new_global_ref(result.l()?)?;
 
-        drop(env);
+        if let Some(work_source) = &self.work_source {
+            set_work_source(env, &wake_lock, work_source)?;
+        }
 
-        Ok(WakeLock {
-            wake_lock,
-            vm,
-            tag: self.tag.clone(),
-        })
+        Ok(wake_lock)
     }
 }
 
@@ -308,22 +1136,510 @@ impl Builder {
 /// Any application using a `WakeLock` must request the
 /// `android.permission.WAKE_LOCK` permission in an `<uses-permission>` element
 /// of the application's manifest.
+///
+/// Dropping a `WakeLock` does not release any outstanding [`Guard`]s; the
+/// underlying Java object is kept alive until the last guard is released.
 #[derive(Debug)]
 pub struct WakeLock {
-    /// Reference to the underlying Java object.
-    wake_lock: GlobalRef,
+    inner: Arc<Inner>,
+}
+
+/// State shared between a wake lock and all of its guards.
+#[derive(Debug)]
+struct Inner {
+    /// The underlying Java object, which may be replaced by
+    /// [`WakeLock::rebuild`].
+    object: Mutex<Object>,
 
-    /// The JVM the object belongs to.
-    vm: JavaVM,
+    /// The JVM the object belongs to, if the wake lock uses the framework.
+    vm: Option<JavaVM>,
 
     /// The tag specified when the wake lock was created.
     tag: String,
-}
 
-impl WakeLock {
-    /// Create a new builder with the given tag for configuring and creating a
-    /// wake lock.
-    ///
+    /// Whether guards should always be released on the worker thread.
+    release_in_background: bool,
+
+    /// Limit on the number of outstanding guards, if any.
+    budget: Option<Arc<Budget>>,
+
+    /// Policy for retrying transient errors, if any.
+    retry: Option<RetryPolicy>,
+
+    /// Upper bound on how long each acquire may hold the wake lock, if any.
+    default_timeout: Option<Duration>,
+
+    /// Monitor releasing the wake lock when the battery is low, if any.
+    low_battery: Option<Monitor>,
+
+    /// Monitor detecting when the system releases the wake lock, if any.
+    forced_release: Option<forced_release::Monitor>,
+
+    /// What to do when acquiring while battery saver is on, if it is
+    /// respected.
+    power_save: Option<PowerSaveFallback>,
+
+    /// The mechanism used to hold the wake lock.
+    backend: Backend,
+
+    /// Observer notified about the wake lock's lifecycle, if any.
+    observer: Option<Observer>,
+
+    /// Guards currently holding the wake lock.
+    guards: ActiveGuards,
+}
+
+/// Handle to the underlying wake lock.
+#[derive(Clone, Debug)]
+enum Handle {
+    /// Reference to the Java object.
+    Java(GlobalRef),
+
+    /// A kernel wake lock named after the tag.
+    #[cfg(feature = "sysfs-backend")]
+    Sysfs,
+}
+
+impl Handle {
+    /// Get the Java object, or an error if the wake lock does not use the
+    /// framework.
+    fn java(&self) -> Result<&GlobalRef> {
+        match self {
+            Self::Java(wake_lock) => Ok(wake_lock),
+            #[cfg(feature = "sysfs-backend")]
+            Self::Sysfs => Err(Error::new(
+                ErrorKind::Unsupported,
+                "operation is not supported by the sysfs backend",
+            )),
+        }
+    }
+}
+
+/// The underlying Java object of a wake lock, along with the references to it
+/// currently held by guards.
+#[derive(Debug)]
+struct Object {
+    /// Handle to the Java object, or to the kernel wake lock.
+    wake_lock: Handle,
+
+    /// The flags the object was created with, including the level.
+    flags: i32,
+
+    /// The configuration the object was created with.
+    config: Builder,
+
+    /// Number of references acquired without a timeout and not yet released.
+    held: usize,
+
+    /// Number of references acquired without a timeout that were already
+    /// released on behalf of their guards, such as because the battery was
+    /// low, but whose guards still exist.
+    revoked: usize,
+
+    /// Deadlines of references acquired with a timeout and not yet released.
+    deadlines: Vec<Instant>,
+}
+
+impl Object {
+    /// Record that a reference acquired with the given deadline, if any, was
+    /// released.
+    fn released(&mut self, deadline: Option<Instant>) {
+        match deadline {
+            Some(deadline) => {
+                if let Some(i) = self.deadlines.iter().position(|d| *d == deadline) {
+                    self.deadlines.swap_remove(i);
+                }
+            }
+            None => self.held = self.held.saturating_sub(1),
+        }
+    }
+}
+
+impl Inner {
+    /// Call a method on the underlying Java object, attaching the current
+    /// thread to the JVM for the duration of the call if necessary.
+    fn call_method<T, F>(&self, mut f: F) -> Result<T>
+    where
+        F: for<'local> FnMut(&mut JNIEnv<'local>, &GlobalRef) -> jni::errors::Result<T>,
+    {
+        self.with_env(|env| {
+            let object = self.object();
+            let wake_lock = object.wake_lock.java()?;
+
+            catch_exceptions(env, |env| f(env, wake_lock))
+        })
+    }
+
+    /// Lock the underlying Java object.
+    fn object(&self) -> MutexGuard<'_, Object> {
+        self.object.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run the given closure with the current thread attached to the JVM.
+    ///
+    /// The closure runs in its own local frame, so that local references it
+    /// creates are freed afterwards even on threads that stay attached, such
+    /// as the worker thread.
+    ///
+    /// If the thread turns out to have been detached in the meantime, for
+    /// example because an attach guard was dropped elsewhere, the thread is
+    /// attached again and the closure is retried once.
+    fn with_env<T, F>(&self, mut f: F) -> Result<T>
+    where
+        F: for<'local> FnMut(&mut JNIEnv<'local>) -> Result<T>,
+    {
+        let vm = self.vm()?;
+        let mut env = stats::timed(JniCall::Attach, || vm.attach_current_thread())?;
+
+        match env.with_local_frame(LOCAL_FRAME_CAPACITY, |env| f(env)) {
+            Err(e) if e.is_thread_detached() => {
+                debug!(
+                    "thread was detached while calling wake lock \"{}\", attaching again",
+                    self.tag
+                );
+
+                drop(env);
+
+                let mut env = stats::timed(JniCall::Attach, || vm.attach_current_thread())?;
+
+                env.with_local_frame(LOCAL_FRAME_CAPACITY, |env| f(env))
+            }
+            result => result,
+        }
+    }
+
+    /// Get the JVM, or an error if the wake lock does not use the framework.
+    fn vm(&self) -> Result<&JavaVM> {
+        self.vm.as_ref().ok_or_else(|| {
+            Error::new(
+                ErrorKind::Unsupported,
+                format!("wake lock \"{}\" does not use the framework", self.tag),
+            )
+        })
+    }
+
+    /// Run the given operation, retrying transient errors if the wake lock has
+    /// a retry policy.
+    fn retrying<T>(&self, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        match &self.retry {
+            Some(policy) => policy.run(f),
+            None => f(),
+        }
+    }
+
+    /// Acquire the wake lock for the guard with the given ID, returning the
+    /// deadline after which the system releases it again if a timeout was
+    /// given.
+    fn acquire(self: &Arc<Self>, id: u64, timeout: Option<Duration>) -> Result<Option<Instant>> {
+        if let Some(monitor) = &self.low_battery {
+            monitor.check(&self.tag)?;
+        }
+
+        let timeout = match (timeout, self.default_timeout) {
+            (Some(timeout), Some(default)) => Some(timeout.min(default)),
+            (timeout, default) => timeout.or(default),
+        };
+
+        let deadline = self.retrying(|| {
+            #[cfg(feature = "sysfs-backend")]
+            if self.backend == Backend::Sysfs {
+                return self.acquire_sysfs(&self.tag, timeout.map(|t| Instant::now() + t));
+            }
+
+            self.with_env(|env| {
+                let mut object = self.object();
+
+                acquire_with(env, object.wake_lock.java()?, timeout).map_err(|e| {
+                    if e.exception_class() == Some("java.lang.SecurityException") {
+                        Error::missing_permission(e, object.flags & ACQUIRE_CAUSES_WAKEUP != 0)
+                    } else {
+                        e
+                    }
+                })?;
+
+                let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+                match deadline {
+                    Some(deadline) => object.deadlines.push(deadline),
+                    None => object.held += 1,
+                }
+
+                Ok(deadline)
+            })
+        })?;
+
+        debug!("guard {} acquired wake lock \"{}\"", id, self.tag);
+
+        Monitor::start(self);
+        forced_release::Monitor::start(self);
+
+        Ok(deadline)
+    }
+
+    /// Release a reference held by the guard with the given ID, acquired with
+    /// the given deadline, if any.
+    fn release(&self, id: u64, flags: i32, deadline: Option<Instant>) -> Result<()> {
+        #[cfg(feature = "sysfs-backend")]
+        if self.backend == Backend::Sysfs {
+            return self.release_sysfs(&self.tag, deadline);
+        }
+
+        self.retrying(|| self.with_env(|env| self.release_with(env, id, flags, deadline)))
+    }
+
+    fn release_with(
+        &self,
+        env: &mut JNIEnv<'_>,
+        id: u64,
+        flags: i32,
+        deadline: Option<Instant>,
+    ) -> Result<()> {
+        let mut object = self.object();
+
+        // If the timeout has expired, the system has already released the
+        // reference. Releasing again would release a reference belonging to a
+        // different guard instead.
+        if let Some(deadline) = deadline {
+            if deadline <= Instant::now() || !object.deadlines.contains(&deadline) {
+                object.released(Some(deadline));
+
+                debug!(
+                    "timed wake lock \"{}\" of guard {} already released by the system",
+                    self.tag, id
+                );
+
+                return Ok(());
+            }
+        } else if object.revoked > 0 {
+            object.revoked -= 1;
+
+            debug!(
+                "wake lock \"{}\" reference of guard {} already revoked",
+                self.tag, id
+            );
+
+            return Ok(());
+        }
+
+        match release_with(env, object.wake_lock.java()?, flags) {
+            Ok(()) => object.released(deadline),
+            // The system already released the reference once its timeout
+            // expired, so it no longer needs to be accounted for either.
+            Err(e) if deadline.is_some() && e.kind() == ErrorKind::UnderLocked => {
+                object.released(deadline);
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        }
+
+        debug!("guard {} released wake lock \"{}\"", id, self.tag);
+
+        Ok(())
+    }
+
+    /// Release all references currently held, leaving the guards holding them
+    /// with nothing to release.
+    fn revoke(&self) -> Result<()> {
+        self.with_env(|env| {
+            let mut object = self.object();
+            let now = Instant::now();
+
+            object.deadlines.retain(|deadline| *deadline > now);
+
+            for _ in 0..object.held + object.deadlines.len() {
+                match release_with(env, object.wake_lock.java()?, 0) {
+                    Ok(()) => {}
+                    // A timeout may have expired in the meantime.
+                    Err(e) if e.kind() == ErrorKind::UnderLocked => {}
+                    Err(e) => return Err(e),
+                }
+            }
+
+            object.revoked += object.held;
+            object.held = 0;
+            object.deadlines.clear();
+
+            Ok(())
+        })
+    }
+
+    fn set_work_source(&self, work_source: &WorkSource) -> Result<()> {
+        self.retrying(|| {
+            self.with_env(|env| set_work_source(env, self.object().wake_lock.java()?, work_source))
+        })
+    }
+
+    /// Take a share of the hold budget for a new guard, if the wake lock has a
+    /// budget.
+    fn permit(&self) -> Result<Option<Permit>> {
+        match &self.budget {
+            Some(budget) => match budget.try_acquire() {
+                Some(permit) => Ok(Some(permit)),
+                None => Err(Error::new(
+                    ErrorKind::BudgetExhausted,
+                    format!(
+                        "hold budget of {} guards exhausted for wake lock \"{}\"",
+                        budget.max(),
+                        self.tag
+                    ),
+                )),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Acquire the wake lock from the worker thread, returning a guard that will
+    /// also be released on the worker thread.
+    fn acquire_on_worker(
+        self: &Arc<Self>,
+        permit: Option<Permit>,
+        caller: &'static Location<'static>,
+    ) -> Result<Guard> {
+        self.attach_worker()?;
+        self.guard(None, true, permit, caller)
+    }
+
+    /// Acquire the wake lock and return a guard for it, or a guard that holds
+    /// nothing if battery saver is on and the wake lock is configured to skip
+    /// acquiring in that case.
+    fn guard(
+        self: &Arc<Self>,
+        timeout: Option<Duration>,
+        on_worker: bool,
+        permit: Option<Permit>,
+        caller: &'static Location<'static>,
+    ) -> Result<Guard> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+        let skip = self.skip_for_power_save()?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let acquired_at = Instant::now();
+        let deadline = if skip {
+            None
+        } else {
+            let deadline = self
+                .acquire(id, timeout)
+                .inspect_err(|e| self.notify_error(e))?;
+
+            stats::acquired(&self.tag);
+            self.guards.insert(ActiveGuard {
+                id,
+                acquired_at,
+                deadline,
+                caller,
+                #[cfg(feature = "audit")]
+                backtrace: Arc::new(std::backtrace::Backtrace::force_capture()),
+            });
+
+            #[cfg(feature = "metrics")]
+            instrument::acquired(&self.tag);
+
+            #[cfg(feature = "log")]
+            history::acquired(
+                &self.tag,
+                id,
+                deadline.map(|deadline| deadline.saturating_duration_since(acquired_at)),
+            );
+
+            observer::notify(self.observer.as_ref(), |observer| {
+                observer.on_acquired(
+                    &self.tag,
+                    id,
+                    deadline.map(|deadline| deadline.saturating_duration_since(acquired_at)),
+                )
+            });
+
+            deadline
+        };
+
+        Ok(Guard {
+            inner: self.clone(),
+            id,
+            released: skip,
+            on_worker,
+            deadline,
+            acquired_at,
+            #[cfg(feature = "tracing")]
+            span: if skip || cfg!(feature = "no-instrumentation") {
+                tracing::Span::none()
+            } else {
+                tracing::info_span!(
+                    "wake_lock",
+                    tag = %self.tag,
+                    timeout = ?deadline.map(|deadline| deadline.saturating_duration_since(acquired_at)),
+                    reason = tracing::field::Empty,
+                    held = tracing::field::Empty,
+                )
+            },
+            #[cfg(feature = "atrace")]
+            _trace: if skip {
+                None
+            } else {
+                atrace::Hold::begin(&self.tag)
+            },
+            _permit: permit,
+        })
+    }
+
+    /// Notify observers of an error.
+    fn notify_error(&self, error: &Error) {
+        observer::notify(self.observer.as_ref(), |observer| {
+            observer.on_error(&self.tag, error)
+        });
+    }
+
+    /// Report an error that cannot be returned to a caller to observers and
+    /// the error hook.
+    fn report_error(&self, error: &Error) {
+        self.notify_error(error);
+        hook::report_error(&self.tag, error);
+    }
+
+    /// Check whether acquiring should be skipped because battery saver is on,
+    /// returning an error if it should be refused instead.
+    fn skip_for_power_save(&self) -> Result<bool> {
+        let Some(fallback) = self.power_save else {
+            return Ok(false);
+        };
+
+        if !power::is_power_save_mode()? {
+            return Ok(false);
+        }
+
+        match fallback {
+            PowerSaveFallback::Error => Err(Error::new(
+                ErrorKind::PowerSaveActive,
+                format!(
+                    "battery saver is on, not acquiring wake lock \"{}\"",
+                    self.tag
+                ),
+            )),
+            PowerSaveFallback::NoOp => {
+                debug!(
+                    "battery saver is on, skipping acquire of wake lock \"{}\"",
+                    self.tag
+                );
+
+                Ok(true)
+            }
+        }
+    }
+
+    /// Attach the worker thread to the JVM for the remainder of its lifetime,
+    /// so that jobs running on it do not repeatedly attach and detach.
+    fn attach_worker(&self) -> Result<()> {
+        if let Some(vm) = &self.vm {
+            vm.attach_current_thread_permanently()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl WakeLock {
+    /// Create a new builder with the given tag for configuring and creating a
+    /// wake lock.
+    ///
     /// # Tags
     ///
     /// Your class name (or other tag) for debugging purposes. Recommended
@@ -347,17 +1663,333 @@ impl WakeLock {
             level: Level::Partial,
             acquire_causes_wakeup: false,
             on_after_release: false,
+            extra_flags: 0,
+            release_in_background: false,
+            hold_budget: None,
+            retry: None,
+            default_timeout: None,
+            low_battery: None,
+            forced_release: None,
+            respect_power_save_mode: false,
+            power_save_fallback: PowerSaveFallback::Error,
+            backend: Backend::Framework,
+            observer: None,
+            work_source: None,
+            validate_tag: true,
+            auto_prefix: false,
+            api_fallback: ApiFallback::Error,
+        }
+    }
+
+    /// Returns true if the given wake lock level is supported on this device.
+    ///
+    /// Some levels, such as [`Level::ProximityScreenOff`], depend on hardware
+    /// that not all devices have. [`Builder::build`] already fails with
+    /// [`ErrorKind::Unsupported`] for unsupported levels unless configured
+    /// otherwise with [`Builder::api_fallback`], but this can be used to check
+    /// ahead of time and choose a different level explicitly.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::{Level, WakeLock};
+    ///
+    /// if WakeLock::level_supported(Level::ProximityScreenOff)? {
+    ///     let wake_lock = WakeLock::builder("myapp:call")
+    ///         .level(Level::ProximityScreenOff)
+    ///         .build()?;
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn level_supported(level: Level) -> Result<bool> {
+        let vm = java_vm()?;
+        let mut env = vm.attach_current_thread()?;
+        let power_manager = power_manager(&mut env)?;
+
+        level_supported(&mut env, &power_manager, level)
+    }
+
+    /// Get the tag of this wake lock, including the package name prefix if
+    /// [`Builder::auto_prefix`] was used.
+    pub fn tag(&self) -> &str {
+        &self.inner.tag
+    }
+
+    /// Get the level of this wake lock.
+    pub fn level(&self) -> Level {
+        self.inner.object().config.level
+    }
+
+    /// Get the raw flags this wake lock was created with, including the level.
+    ///
+    /// These are the flags that were passed to `PowerManager.newWakeLock`,
+    /// which may also include [extra flags][Builder::extra_flags].
+    pub fn flags(&self) -> i32 {
+        self.inner.object().flags
+    }
+
+    /// Create a new, independent wake lock with the same configuration as this
+    /// one.
+    ///
+    /// Unlike sharing a `WakeLock` between subsystems, the new wake lock is a
+    /// separate Java object that is reference counted on its own, and has its
+    /// own hold budget if one was configured. This is equivalent to building
+    /// [`Builder::from_lock`] again.
+    pub fn try_clone(&self) -> Result<WakeLock> {
+        Builder::from_lock(self).build()
+    }
+
+    /// Create a new, independent wake lock with the same configuration as this
+    /// one, but with the given suffix appended to its tag.
+    ///
+    /// See [`try_clone`][WakeLock::try_clone] for more information. Giving each
+    /// subsystem a distinct tag makes it possible to tell their wake locks
+    /// apart when debugging.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let template = android_wakelock::partial("myapp:sync")?;
+    ///
+    /// // Creates a wake lock with the tag "myapp:sync:photos".
+    /// let photos = template.try_clone_with_suffix(":photos")?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn try_clone_with_suffix(&self, suffix: &str) -> Result<WakeLock> {
+        let mut builder = Builder::from_lock(self);
+        builder.tag.push_str(suffix);
+        builder.build()
+    }
+
+    /// Replace the underlying wake lock with a new one created from the given
+    /// configuration, without releasing any outstanding [`Guard`]s.
+    ///
+    /// The new wake lock is acquired once for each reference currently held by
+    /// a guard before the old wake lock is released, so the device is kept
+    /// awake throughout. References acquired with
+    /// [`acquire_timeout`][WakeLock::acquire_timeout] are acquired again with
+    /// whatever time remains before their timeout. Existing guards then
+    /// release the new wake lock when they are dropped.
+    ///
+    /// Only the options that affect the underlying wake lock, such as the
+    /// level, flags, and work source, can be changed this way. The tag
+    /// must stay the same, and the hold budget, retry policy, default timeout,
+    /// low battery threshold, battery saver options, and
+    /// [`release_in_background`][Builder::release_in_background] options
+    /// of this wake lock are kept as they are. If the new wake lock cannot be
+    /// created or acquired, an error is returned and the old wake lock remains
+    /// in use.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::{Builder, Level, WakeLock};
+    ///
+    /// let wake_lock = WakeLock::builder("myapp:call")
+    ///     .level(Level::ProximityScreenOff)
+    ///     .build()?;
+    /// let guard = wake_lock.acquire()?;
+    ///
+    /// // Switch to keeping only the CPU awake, without letting go in between.
+    /// wake_lock.rebuild(&Builder::from_lock(&wake_lock).level(Level::Partial))?;
+    ///
+    /// drop(guard);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn rebuild(&self, builder: &Builder) -> Result<()> {
+        builder.validate()?;
+
+        self.inner
+            .retrying(|| self.inner.with_env(|env| self.rebuild_with(env, builder)))
+    }
+
+    fn rebuild_with(&self, env: &mut JNIEnv<'_>, builder: &Builder) -> Result<()> {
+        let builder = builder.resolve(env)?;
+
+        if builder.backend != self.inner.backend {
+            return Err(Error::new(
+                ErrorKind::InvalidConfig,
+                format!(
+                    "cannot rebuild wake lock \"{}\" with a different backend",
+                    self.inner.tag
+                ),
+            ));
+        }
+
+        if builder.tag != self.inner.tag {
+            return Err(Error::new(
+                ErrorKind::InvalidConfig,
+                format!(
+                    "cannot rebuild wake lock \"{}\" with a different tag \"{}\"",
+                    self.inner.tag, builder.tag
+                ),
+            ));
+        }
+
+        let mut object = self.inner.object();
+        let wake_lock = builder.create(env)?;
+
+        // References whose timeout has already expired were released by
+        // the system and do not need to be carried over.
+        let now = Instant::now();
+        object.deadlines.retain(|deadline| *deadline > now);
+
+        let timeouts = object
+            .deadlines
+            .iter()
+            .map(|deadline| Some(*deadline - now))
+            .collect::<Vec<_>>();
+        let references = std::iter::repeat_n(None, object.held)
+            .chain(timeouts)
+            .collect::<Vec<_>>();
+
+        for (acquired, timeout) in references.iter().enumerate() {
+            if let Err(e) = acquire_with(env, &wake_lock, *timeout) {
+                for _ in 0..acquired {
+                    let _ = release_with(env, &wake_lock, 0);
+                }
+
+                return Err(e);
+            }
         }
+
+        let old = object.wake_lock.java()?.clone();
+
+        for _ in &references {
+            if let Err(e) = release_with(env, &old, 0) {
+                warn!(
+                    "failed to release old wake lock \"{}\" while rebuilding: {}",
+                    self.inner.tag, e
+                );
+            }
+        }
+
+        let mut config = builder;
+        config.release_in_background = object.config.release_in_background;
+        config.hold_budget = object.config.hold_budget;
+        config.retry = object.config.retry.clone();
+        config.default_timeout = object.config.default_timeout;
+        config.low_battery = object.config.low_battery.clone();
+        config.forced_release = object.config.forced_release.clone();
+        config.respect_power_save_mode = object.config.respect_power_save_mode;
+        config.power_save_fallback = object.config.power_save_fallback;
+        config.observer = object.config.observer.clone();
+
+        object.wake_lock = Handle::Java(wake_lock);
+        object.flags = config.get_flags();
+        object.config = config;
+
+        debug!("rebuilt wake lock \"{}\"", self.inner.tag);
+
+        Ok(())
+    }
+
+    /// Returns true if this and the other wake lock refer to the same
+    /// underlying Java object.
+    ///
+    /// Wake lock handles received from different places may refer to the same
+    /// Java object, in which case acquiring both holds the same wake lock
+    /// twice. This can be used to detect and deduplicate such handles.
+    pub fn same_as(&self, other: &WakeLock) -> Result<bool> {
+        if Arc::ptr_eq(&self.inner, &other.inner) {
+            return Ok(true);
+        }
+
+        // Kernel wake locks with the same name are the same wake lock.
+        #[cfg(feature = "sysfs-backend")]
+        if self.inner.backend == Backend::Sysfs || other.inner.backend == Backend::Sysfs {
+            return Ok(
+                self.inner.backend == other.inner.backend && self.inner.tag == other.inner.tag
+            );
+        }
+
+        // Avoid holding both locks at once.
+        let other = other.inner.object().wake_lock.java()?.clone();
+
+        self.inner
+            .call_method(|env, wake_lock| env.is_same_object(wake_lock, &other))
+    }
+
+    /// Get a description of the underlying Java object from its `toString()`
+    /// method.
+    ///
+    /// The description is produced by the platform and includes the flags,
+    /// tag, and whether the wake lock is held as the platform sees them. This
+    /// is intended for debugging only; its format is not stable.
+    pub fn describe(&self) -> Result<String> {
+        self.inner.call_method(|env, wake_lock| {
+            let description = env
+                .call_method(wake_lock, "toString", "()Ljava/lang/String;", &[])?
+                .l()?;
+
+            Ok(java_string(env, description)?.unwrap_or_default())
+        })
     }
 
     /// Returns true if the wake lock has outstanding references not yet
     /// released.
     pub fn is_held(&self) -> Result<bool> {
-        let mut env = self.vm.attach_current_thread()?;
+        #[cfg(feature = "sysfs-backend")]
+        if self.inner.backend == Backend::Sysfs {
+            return Ok(sysfs::is_held(&self.inner.object()));
+        }
 
-        catch_exceptions(&mut env, |env| {
-            env.call_method(&self.wake_lock, "isHeld", "()Z", &[])?.z()
-        })
+        self.inner.call_method(is_held_with)
+    }
+
+    /// Get information about each guard currently holding this wake lock, in
+    /// the order they acquired it.
+    ///
+    /// This is useful for watchdog screens showing what is keeping the device
+    /// awake, or for asserting in integration tests that no guards are left
+    /// behind once some work finishes. Guards that skipped acquiring the wake
+    /// lock, such as in battery saver mode, are not included.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let wake_lock = android_wakelock::partial("myapp:sync")?;
+    ///
+    /// // Run the sync...
+    ///
+    /// for guard in wake_lock.active_guards() {
+    ///     log::warn!(
+    ///         "guard {} acquired at {} still alive after {:?} ({:?})",
+    ///         guard.id(),
+    ///         guard.call_site(),
+    ///         guard.held(),
+    ///         guard.reason(),
+    ///     );
+    /// }
+    ///
+    /// assert!(wake_lock.active_guards().is_empty());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn active_guards(&self) -> Vec<GuardInfo> {
+        let now = Instant::now();
+
+        self.inner
+            .guards
+            .snapshot()
+            .into_iter()
+            .map(|guard| GuardInfo {
+                id: guard.id,
+                call_site: guard.caller,
+                held: match guard.deadline {
+                    Some(deadline) if deadline <= now => deadline - guard.acquired_at,
+                    _ => now.saturating_duration_since(guard.acquired_at),
+                },
+                timeout: guard
+                    .deadline
+                    .map(|deadline| deadline.saturating_duration_since(guard.acquired_at)),
+                reason: guard
+                    .deadline
+                    .filter(|deadline| *deadline <= now)
+                    .map(|_| ReleaseReason::TimedOut),
+                #[cfg(feature = "audit")]
+                backtrace: guard.backtrace,
+            })
+            .collect()
     }
 
     /// Acquire the wake lock and force the device to stay on at the level that
@@ -388,23 +2020,255 @@ impl WakeLock {
     ///
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
-    pub fn acquire(&self) -> Result<Guard<'_>> {
-        let mut env = self.vm.attach_current_thread()?;
+    #[track_caller]
+    pub fn acquire(&self) -> Result<Guard> {
+        let caller = Location::caller();
+        let permit = self.inner.permit()?;
 
-        catch_exceptions(&mut env, |env| {
-            env.call_method(&self.wake_lock, "acquire", "()V", &[])
-        })?;
+        self.inner.guard(None, false, permit, caller)
+    }
 
-        log::debug!("acquired wake lock \"{}\"", self.tag);
+    /// Acquire the wake lock, and have the system release it automatically
+    /// after the given timeout.
+    ///
+    /// This behaves like [`acquire`][WakeLock::acquire], except that the
+    /// system releases the wake lock once the timeout expires, even if the
+    /// returned [`Guard`] has not been dropped yet. This is a useful safety net
+    /// to ensure the wake lock is never held for longer than intended.
+    ///
+    /// If the timeout has already expired when the guard is released, the
+    /// system has already released the wake lock and releasing it again is
+    /// skipped. Should the timeout expire while the release is in progress,
+    /// releasing may still fail with [`ErrorKind::UnderLocked`], which
+    /// dropping a timed guard ignores instead of panicking.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// let wake_lock = android_wakelock::partial("myapp:mytag")?;
+    ///
+    /// // Keep the device awake for at most 10 minutes.
+    /// let guard = wake_lock.acquire_timeout(Duration::from_secs(600))?;
+    ///
+    /// // Do some work while the device is awake...
+    ///
+    /// drop(guard);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[track_caller]
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<Guard> {
+        let caller = Location::caller();
+        let permit = self.inner.permit()?;
 
-        Ok(Guard {
-            wake_lock: self.wake_lock.clone(),
-            env,
-            tag: &self.tag,
+        self.inner.guard(Some(timeout), false, permit, caller)
+    }
+
+    /// Acquire the wake lock without performing any JNI calls on the current
+    /// thread.
+    ///
+    /// This behaves like [`acquire`][WakeLock::acquire], except that the
+    /// acquire is performed on a dedicated background thread owned by this
+    /// crate, and the returned future resolves once it completes. The
+    /// resulting [`Guard`] will also be released on that same background
+    /// thread, so that the calling thread never needs to be attached to the
+    /// JVM. This is useful for async executor threads that should remain
+    /// JVM-free.
+    ///
+    /// The returned future does not depend on any particular async runtime. If
+    /// the future is dropped before it completes, the wake lock is released
+    /// again as soon as the acquire finishes.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let wake_lock = android_wakelock::partial("myapp:mytag")?;
+    ///
+    /// // Start keeping the device awake.
+    /// let guard = wake_lock.acquire_async().await?;
+    ///
+    /// // Do some async work while the device is awake...
+    ///
+    /// // Release the wake lock to allow the device to sleep again.
+    /// drop(guard);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn acquire_async(&self) -> impl Future<Output = Result<Guard>> + Send + 'static {
+        self.acquire_task()
+    }
+
+    /// Acquire the wake lock, waiting for an existing guard to be released
+    /// first if the wake lock's hold budget is exhausted.
+    ///
+    /// This behaves like [`acquire_async`][WakeLock::acquire_async], except
+    /// that when the limit set with [`Builder::hold_budget`] has been reached,
+    /// the returned future waits until a guard is released instead of failing.
+    /// This lets async pipelines throttle themselves to a bounded amount of
+    /// concurrent work keeping the device awake. For wake locks without a hold
+    /// budget, this is the same as `acquire_async`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn process(_: u32) {}
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let wake_lock = android_wakelock::WakeLock::builder("myapp:pipeline")
+    ///     .hold_budget(4)
+    ///     .build()?;
+    ///
+    /// for item in 0..100 {
+    ///     // Waits while 4 items are already being processed.
+    ///     let guard = wake_lock.acquire_shared().await?;
+    ///
+    ///     // Process the item elsewhere, dropping the guard once done.
+    ///     # drop(guard);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn acquire_shared(&self) -> impl Future<Output = Result<Guard>> + Send + 'static {
+        let inner = self.inner.clone();
+        let caller = Location::caller();
+
+        async move {
+            let permit = match &inner.budget {
+                Some(budget) => Some(budget.acquire().await),
+                None => None,
+            };
+
+            worker::run(move || inner.acquire_on_worker(permit, caller)).await
+        }
+    }
+
+    /// Keep the device awake for the duration of the given future.
+    ///
+    /// The wake lock is acquired before the future is polled for the first
+    /// time, and released when the future completes or when the returned
+    /// future is dropped, such as when a task is cancelled. Like
+    /// [`acquire_async`][WakeLock::acquire_async], no JNI calls are made on
+    /// the executor's threads, so this works with any async runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn sync_data() {}
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let wake_lock = android_wakelock::partial("myapp:sync")?;
+    ///
+    /// // Hold a partial wake lock while the job runs.
+    /// wake_lock.keep_awake(sync_data()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn keep_awake<F>(&self, future: F) -> WithWakeLock<F>
+    where
+        F: Future,
+    {
+        future.with_wake_lock(self)
+    }
+
+    /// Keep the device awake while running the given async closure.
+    ///
+    /// The wake lock is acquired before the closure is called, and released
+    /// once the future it returns completes or when the returned future is
+    /// dropped, such as when a task is cancelled. This is the async analogue of
+    /// holding a [`Guard`] for the duration of a scope. Like
+    /// [`acquire_async`][WakeLock::acquire_async], no JNI calls are made on the
+    /// calling thread.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn upload(_: &str) -> usize { 0 }
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let wake_lock = android_wakelock::partial("myapp:upload")?;
+    ///
+    /// let uploaded = wake_lock
+    ///     .keep_awake_while(|| async {
+    ///         upload("photos").await + upload("videos").await
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn keep_awake_while<F, Fut>(&self, f: F) -> impl Future<Output = Result<Fut::Output>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future,
+    {
+        let acquire = self.acquire_task();
+
+        async move {
+            let guard = acquire.await?;
+            let output = f().await;
+
+            drop(guard);
+
+            Ok(output)
+        }
+    }
+
+    /// Submit an acquire to the worker thread. The resulting guard will also be
+    /// released on the worker thread.
+    #[track_caller]
+    fn acquire_task(&self) -> worker::Task<Result<Guard>> {
+        self.acquire_task_at(Location::caller())
+    }
+
+    /// Like [`acquire_task`][WakeLock::acquire_task], but recording the given
+    /// call site for the guard instead of the caller.
+    fn acquire_task_at(&self, caller: &'static Location<'static>) -> worker::Task<Result<Guard>> {
+        let inner = self.inner.clone();
+
+        worker::run(move || {
+            inner
+                .permit()
+                .and_then(|permit| inner.acquire_on_worker(permit, caller))
         })
     }
 }
 
+/// Summarizes the wake lock's tag, level, and how many guards have been
+/// holding it for how long, such as `"myapp:sync" (Partial): held by 2 guards
+/// for 1.53s`.
+///
+/// # Examples
+///
+/// ```no_run
+/// let wake_lock = android_wakelock::partial("myapp:sync")?;
+/// let _guard = wake_lock.acquire()?;
+///
+/// log::info!("{}", wake_lock);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+impl fmt::Display for WakeLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\" ({:?}): ", self.inner.tag, self.level())?;
+
+        match self.inner.guards.held_since() {
+            Some(since) => {
+                let guards = self.inner.guards.len();
+
+                write!(
+                    f,
+                    "held by {} guard{} for {:?}",
+                    guards,
+                    if guards == 1 { "" } else { "s" },
+                    truncate_millis(since.elapsed())
+                )
+            }
+            None => f.write_str("not held"),
+        }
+    }
+}
+
 /// A guard for an acquired wake lock.
 ///
 /// To create a guard see [`WakeLock::acquire`].
@@ -413,50 +2277,635 @@ impl WakeLock {
 /// panics if there is an error releasing the wake lock. If you want to handle
 /// errors on release then you can call [`Guard::release`] instead.
 ///
-/// The current thread will remain attached to the current JVM until the guard
-/// is released. The guard cannot be sent between threads.
-pub struct Guard<'a> {
-    /// Reference to the underlying Java object.
-    wake_lock: GlobalRef,
+/// A guard does not borrow the [`WakeLock`] it was acquired from, and keeps the
+/// underlying Java object alive until it is released. This means the
+/// `WakeLock` may be dropped while guards are still outstanding.
+///
+/// Guards can be sent between threads. Guards returned by
+/// [`WakeLock::acquire_async`] or acquired from a wake lock configured with
+/// [`Builder::release_in_background`] are released on the crate's background
+/// thread rather than the thread that drops them. The same happens if the
+/// dropping thread cannot be attached to the JVM. Errors releasing in the
+/// background are passed to the hook set with [`set_error_hook`] instead of
+/// causing a panic.
+///
+/// With the `tracing` feature, each guard that acquires the wake lock opens
+/// an info-level `wake_lock` span with the wake lock's `tag` and `timeout`,
+/// which is closed when the guard is dropped. Once the guard releases its
+/// reference, the span records the `reason` and how long it was `held`, with
+/// the same values passed to [`WakeLockObserver::on_released`].
+///
+/// With the `metrics` feature, guards also emit the following metrics through
+/// the [`metrics`](https://docs.rs/metrics) facade, all labeled with the wake
+/// lock's `tag`:
+///
+/// - `android_wakelock_acquires_total`: counter of guards that acquired the
+///   wake lock.
+/// - `android_wakelock_releases_total`: counter of guards that released it,
+///   additionally labeled with the `reason`.
+/// - `android_wakelock_held`: gauge of guards currently holding it.
+/// - `android_wakelock_held_seconds_total`: gauge of the total time guards
+///   have held it, which only ever increases.
+///
+/// With the `atrace` feature, each guard that acquires the wake lock also
+/// marks the time it is held as an async section named `WakeLock:<tag>` in
+/// system traces, such as those captured with Perfetto, and the number of
+/// guards holding wake locks with the same tag is published as a counter named
+/// `WakeLock guards:<tag>`. These are only recorded on API level 29 and
+/// higher. Sections are only recorded if tracing is enabled when the guard is
+/// acquired.
+///
+/// With the `audit` feature, a backtrace of where each guard acquired the wake
+/// lock is captured and kept until it is released. Backtraces are included in
+/// [`dump`] with the `dump` feature, and logged for each guard still alive
+/// when the wake lock is released from under them, such as by
+/// [`Builder::detect_forced_release`] or
+/// [`Builder::auto_release_below_battery`]. Capturing backtraces is slow, so
+/// this is meant for debugging builds.
+pub struct Guard {
+    inner: Arc<Inner>,
 
-    env: AttachGuard<'a>,
+    /// Identifies the guard among all guards in the process.
+    id: u64,
 
-    /// The tag specified when the wake lock was created.
-    tag: &'a str,
+    /// Whether the guard has nothing left to release, because it was already
+    /// released explicitly or never acquired the wake lock.
+    released: bool,
+
+    /// Whether the guard should be released on the worker thread.
+    on_worker: bool,
+
+    /// When the system releases the guard's reference if it was acquired with
+    /// a timeout, so that it may already have been released by the time the
+    /// guard is dropped.
+    deadline: Option<Instant>,
+
+    /// When the wake lock was acquired.
+    acquired_at: Instant,
+
+    /// Span covering the time the guard holds the wake lock.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+
+    /// Marks the time the guard holds the wake lock in system traces.
+    #[cfg(feature = "atrace")]
+    _trace: Option<atrace::Hold>,
+
+    /// Share of the wake lock's hold budget, returned once the guard is
+    /// dropped.
+    _permit: Option<Permit>,
 }
 
-impl Guard<'_> {
+impl Guard {
+    /// Get the ID of the guard, which is unique among all guards created in
+    /// the process.
+    ///
+    /// IDs are assigned in increasing order as guards are created. The ID is
+    /// included in the crate's debug logs, in the events passed to
+    /// [`WakeLockObserver`], and in the history log and [`dump`] with their
+    /// respective features, so that the acquire and release of a single guard
+    /// can be told apart from those of other guards with the same tag.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
     /// Releases the wake lock, returning an error if the underlying API threw
     /// an exception.
-    pub fn release(mut self) -> Result<()> {
-        self.release_one()
+    ///
+    /// For guards returned by [`WakeLock::acquire_async`], this blocks the
+    /// current thread until the release has been performed on the crate's
+    /// background thread.
+    pub fn release(self) -> Result<()> {
+        self.release_with_flags(0)
+    }
+
+    /// Releases the wake lock, but if the wake lock is a
+    /// [`Level::ProximityScreenOff`] wake lock that has turned the screen off,
+    /// keep the screen off until the proximity sensor reports that the object
+    /// has moved away.
+    ///
+    /// This is useful for calling apps ending a call while the device is still
+    /// held against the user's ear, to avoid the screen turning back on too
+    /// early. For other wake lock levels this behaves the same as
+    /// [`release`][Guard::release].
+    pub fn release_waiting_for_no_proximity(self) -> Result<()> {
+        self.release_with_flags(RELEASE_FLAG_WAIT_FOR_NO_PROXIMITY)
     }
 
-    fn release_one(&mut self) -> Result<()> {
-        catch_exceptions(&mut self.env, |env| {
-            env.call_method(&self.wake_lock, "release", "()V", &[])?;
+    /// Attribute the battery usage of the wake lock to the apps in the given
+    /// work source from now on, replacing any work source set previously.
+    ///
+    /// This allows a long-held wake lock to be re-attributed as the client it
+    /// is being held on behalf of changes. The work source applies to the
+    /// wake lock as a whole, including any other guards currently held for
+    /// it. See [`WorkSource`] for more information.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::WorkSource;
+    ///
+    /// # let (first_uid, second_uid) = (10123, 10124);
+    /// let wake_lock = android_wakelock::partial("myservice:client")?;
+    /// let guard = wake_lock.acquire()?;
+    ///
+    /// guard.update_work_source(&WorkSource::new().add(first_uid))?;
+    ///
+    /// // Later, a different client takes over...
+    /// guard.update_work_source(&WorkSource::new().add(second_uid))?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn update_work_source(&self, work_source: &WorkSource) -> Result<()> {
+        if self.on_worker {
+            let inner = self.inner.clone();
+            let work_source = work_source.clone();
 
-            log::debug!("released wake lock \"{}\"", self.tag);
+            worker::run(move || inner.set_work_source(&work_source)).wait()
+        } else {
+            self.inner.set_work_source(work_source)
+        }
+    }
 
-            Ok(())
+    fn release_with_flags(mut self, flags: i32) -> Result<()> {
+        if std::mem::replace(&mut self.released, true) {
+            return Ok(());
+        }
+
+        let result = if self.on_worker {
+            let inner = self.inner.clone();
+            let (id, deadline) = (self.id, self.deadline);
+
+            worker::run(move || inner.release(id, flags, deadline)).wait()
+        } else {
+            self.inner.release(self.id, flags, self.deadline)
+        };
+
+        self.take_release()
+            .notify_result(&result, ReleaseReason::Released);
+
+        result
+    }
+
+    /// Release the wake lock on the crate's background thread, without
+    /// blocking the current thread, for releasing from async tasks.
+    pub(crate) async fn release_on_worker(mut self) -> Result<()> {
+        if std::mem::replace(&mut self.released, true) {
+            return Ok(());
+        }
+
+        let inner = self.inner.clone();
+        let (id, deadline) = (self.id, self.deadline);
+        let result = worker::run(move || {
+            inner
+                .attach_worker()
+                .and_then(|_| inner.release(id, 0, deadline))
         })
+        .await;
+
+        self.take_release()
+            .notify_result(&result, ReleaseReason::Released);
+
+        result
+    }
+
+    /// Take what is needed to notify observers once the guard's reference is
+    /// released, which may only happen after the guard is gone.
+    fn take_release(&mut self) -> Release {
+        Release {
+            inner: self.inner.clone(),
+            id: self.id,
+            deadline: self.deadline,
+            acquired_at: self.acquired_at,
+            #[cfg(feature = "tracing")]
+            span: std::mem::replace(&mut self.span, tracing::Span::none()),
+        }
+    }
+}
+
+/// A guard's reference that is being released, which observers are notified
+/// about once the result is known.
+struct Release {
+    inner: Arc<Inner>,
+    id: u64,
+    deadline: Option<Instant>,
+    acquired_at: Instant,
+
+    /// The guard's span, which stays open until the release is reported.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl Release {
+    /// Queue the release to be performed on the worker thread. Errors are
+    /// passed to the error hook.
+    fn in_background(self) {
+        worker::execute(move || {
+            let result = self
+                .inner
+                .attach_worker()
+                .and_then(|_| self.inner.release(self.id, 0, self.deadline));
+
+            match result {
+                // The timeout expired before the guard was dropped, so the
+                // system already released it.
+                Err(e) if !(self.deadline.is_some() && e.kind() == ErrorKind::UnderLocked) => {
+                    self.inner.guards.remove(self.id);
+                    self.inner.report_error(&e);
+                }
+                _ => self.notify(ReleaseReason::Dropped),
+            }
+        });
+    }
+
+    /// Notify observers of the result of releasing the reference. The guard
+    /// is gone either way, so it no longer counts as active even if releasing
+    /// failed.
+    fn notify_result(&self, result: &Result<()>, reason: ReleaseReason) {
+        match result {
+            Ok(()) => self.notify(reason),
+            Err(e) => {
+                self.inner.guards.remove(self.id);
+                self.inner.notify_error(e);
+            }
+        }
+    }
+
+    /// Notify observers that the reference was released.
+    fn notify(&self, reason: ReleaseReason) {
+        let (held, reason) = match self.deadline {
+            Some(deadline) if deadline <= Instant::now() => {
+                (deadline - self.acquired_at, ReleaseReason::TimedOut)
+            }
+            _ => (self.acquired_at.elapsed(), reason),
+        };
+
+        #[cfg(feature = "tracing")]
+        self.span
+            .record("reason", tracing::field::debug(reason))
+            .record("held", tracing::field::debug(held));
+
+        self.inner.guards.remove(self.id);
+        stats::released(&self.inner.tag, held);
+
+        #[cfg(feature = "log")]
+        history::released(
+            &self.inner.tag,
+            self.id,
+            held,
+            reason,
+            self.acquired_at + held,
+        );
+
+        #[cfg(feature = "metrics")]
+        instrument::released(&self.inner.tag, held, reason);
+
+        observer::notify(self.inner.observer.as_ref(), |observer| {
+            observer.on_released(&self.inner.tag, self.id, held, reason)
+        });
     }
 }
 
-impl fmt::Debug for Guard<'_> {
+impl fmt::Debug for Guard {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Guard")
-            .field("wake_lock", &self.wake_lock)
-            .field("tag", &self.tag)
+            .field("id", &self.id)
+            .field("wake_lock", &self.inner.object().wake_lock)
+            .field("tag", &self.inner.tag)
             .finish()
     }
 }
 
-impl Drop for Guard<'_> {
+/// Summarizes the guard's ID, the tag and level of its wake lock, and how
+/// long it has been holding it, such as `guard 7 of "myapp:sync" (Partial):
+/// held for 1.53s`.
+impl fmt::Display for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "guard {} of \"{}\" ({:?}): ",
+            self.id,
+            self.inner.tag,
+            self.inner.object().config.level
+        )?;
+
+        match self.deadline {
+            _ if self.released => f.write_str("not held"),
+            Some(deadline) if deadline <= Instant::now() => write!(
+                f,
+                "timed out after {:?}",
+                truncate_millis(deadline - self.acquired_at)
+            ),
+            _ => write!(
+                f,
+                "held for {:?}",
+                truncate_millis(self.acquired_at.elapsed())
+            ),
+        }
+    }
+}
+
+impl Drop for Guard {
     fn drop(&mut self) {
-        if let Err(e) = self.release_one() {
-            panic!("error releasing wake lock \"{}\" on drop: {}", self.tag, e);
+        if self.released {
+            return;
+        }
+
+        // Observers are only notified once the release has happened, which
+        // may be on the worker thread.
+        let release = self.take_release();
+
+        if self.on_worker || self.inner.release_in_background {
+            release.in_background();
+            return;
+        }
+
+        let Some(vm) = &self.inner.vm else {
+            // No JVM is involved, so release directly.
+            let result = self.inner.release(self.id, 0, self.deadline);
+
+            release.notify_result(&result, ReleaseReason::Dropped);
+
+            if let Err(e) = result {
+                panic!(
+                    "error releasing wake lock \"{}\" on drop: {}",
+                    self.inner.tag, e
+                );
+            }
+
+            return;
+        };
+
+        // Releasing requires the current thread to be attached to the JVM. If
+        // that isn't possible here, hand the release off to the worker thread
+        // instead.
+        let mut env = match stats::timed(JniCall::Attach, || vm.attach_current_thread()) {
+            Ok(env) => env,
+            Err(e) => {
+                debug!(
+                    "failed to attach thread to release wake lock \"{}\" of guard {}, releasing in background: {}",
+                    self.inner.tag,
+                    self.id,
+                    e
+                );
+                release.in_background();
+                return;
+            }
+        };
+
+        match self.inner.release_with(&mut env, self.id, 0, self.deadline) {
+            Ok(()) => release.notify(ReleaseReason::Dropped),
+            // The thread was detached out from under us, so try again in the
+            // background instead.
+            Err(e) if e.is_thread_detached() => {
+                drop(env);
+                release.in_background();
+            }
+            // The timeout expired before the guard was dropped, so the system
+            // already released it.
+            Err(e) if self.deadline.is_some() && e.kind() == ErrorKind::UnderLocked => {
+                debug!(
+                    "timed wake lock \"{}\" of guard {} already released by the system",
+                    self.inner.tag, self.id
+                );
+                release.notify(ReleaseReason::Dropped);
+            }
+            Err(e) => {
+                self.inner.guards.remove(self.id);
+                self.inner.notify_error(&e);
+
+                panic!(
+                    "error releasing wake lock \"{}\" on drop: {}",
+                    self.inner.tag, e
+                )
+            }
+        }
+    }
+}
+
+/// Information about a guard currently holding a wake lock, returned by
+/// [`WakeLock::active_guards`].
+#[derive(Clone, Debug)]
+pub struct GuardInfo {
+    id: u64,
+    call_site: &'static Location<'static>,
+    held: Duration,
+    timeout: Option<Duration>,
+    reason: Option<ReleaseReason>,
+    #[cfg(feature = "audit")]
+    backtrace: Arc<std::backtrace::Backtrace>,
+}
+
+impl GuardInfo {
+    /// Get the [ID][Guard::id] of the guard.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Get where in the app's code the guard acquired the wake lock.
+    pub fn call_site(&self) -> &'static Location<'static> {
+        self.call_site
+    }
+
+    /// Get how long the guard has been holding the wake lock. For guards whose
+    /// timeout has expired, this is how long they held it until then.
+    pub fn held(&self) -> Duration {
+        self.held
+    }
+
+    /// Get the timeout the guard acquired the wake lock with, if any.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns true if the guard acquired the wake lock with a timeout that
+    /// has expired, so that the system has already released its reference
+    /// even though the guard is still alive.
+    pub fn timed_out(&self) -> bool {
+        self.reason == Some(ReleaseReason::TimedOut)
+    }
+
+    /// Get the reason the guard's reference to the wake lock was already
+    /// released even though the guard is still alive, such as
+    /// [`ReleaseReason::TimedOut`] once its timeout has expired. Returns `None`
+    /// while the guard is still holding the wake lock.
+    pub fn reason(&self) -> Option<ReleaseReason> {
+        self.reason
+    }
+
+    /// Get a backtrace of where the guard acquired the wake lock. Requires
+    /// the `audit` feature.
+    #[cfg(feature = "audit")]
+    pub fn backtrace(&self) -> &std::backtrace::Backtrace {
+        &self.backtrace
+    }
+}
+
+/// Truncate a duration to whole milliseconds, to keep it short when formatted.
+fn truncate_millis(duration: Duration) -> Duration {
+    Duration::from_millis(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// Number of local references reserved for a local frame. More can be created
+/// within the frame if needed.
+const LOCAL_FRAME_CAPACITY: i32 = 16;
+
+/// Get the JVM of the current Android app.
+fn java_vm() -> Result<JavaVM> {
+    let ctx = ndk_context::android_context();
+
+    Ok(unsafe { JavaVM::from_raw(ctx.vm().cast()) }?)
+}
+
+/// Returns true if the current Android app has been granted the given
+/// permission.
+fn has_permission(env: &mut JNIEnv<'_>, permission: &str) -> Result<bool> {
+    let ctx = ndk_context::android_context();
+    let permission = env.new_string(permission)?;
+
+    catch_exceptions(env, |env| {
+        // PackageManager.PERMISSION_GRANTED
+        Ok(env
+            .call_method(
+                unsafe { JObject::from_raw(ctx.context().cast()) },
+                "checkSelfPermission",
+                "(Ljava/lang/String;)I",
+                &[JValue::from(&permission)],
+            )?
+            .i()?
+            == 0)
+    })
+}
+
+/// Get the PowerManager system service, fetching it the first time.
+///
+/// The service is the same for the lifetime of the process, so it is kept in
+/// a global reference instead of being fetched again for every wake lock.
+fn power_manager(env: &mut JNIEnv<'_>) -> Result<GlobalRef> {
+    static POWER_MANAGER: OnceLock<GlobalRef> = OnceLock::new();
+
+    if let Some(power_manager) = POWER_MANAGER.get() {
+        return Ok(power_manager.clone());
+    }
+
+    let service = system_service(env, "power")?;
+    let power_manager = env.new_global_ref(&service)?;
+
+    // Not cached if unavailable, so that fetching it is attempted again.
+    if service.is_null() {
+        return Ok(power_manager);
+    }
+
+    Ok(POWER_MANAGER.get_or_init(|| power_manager).clone())
+}
+
+/// Fetch a system service of the current Android app by name.
+fn system_service<'local>(env: &mut JNIEnv<'local>, name: &str) -> Result<JObject<'local>> {
+    let ctx = ndk_context::android_context();
+    let name = env.new_string(name)?;
+
+    catch_exceptions(env, |env| {
+        env.call_method(
+            unsafe { JObject::from_raw(ctx.context().cast()) },
+            "getSystemService",
+            "(Ljava/lang/String;)Ljava/lang/Object;",
+            &[JValue::from(&name)],
+        )?
+        .l()
+    })
+}
+
+/// Acquire the given Java wake lock object.
+fn acquire_with(
+    env: &mut JNIEnv<'_>,
+    wake_lock: &GlobalRef,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    stats::timed(JniCall::Acquire, || {
+        catch_exceptions(env, |env| methods::acquire(env, wake_lock, timeout))
+    })
+}
+
+/// Check whether the given Java wake lock object is held.
+fn is_held_with(env: &mut JNIEnv<'_>, wake_lock: &GlobalRef) -> jni::errors::Result<bool> {
+    stats::timed(JniCall::IsHeld, || methods::is_held(env, wake_lock))
+}
+
+/// Release the given Java wake lock object.
+fn release_with(env: &mut JNIEnv<'_>, wake_lock: &GlobalRef, flags: i32) -> Result<()> {
+    stats::timed(JniCall::Release, || {
+        catch_exceptions(env, |env| methods::release(env, wake_lock, flags))
+    })
+    .map_err(|e| {
+        // Thrown by the platform when releasing more times than acquired.
+        if e.exception_class() == Some("java.lang.RuntimeException")
+            && e.exception_message()
+                .is_some_and(|message| message.starts_with("WakeLock under-locked"))
+        {
+            Error::under_locked(e)
+        } else {
+            e
         }
+    })
+}
+
+/// Set the work source of the given Java wake lock or Wi-Fi lock object.
+fn set_work_source(
+    env: &mut JNIEnv<'_>,
+    wake_lock: &GlobalRef,
+    work_source: &WorkSource,
+) -> Result<()> {
+    catch_exceptions(env, |env| {
+        let work_source = work_source.to_java(env)?;
+
+        env.call_method(
+            wake_lock,
+            "setWorkSource",
+            "(Landroid/os/WorkSource;)V",
+            &[JValue::from(&work_source)],
+        )?
+        .v()
+    })
+}
+
+/// Get the package name of the current Android app.
+fn package_name(env: &mut JNIEnv<'_>) -> Result<String> {
+    let ctx = ndk_context::android_context();
+
+    catch_exceptions(env, |env| {
+        let name = env
+            .call_method(
+                unsafe { JObject::from_raw(ctx.context().cast()) },
+                "getPackageName",
+                "()Ljava/lang/String;",
+                &[],
+            )?
+            .l()?;
+
+        java_string(env, name)
+    })?
+    .ok_or_else(|| Error::new(ErrorKind::Other, "app has no package name"))
+}
+
+/// Check whether a wake lock level is supported using the given PowerManager.
+fn level_supported(
+    env: &mut JNIEnv<'_>,
+    power_manager: &JObject<'_>,
+    level: Level,
+) -> Result<bool> {
+    let result = catch_exceptions(env, |env| {
+        env.call_method(
+            power_manager,
+            "isWakeLockLevelSupported",
+            "(I)Z",
+            &[JValue::from(level as i32)],
+        )?
+        .z()
+    });
+
+    match result {
+        // Devices older than API level 21 do not have a way of checking.
+        Err(e) if e.is_method_not_found() => Ok(true),
+        result => result,
     }
 }
 
@@ -467,20 +2916,38 @@ fn catch_exceptions<'a, T, F>(env: &mut jni::JNIEnv<'a>, f: F) -> Result<T>
 where
     F: FnOnce(&mut jni::JNIEnv<'a>) -> jni::errors::Result<T>,
 {
+    // Calling into Java with an exception already pending is not allowed. The
+    // exception belongs to unrelated code, so leave it for that code to handle
+    // unless a retry policy is running and has to clear it to try again.
+    if env.exception_check()? {
+        if !retry::is_retrying() {
+            return Err(Error::pending_exception(None));
+        }
+
+        let exception = env.exception_occurred()?;
+        env.exception_clear()?;
+
+        let error = describe_exception(env, &exception).unwrap_or_else(|e| {
+            let _ = env.exception_clear();
+            e.into()
+        });
+
+        warn!("cleared unrelated pending Java exception: {}", error);
+
+        return Err(Error::pending_exception(Some(error)));
+    }
+
     match f(env) {
         Ok(value) => Ok(value),
         Err(e @ jni::errors::Error::JavaException) => Err({
             if let Ok(exception) = env.exception_occurred() {
                 let _ = env.exception_clear();
 
-                env.call_method(exception, "getMessage", "()Ljava/lang/String;", &[])
-                    .and_then(|value| value.l())
-                    .and_then(|message| {
-                        env.get_string(&message.into())
-                            .map(|s| s.to_string_lossy().into_owned())
-                    })
-                    .map(|message| message.into())
-                    .unwrap_or_else(|_| e.into())
+                describe_exception(env, &exception).unwrap_or_else(|_| {
+                    // Describing the exception may have thrown another one.
+                    let _ = env.exception_clear();
+                    e.into()
+                })
             } else {
                 e.into()
             }
@@ -488,3 +2955,52 @@ where
         Err(e) => Err(e.into()),
     }
 }
+
+/// Extract the class name and message of a thrown exception.
+fn describe_exception(env: &mut JNIEnv<'_>, exception: &JObject<'_>) -> jni::errors::Result<Error> {
+    let class = env.get_object_class(exception)?;
+    let class_name = env
+        .call_method(&class, "getName", "()Ljava/lang/String;", &[])?
+        .l()?;
+    let message = env
+        .call_method(exception, "getMessage", "()Ljava/lang/String;", &[])?
+        .l()?;
+
+    let error = Error::exception(java_string(env, class_name)?, java_string(env, message)?);
+
+    #[cfg(feature = "verbose-errors")]
+    let error = {
+        let stack_trace = env
+            .call_static_method(
+                "android/util/Log",
+                "getStackTraceString",
+                "(Ljava/lang/Throwable;)Ljava/lang/String;",
+                &[JValue::from(exception)],
+            )
+            .and_then(|value| value.l())
+            .and_then(|value| java_string(env, value));
+
+        // A failure to get the stack trace should not hide the original
+        // exception.
+        if stack_trace.is_err() {
+            let _ = env.exception_clear();
+        }
+
+        error.with_stack_trace(stack_trace.ok().flatten())
+    };
+
+    Ok(error)
+}
+
+/// Convert a possibly null Java string into a Rust string.
+fn java_string(env: &mut JNIEnv<'_>, object: JObject<'_>) -> jni::errors::Result<Option<String>> {
+    if object.is_null() {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        env.get_string(&object.into())?
+            .to_string_lossy()
+            .into_owned(),
+    ))
+}
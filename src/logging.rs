@@ -0,0 +1,85 @@
+//! Log macros that forward to the `log` crate when the `log` feature is
+//! enabled, and compile to nothing otherwise or with the `no-instrumentation`
+//! feature.
+
+#[cfg(feature = "log")]
+use std::sync::RwLock;
+
+#[cfg(feature = "log")]
+static TARGET: RwLock<Option<&'static str>> = RwLock::new(None);
+
+/// Set the target used for all log messages emitted by this crate.
+///
+/// By default, messages are logged with the path of the module they come
+/// from, such as `android_wakelock::events`, like with any other crate. Setting
+/// a single target instead, such as `"power"`, makes it easy to filter for the
+/// crate's messages, for example with
+/// [`android_logger`](https://docs.rs/android_logger).
+///
+/// Requires the `log` feature, which is enabled by default.
+///
+/// # Examples
+///
+/// ```
+/// android_wakelock::set_log_target("power");
+/// ```
+#[cfg(feature = "log")]
+pub fn set_log_target(target: &'static str) {
+    *TARGET.write().unwrap_or_else(|e| e.into_inner()) = Some(target);
+}
+
+/// Get the target set with [`set_log_target`], if any.
+#[cfg(feature = "log")]
+#[cfg_attr(feature = "no-instrumentation", allow(dead_code))]
+pub(crate) fn target() -> Option<&'static str> {
+    *TARGET.read().unwrap_or_else(|e| e.into_inner())
+}
+
+macro_rules! log_at {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(all(feature = "log", not(feature = "no-instrumentation")))]
+        ::log::$level!(
+            target: $crate::logging::target().unwrap_or(module_path!()),
+            $($arg)+
+        );
+
+        // Still type check the arguments, and keep them from being unused,
+        // without evaluating them.
+        #[cfg(any(not(feature = "log"), feature = "no-instrumentation"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+// Only used with some features.
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        log_at!(trace, $($arg)+)
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        log_at!(debug, $($arg)+)
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => {
+        log_at!(info, $($arg)+)
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        log_at!(warn, $($arg)+)
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => {
+        log_at!(error, $($arg)+)
+    };
+}
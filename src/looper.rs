@@ -0,0 +1,156 @@
+//! Running closures on the app's main thread.
+
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use jni::objects::JValue;
+
+use crate::{
+    callback, catch_exceptions, java_vm,
+    worker::{self, Task},
+    Error, ErrorKind, Result,
+};
+
+const RUNNABLE_CLASS: &str = "rs/android_wakelock/NativeRunnable";
+
+/// Run a closure on the app's main (UI) thread.
+///
+/// Many Android APIs, such as changing window flags with the functions in the
+/// [`screen`][crate::screen] module, may only be called on the main thread.
+/// This posts the closure to a `Handler` for the main thread's `Looper`, and
+/// returns right away with a [`MainLooperTask`] that can be used to wait for
+/// its result. The closure runs after any messages already queued for the
+/// main thread, even when called from the main thread itself.
+///
+/// This uses one of the crate's Java support classes. See the [crate-level
+/// documentation][crate#platform-support] for details.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::screen;
+///
+/// let guard = android_wakelock::run_on_main_looper(|| {
+///     screen::keep_screen_on(&screen::current_activity())
+/// })?
+/// .wait()??;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn run_on_main_looper<T, F>(f: F) -> Result<MainLooperTask<T>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let class = callback::load_class(&mut env, RUNNABLE_CLASS)?;
+
+    let (task, complete) = worker::pending();
+    let job = Mutex::new(Some((f, complete)));
+
+    // The handle is only known once registered, but the runnable cannot run
+    // before it is posted below.
+    let handle = Arc::new(AtomicI64::new(0));
+    let handle_id = callback::register({
+        let handle = handle.clone();
+
+        move |_, _, _| {
+            callback::unregister(handle.load(Ordering::SeqCst));
+
+            let job = job.lock().unwrap_or_else(|e| e.into_inner()).take();
+
+            if let Some((f, complete)) = job {
+                // Complete the task even if the closure panics, so that
+                // waiting on it does not block forever.
+                complete(panic::catch_unwind(AssertUnwindSafe(f)).ok());
+            }
+        }
+    });
+    handle.store(handle_id, Ordering::SeqCst);
+
+    let posted = catch_exceptions(&mut env, |env| {
+        let looper = env
+            .call_static_method(
+                "android/os/Looper",
+                "getMainLooper",
+                "()Landroid/os/Looper;",
+                &[],
+            )?
+            .l()?;
+        let handler = env.new_object(
+            "android/os/Handler",
+            "(Landroid/os/Looper;)V",
+            &[JValue::from(&looper)],
+        )?;
+        let runnable = env.new_object(&class, "(J)V", &[JValue::from(handle_id)])?;
+
+        env.call_method(
+            &handler,
+            "post",
+            "(Ljava/lang/Runnable;)Z",
+            &[JValue::from(&runnable)],
+        )?
+        .z()
+    });
+
+    match posted {
+        Ok(true) => Ok(MainLooperTask { task }),
+        Ok(false) => {
+            callback::unregister(handle_id);
+
+            Err(Error::new(
+                ErrorKind::Other,
+                "the main looper is exiting and no longer runs posted closures",
+            ))
+        }
+        Err(e) => {
+            callback::unregister(handle_id);
+
+            Err(e)
+        }
+    }
+}
+
+/// A handle to a closure posted to the main thread with
+/// [`run_on_main_looper`].
+///
+/// The closure's result can be retrieved either by awaiting the task as a
+/// future, or by blocking the current thread with [`MainLooperTask::wait`].
+/// Dropping the task does not prevent the closure from running.
+///
+/// If the closure panics, the result is an error of kind [`ErrorKind::Other`].
+#[derive(Debug)]
+pub struct MainLooperTask<T> {
+    task: Task<Option<T>>,
+}
+
+impl<T> MainLooperTask<T> {
+    /// Block the current thread until the closure has run on the main thread
+    /// and return its result.
+    ///
+    /// This must not be called on the main thread itself, since the closure
+    /// could then never run.
+    pub fn wait(self) -> Result<T> {
+        finish(self.task.wait())
+    }
+}
+
+impl<T> Future for MainLooperTask<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().task).poll(cx).map(finish)
+    }
+}
+
+fn finish<T>(value: Option<T>) -> Result<T> {
+    value.ok_or_else(|| Error::new(ErrorKind::Other, "closure run on the main looper panicked"))
+}
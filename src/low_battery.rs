@@ -0,0 +1,236 @@
+//! Releasing wake locks automatically when the battery runs low.
+
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    battery,
+    events::{self, BatteryWatch, PowerEvent},
+    hook, timer, worker, Error, ErrorKind, Inner, Result,
+};
+
+/// How often the battery level is checked while a wake lock is held.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Callback notified with the battery level when a wake lock is released
+/// because the battery is low.
+type Callback = Arc<dyn Fn(u8) + Send + Sync>;
+
+/// Low battery options set with
+/// [`Builder::auto_release_below_battery`][crate::Builder::auto_release_below_battery].
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub(crate) threshold: u8,
+    pub(crate) callback: Option<Callback>,
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("threshold", &self.threshold)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/// Monitors the battery level on behalf of a wake lock while it is held.
+#[derive(Debug)]
+pub(crate) struct Monitor {
+    config: Config,
+
+    /// Whether a check is currently scheduled.
+    running: AtomicBool,
+
+    /// Registration for battery events while running, which trigger a check
+    /// right away instead of waiting for the next scheduled one.
+    watch: Mutex<Option<BatteryWatch>>,
+}
+
+impl Monitor {
+    pub(crate) fn new(config: Config) -> Self {
+        Self {
+            config,
+            running: AtomicBool::new(false),
+            watch: Mutex::new(None),
+        }
+    }
+
+    /// Return an error if the battery is currently below the threshold and not
+    /// charging.
+    pub(crate) fn check(&self, tag: &str) -> Result<()> {
+        match self.low_level() {
+            Ok(Some(level)) => Err(Error::new(
+                ErrorKind::BatteryLow,
+                format!(
+                    "battery level {}% is below the threshold of {}% for wake lock \"{}\"",
+                    level, self.config.threshold, tag
+                ),
+            )),
+            Ok(None) => Ok(()),
+            // Do not prevent acquiring on devices without a battery.
+            Err(e) if e.kind() == ErrorKind::Unsupported => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Start checking the battery level periodically, unless already doing so.
+    pub(crate) fn start(inner: &Arc<Inner>) {
+        if let Some(monitor) = &inner.low_battery {
+            if !monitor.running.swap(true, Ordering::SeqCst) {
+                monitor.watch(Arc::downgrade(inner));
+                schedule(Arc::downgrade(inner));
+            }
+        }
+    }
+
+    /// Check right away when the battery becomes low or the charger is
+    /// disconnected. Falls back to only checking periodically if battery
+    /// events cannot be received.
+    fn watch(&self, inner: Weak<Inner>) {
+        let watch = events::watch_battery(move |event| {
+            if matches!(
+                event,
+                PowerEvent::BatteryLow | PowerEvent::PowerDisconnected
+            ) {
+                let inner = inner.clone();
+
+                worker::execute(move || {
+                    if let Some(inner) = inner.upgrade() {
+                        check_now(&inner);
+                    }
+                });
+            }
+        });
+
+        match watch {
+            Ok(watch) => *self.lock_watch() = Some(watch),
+            Err(e) => debug!("not watching battery events: {}", e),
+        }
+    }
+
+    /// Stop checking the battery level.
+    fn stop(&self) {
+        let watch = self.lock_watch().take();
+
+        self.running.store(false, Ordering::SeqCst);
+        drop(watch);
+    }
+
+    fn lock_watch(&self) -> MutexGuard<'_, Option<BatteryWatch>> {
+        self.watch.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get the battery level if it is below the threshold and not charging.
+    fn low_level(&self) -> Result<Option<u8>> {
+        let level = battery::level()?;
+
+        if level >= self.config.threshold || battery::is_charging()? {
+            Ok(None)
+        } else {
+            Ok(Some(level))
+        }
+    }
+}
+
+fn schedule(inner: Weak<Inner>) {
+    timer::schedule(Instant::now() + POLL_INTERVAL, move || {
+        // Check on the worker thread, since it involves JNI calls.
+        worker::execute(move || {
+            if let Some(inner) = inner.upgrade() {
+                poll(&inner);
+            }
+        });
+    });
+}
+
+/// Release every reference to the wake lock if the battery is low, otherwise
+/// check again later. Stops once the wake lock is no longer held.
+fn poll(inner: &Arc<Inner>) {
+    let Some(monitor) = &inner.low_battery else {
+        return;
+    };
+
+    {
+        let object = inner.object();
+        let now = Instant::now();
+
+        // Checked while holding the lock, so that a concurrent acquire either
+        // is seen here or sees the monitor as stopped and restarts it.
+        if object.held == 0 && object.deadlines.iter().all(|d| *d <= now) {
+            monitor.stop();
+            return;
+        }
+    }
+
+    let level = match monitor.low_level() {
+        Ok(Some(level)) => level,
+        Ok(None) => return schedule(Arc::downgrade(inner)),
+        Err(e) => {
+            hook::report_error(&inner.tag, &e);
+            return schedule(Arc::downgrade(inner));
+        }
+    };
+
+    revoke_and_report(inner);
+    monitor.stop();
+    notify(inner, monitor, level);
+}
+
+/// Release every reference to the wake lock if the battery is low, in
+/// response to a battery event. Scheduled checks continue as before, and stop
+/// once they see the wake lock is no longer held.
+fn check_now(inner: &Arc<Inner>) {
+    let Some(monitor) = &inner.low_battery else {
+        return;
+    };
+
+    {
+        let object = inner.object();
+        let now = Instant::now();
+
+        if object.held == 0 && object.deadlines.iter().all(|d| *d <= now) {
+            return;
+        }
+    }
+
+    match monitor.low_level() {
+        Ok(Some(level)) => {
+            revoke_and_report(inner);
+            notify(inner, monitor, level);
+        }
+        Ok(None) => {}
+        Err(e) => hook::report_error(&inner.tag, &e),
+    }
+}
+
+fn revoke_and_report(inner: &Inner) {
+    if let Err(e) = inner.attach_worker().and_then(|_| inner.revoke()) {
+        inner.report_error(&e);
+    }
+}
+
+/// Log and notify the callback that the wake lock was released.
+fn notify(inner: &Inner, monitor: &Monitor, level: u8) {
+    warn!(
+        "released wake lock \"{}\" because the battery level {}% is below {}%",
+        inner.tag, level, monitor.config.threshold
+    );
+
+    #[cfg(feature = "audit")]
+    inner.guards.warn_backtraces(&inner.tag);
+
+    // The callback runs on the worker thread, whose other jobs must not be
+    // affected if it panics.
+    if let Some(callback) = &monitor.config.callback {
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| callback(level))) {
+            hook::report_panic(&inner.tag, "the low battery callback", payload);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Calling methods of `PowerManager.WakeLock` through cached method IDs, so
+//! that acquiring and releasing does not look up the methods by name and
+//! signature every time.
+
+use std::{sync::OnceLock, time::Duration};
+
+use jni::{
+    objects::{GlobalRef, JMethodID, JValue},
+    signature::{Primitive, ReturnType},
+    JNIEnv,
+};
+
+const WAKE_LOCK_CLASS: &str = "android/os/PowerManager$WakeLock";
+
+static METHODS: OnceLock<Methods> = OnceLock::new();
+
+struct Methods {
+    /// Keeps the class from being unloaded, which would invalidate the method
+    /// IDs.
+    _class: GlobalRef,
+    acquire: JMethodID,
+    acquire_timeout: JMethodID,
+    release: JMethodID,
+    release_flags: JMethodID,
+    is_held: JMethodID,
+}
+
+/// Get the method IDs, looking them up the first time.
+fn methods(env: &mut JNIEnv<'_>) -> jni::errors::Result<&'static Methods> {
+    if let Some(methods) = METHODS.get() {
+        return Ok(methods);
+    }
+
+    // Errors are not cached, so the lookup is attempted again next time.
+    let class = env.find_class(WAKE_LOCK_CLASS)?;
+    let methods = Methods {
+        acquire: env.get_method_id(&class, "acquire", "()V")?,
+        acquire_timeout: env.get_method_id(&class, "acquire", "(J)V")?,
+        release: env.get_method_id(&class, "release", "()V")?,
+        release_flags: env.get_method_id(&class, "release", "(I)V")?,
+        is_held: env.get_method_id(&class, "isHeld", "()Z")?,
+        _class: env.new_global_ref(class)?,
+    };
+
+    Ok(METHODS.get_or_init(|| methods))
+}
+
+/// Call `acquire` on the given wake lock, with the given timeout if any.
+pub(crate) fn acquire(
+    env: &mut JNIEnv<'_>,
+    wake_lock: &GlobalRef,
+    timeout: Option<Duration>,
+) -> jni::errors::Result<()> {
+    let methods = methods(env)?;
+
+    // SAFETY: The method IDs belong to the class of all wake lock objects,
+    // and the arguments and return types match their signatures.
+    unsafe {
+        match timeout {
+            Some(timeout) => {
+                let millis = i64::try_from(timeout.as_millis()).unwrap_or(i64::MAX);
+
+                env.call_method_unchecked(
+                    wake_lock,
+                    methods.acquire_timeout,
+                    ReturnType::Primitive(Primitive::Void),
+                    &[JValue::from(millis).as_jni()],
+                )?
+            }
+            None => env.call_method_unchecked(
+                wake_lock,
+                methods.acquire,
+                ReturnType::Primitive(Primitive::Void),
+                &[],
+            )?,
+        }
+    }
+    .v()
+}
+
+/// Call `release` on the given wake lock, with the given flags if any.
+pub(crate) fn release(
+    env: &mut JNIEnv<'_>,
+    wake_lock: &GlobalRef,
+    flags: i32,
+) -> jni::errors::Result<()> {
+    let methods = methods(env)?;
+
+    // SAFETY: See `acquire`.
+    unsafe {
+        if flags == 0 {
+            env.call_method_unchecked(
+                wake_lock,
+                methods.release,
+                ReturnType::Primitive(Primitive::Void),
+                &[],
+            )?
+        } else {
+            env.call_method_unchecked(
+                wake_lock,
+                methods.release_flags,
+                ReturnType::Primitive(Primitive::Void),
+                &[JValue::from(flags).as_jni()],
+            )?
+        }
+    }
+    .v()
+}
+
+/// Call `isHeld` on the given wake lock.
+pub(crate) fn is_held(env: &mut JNIEnv<'_>, wake_lock: &GlobalRef) -> jni::errors::Result<bool> {
+    let methods = methods(env)?;
+
+    // SAFETY: See `acquire`.
+    unsafe {
+        env.call_method_unchecked(
+            wake_lock,
+            methods.is_held,
+            ReturnType::Primitive(Primitive::Boolean),
+            &[],
+        )?
+    }
+    .z()
+}
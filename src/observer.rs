@@ -0,0 +1,163 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use crate::Error;
+
+/// Why a guard released its reference to a wake lock, as reported to
+/// [`WakeLockObserver::on_released`].
+///
+/// New reasons may be added in the future, so code matching on this should
+/// include a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ReleaseReason {
+    /// The guard was released explicitly, such as with
+    /// [`Guard::release`][crate::Guard::release].
+    Released,
+
+    /// The guard was dropped.
+    Dropped,
+
+    /// The guard was acquired with a timeout, which expired before the guard
+    /// was released or dropped.
+    TimedOut,
+}
+
+/// Receives notifications about the lifecycle of wake locks, for example to
+/// feed an app's own analytics or telemetry.
+///
+/// An observer can be set for all wake locks with [`set_observer`], or for a
+/// single wake lock with [`Builder::observer`][crate::Builder::observer]. When
+/// both are set, both are notified. All methods do nothing by default, so only
+/// the ones of interest need to be implemented.
+///
+/// Observers are called synchronously from whichever thread acquires or
+/// releases a wake lock, including the crate's background thread, and should
+/// return quickly.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use android_wakelock::{ReleaseReason, WakeLockObserver};
+///
+/// struct Telemetry;
+///
+/// impl WakeLockObserver for Telemetry {
+///     fn on_released(&self, tag: &str, guard: u64, held: Duration, reason: ReleaseReason) {
+///         println!("{} was held by guard {} for {:?} ({:?})", tag, guard, held, reason);
+///     }
+/// }
+///
+/// android_wakelock::set_observer(Telemetry);
+/// ```
+pub trait WakeLockObserver: Send + Sync {
+    /// Called after a wake lock is acquired, with the [ID][crate::Guard::id]
+    /// of the guard that acquired it, and the timeout it was acquired with, if
+    /// any.
+    fn on_acquired(&self, _tag: &str, _guard: u64, _timeout: Option<Duration>) {}
+
+    /// Called when a guard releases its reference to a wake lock, with the
+    /// guard's [ID][crate::Guard::id], how long the reference was held and why
+    /// it was released.
+    ///
+    /// This is only called once the reference was actually released, which
+    /// for guards released in the background happens later on the crate's
+    /// background thread. If releasing fails, [`on_error`][Self::on_error] is
+    /// called instead.
+    ///
+    /// The ID matches the one passed to [`on_acquired`][Self::on_acquired]
+    /// when the guard acquired the wake lock, so that the two can be matched
+    /// up when several guards hold wake locks with the same tag.
+    fn on_released(&self, _tag: &str, _guard: u64, _held: Duration, _reason: ReleaseReason) {}
+
+    /// Called when acquiring or releasing a wake lock fails. The error is also
+    /// returned to the caller or passed to the error hook as usual.
+    fn on_error(&self, _tag: &str, _error: &Error) {}
+
+    /// Called once when the device is shutting down, after all wake locks were
+    /// released by
+    /// [`policies::release_all_on_shutdown`][crate::policies::release_all_on_shutdown].
+    /// This is the last notification an observer receives, and is the place to
+    /// flush any buffered data before the process is killed.
+    fn on_shutdown(&self) {}
+}
+
+static OBSERVER: RwLock<Option<Arc<dyn WakeLockObserver>>> = RwLock::new(None);
+
+/// Set once observers have been notified of shutdown, after which they are
+/// not notified anymore.
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Set a process-wide observer to be notified about the lifecycle of all wake
+/// locks.
+///
+/// Setting an observer replaces any previously set observer. See
+/// [`WakeLockObserver`] for details.
+pub fn set_observer<O>(observer: O)
+where
+    O: WakeLockObserver + 'static,
+{
+    *OBSERVER.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(observer));
+}
+
+/// An observer set for a single wake lock.
+#[derive(Clone)]
+pub(crate) struct Observer(pub(crate) Arc<dyn WakeLockObserver>);
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observer").finish_non_exhaustive()
+    }
+}
+
+/// Notify the given wake lock's observer, if any, and the process-wide
+/// observer.
+pub(crate) fn notify<F>(observer: Option<&Observer>, f: F)
+where
+    F: Fn(&dyn WakeLockObserver),
+{
+    if cfg!(feature = "no-instrumentation") || SHUT_DOWN.load(Ordering::SeqCst) {
+        return;
+    }
+
+    if let Some(observer) = observer {
+        f(&*observer.0);
+    }
+
+    // Do not hold the lock while calling out, so that the observer may be
+    // replaced from within a callback.
+    let global = OBSERVER.read().unwrap_or_else(|e| e.into_inner()).clone();
+
+    if let Some(observer) = global {
+        f(&*observer);
+    }
+}
+
+/// Notify the given observers of wake locks, and the process-wide observer, of
+/// shutdown. Each observer is notified once, even if it was set for several
+/// wake locks.
+pub(crate) fn notify_shutdown<'a, I>(observers: I)
+where
+    I: IntoIterator<Item = &'a Observer>,
+{
+    if cfg!(feature = "no-instrumentation") || SHUT_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let mut notified: Vec<&Arc<dyn WakeLockObserver>> = Vec::new();
+    let global = OBSERVER.read().unwrap_or_else(|e| e.into_inner()).clone();
+
+    for observer in observers.into_iter().map(|o| &o.0).chain(global.as_ref()) {
+        if !notified.iter().any(|o| Arc::ptr_eq(o, observer)) {
+            observer.on_shutdown();
+            notified.push(observer);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Rules that acquire and release wake locks automatically in response to
+//! platform events.
+//!
+//! Each rule is active for as long as the handle returned for it is alive, and
+//! releases anything it holds when dropped.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    battery,
+    callback::IntentReceiver,
+    consts::ACTION_SHUTDOWN,
+    events::{self, BatteryWatch, PowerEvent},
+    hook, java_vm, observer, registry, worker, Error, Guard, Result, WakeLock,
+};
+
+/// Hold the given wake lock whenever the device is plugged in, and release it
+/// when it is unplugged.
+///
+/// This is useful for kiosk apps, or apps that sync large amounts of data only
+/// while charging. The wake lock is acquired right away if the device is
+/// already plugged in. The rule follows the power connected and disconnected
+/// events described in [`events::battery`], and shares its broadcast receiver.
+///
+/// All JNI calls in response to events, as well as the initial check, are made
+/// on the crate's background thread. Errors acquiring the wake lock are passed
+/// to the hook set with [`set_error_hook`][crate::set_error_hook].
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::policies;
+///
+/// let wake_lock = android_wakelock::partial("myapp:sync-on-charger")?;
+/// let _rule = policies::hold_while_charging(&wake_lock)?;
+///
+/// // The wake lock is held while plugged in, until the rule is dropped.
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn hold_while_charging(wake_lock: &WakeLock) -> Result<HoldWhileCharging> {
+    let shared = Arc::new(Shared {
+        wake_lock: WakeLock {
+            inner: wake_lock.inner.clone(),
+        },
+        state: Mutex::new(State {
+            guard: None,
+            active: true,
+        }),
+    });
+
+    // Subscribe first, so that no change is missed after checking the
+    // initial state. The check is queued on the worker thread behind any
+    // events received so far, so that updates are applied in order.
+    let watch = events::watch_battery({
+        let shared = shared.clone();
+
+        move |event| {
+            let plugged_in = match event {
+                PowerEvent::PowerConnected => true,
+                PowerEvent::PowerDisconnected => false,
+                _ => return,
+            };
+            let shared = shared.clone();
+
+            worker::execute(move || shared.update(plugged_in));
+        }
+    })?;
+
+    worker::run({
+        let shared = shared.clone();
+
+        move || {
+            if battery::plug_type()?.is_some() {
+                shared.update(true);
+            }
+
+            Ok::<_, Error>(())
+        }
+    })
+    .wait()?;
+
+    Ok(HoldWhileCharging { shared, watch })
+}
+
+/// A rule created by [`hold_while_charging`], which holds a wake lock while
+/// the device is plugged in until it is dropped.
+pub struct HoldWhileCharging {
+    shared: Arc<Shared>,
+    watch: BatteryWatch,
+}
+
+struct Shared {
+    wake_lock: WakeLock,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// Held while the device is plugged in.
+    guard: Option<Guard>,
+
+    /// Whether the rule has not been dropped yet.
+    active: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Acquire or release the wake lock for the given plug state, without
+    /// holding the lock while calling into Java.
+    fn update(&self, plugged_in: bool) {
+        if !plugged_in {
+            let guard = self.lock().guard.take();
+
+            drop(guard);
+            return;
+        }
+
+        {
+            let state = self.lock();
+
+            if !state.active || state.guard.is_some() {
+                return;
+            }
+        }
+
+        match self.wake_lock.acquire() {
+            Ok(guard) => {
+                let mut state = self.lock();
+
+                // The rule may have been dropped in the meantime.
+                if state.active && state.guard.is_none() {
+                    state.guard = Some(guard);
+                } else {
+                    drop(state);
+                    drop(guard);
+                }
+            }
+            Err(e) => hook::report_error(&self.wake_lock.inner.tag, &e),
+        }
+    }
+}
+
+impl HoldWhileCharging {
+    /// Returns true if the rule is currently holding the wake lock.
+    pub fn is_holding(&self) -> bool {
+        self.shared.lock().guard.is_some()
+    }
+}
+
+impl fmt::Debug for HoldWhileCharging {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HoldWhileCharging")
+            .field("tag", &self.shared.wake_lock.inner.tag)
+            .field("watch", &self.watch)
+            .field("holding", &self.is_holding())
+            .finish()
+    }
+}
+
+impl Drop for HoldWhileCharging {
+    fn drop(&mut self) {
+        // Events already queued on the background thread may still arrive, so
+        // keep them from acquiring the wake lock again.
+        let guard = {
+            let mut state = self.shared.lock();
+
+            state.active = false;
+            state.guard.take()
+        };
+
+        drop(guard);
+    }
+}
+
+/// Release every wake lock held by the app when the device shuts down.
+///
+/// Wake locks still held while the device powers off are attributed to the
+/// app in the battery statistics, and may get it flagged as misbehaving. This
+/// listens for `Intent.ACTION_SHUTDOWN`, and in response releases all
+/// references to all wake locks in the process, leaving any remaining guards
+/// with nothing to release, like
+/// [`Builder::auto_release_below_battery`][crate::Builder::auto_release_below_battery]
+/// does.
+///
+/// Afterwards, all observers are notified with
+/// [`WakeLockObserver::on_shutdown`][crate::WakeLockObserver::on_shutdown] so
+/// that they can flush their data, and are not notified about anything else
+/// from then on.
+///
+/// The wake locks are released on the main thread, where the broadcast is
+/// delivered, since the device may power off before the crate's background
+/// thread gets to it. Errors releasing them are passed to the hook set with
+/// [`set_error_hook`][crate::set_error_hook]. Wake locks using the
+/// [sysfs backend][crate::Backend::Sysfs] are not released.
+///
+/// This uses one of the crate's Java support classes. See the [crate-level
+/// documentation][crate#platform-support] for details.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::policies;
+///
+/// // Keep the rule for the lifetime of the app.
+/// let _rule = policies::release_all_on_shutdown()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn release_all_on_shutdown() -> Result<ReleaseOnShutdown> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    let receiver =
+        IntentReceiver::register(&mut env, "shutdown monitor", &[ACTION_SHUTDOWN], |_, _| {
+            release_all()
+        })?;
+
+    Ok(ReleaseOnShutdown { receiver })
+}
+
+/// A rule created by [`release_all_on_shutdown`], which releases all wake
+/// locks when the device shuts down until it is dropped.
+pub struct ReleaseOnShutdown {
+    receiver: IntentReceiver,
+}
+
+impl fmt::Debug for ReleaseOnShutdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReleaseOnShutdown")
+            .field("receiver", &self.receiver)
+            .finish()
+    }
+}
+
+fn release_all() {
+    let wake_locks = registry::wake_locks();
+
+    info!(
+        "device is shutting down, releasing {} wake locks",
+        wake_locks.len()
+    );
+
+    for inner in &wake_locks {
+        if inner.vm.is_none() {
+            continue;
+        }
+
+        if let Err(e) = inner.revoke() {
+            inner.report_error(&e);
+        }
+    }
+
+    observer::notify_shutdown(
+        wake_locks
+            .iter()
+            .filter_map(|inner| inner.observer.as_ref()),
+    );
+}
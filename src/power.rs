@@ -0,0 +1,515 @@
+//! Queries about the power state of the device.
+//!
+//! These wrap `PowerManager` methods that are useful when deciding whether to
+//! acquire a wake lock, or which level to use.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use android_wakelock::power;
+//!
+//! if power::is_interactive()? {
+//!     // The user is likely using the device...
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::time::Duration;
+
+use jni::{
+    objects::{JObject, JValue},
+    JNIEnv,
+};
+
+use crate::{
+    catch_exceptions, java_vm, package_name, power_manager, sys, Error, ErrorKind, Result,
+};
+
+/// Returns true if the device is in an interactive state.
+///
+/// This wraps `PowerManager.isInteractive()`. A device is interactive when it
+/// is awake and ready to interact with the user, which typically means the
+/// screen is on. Note that being interactive does not guarantee that the
+/// screen is visible to the user: it may be dimmed, covered, or the screen
+/// may be off while a proximity wake lock is held.
+///
+/// On devices older than API level 20, `PowerManager.isScreenOn()` is used
+/// instead.
+pub fn is_interactive() -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let power_manager = power_manager(&mut env)?;
+
+    let result = catch_exceptions(&mut env, |env| {
+        env.call_method(&power_manager, "isInteractive", "()Z", &[])?
+            .z()
+    });
+
+    match result {
+        Err(e) if e.is_method_not_found() => catch_exceptions(&mut env, |env| {
+            env.call_method(&power_manager, "isScreenOn", "()Z", &[])?
+                .z()
+        }),
+        result => result,
+    }
+}
+
+/// Returns true if the device is currently in power save mode, also known as
+/// battery saver.
+///
+/// This wraps `PowerManager.isPowerSaveMode()`. When power save mode is on,
+/// apps are expected to reduce their battery usage, for example by skipping
+/// optional background work and the wake locks that come with it.
+///
+/// Power save mode was added in API level 21; on older devices this always
+/// returns false. Use [`events::power_save_mode`][crate::events::power_save_mode]
+/// to be notified when it changes instead of polling.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::power;
+///
+/// # fn sync_optional_data() {}
+/// if !power::is_power_save_mode()? {
+///     let wake_lock = android_wakelock::partial("myapp:sync")?;
+///     let _guard = wake_lock.acquire()?;
+///
+///     sync_optional_data();
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn is_power_save_mode() -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    power_save_mode(&mut env)
+}
+
+pub(crate) fn power_save_mode(env: &mut JNIEnv<'_>) -> Result<bool> {
+    let power_manager = power_manager(env)?;
+
+    let result = catch_exceptions(env, |env| {
+        env.call_method(&power_manager, "isPowerSaveMode", "()Z", &[])?
+            .z()
+    });
+
+    match result {
+        Err(e) if e.is_method_not_found() => Ok(false),
+        result => result,
+    }
+}
+
+/// Returns true if the current app is exempt from battery optimizations.
+///
+/// This wraps `PowerManager.isIgnoringBatteryOptimizations(String)` for the
+/// current app's package. Apps that are not exempt are subject to Doze and App
+/// Standby restrictions, which may defer their background work even while a
+/// wake lock is held.
+///
+/// Battery optimizations were added in API level 23; on older devices this
+/// always returns true.
+pub fn is_ignoring_battery_optimizations() -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let package = package_name(&mut env)?;
+
+    ignoring_battery_optimizations(&mut env, &package)
+}
+
+/// Returns true if the app with the given package name is exempt from battery
+/// optimizations.
+///
+/// See [`is_ignoring_battery_optimizations`] for more information.
+pub fn is_package_ignoring_battery_optimizations(package: &str) -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    ignoring_battery_optimizations(&mut env, package)
+}
+
+fn ignoring_battery_optimizations(env: &mut JNIEnv<'_>, package: &str) -> Result<bool> {
+    let power_manager = power_manager(env)?;
+    let package = env.new_string(package)?;
+
+    let result = catch_exceptions(env, |env| {
+        env.call_method(
+            &power_manager,
+            "isIgnoringBatteryOptimizations",
+            "(Ljava/lang/String;)Z",
+            &[JValue::from(&package)],
+        )?
+        .z()
+    });
+
+    match result {
+        Err(e) if e.is_method_not_found() => Ok(true),
+        result => result,
+    }
+}
+
+/// Ask the user to exempt the current app from battery optimizations.
+///
+/// This starts an activity with the
+/// `Settings.ACTION_REQUEST_IGNORE_BATTERY_OPTIMIZATIONS` intent for the
+/// current app's package, which shows a system dialog allowing the user to
+/// exempt the app directly. The app must declare the
+/// `android.permission.REQUEST_IGNORE_BATTERY_OPTIMIZATIONS` permission in its
+/// manifest, and should only do so if it meets Google Play's policy for
+/// requesting the exemption. Otherwise, use
+/// [`open_battery_optimization_settings`] instead.
+///
+/// This returns once the activity has been started, not once the user has
+/// made a choice. Use [`is_ignoring_battery_optimizations`] to check the result
+/// afterwards, for example when the app is next resumed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::power;
+///
+/// if !power::is_ignoring_battery_optimizations()? {
+///     power::request_ignore_battery_optimizations()?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn request_ignore_battery_optimizations() -> Result<()> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let package = package_name(&mut env)?;
+
+    start_activity(
+        &mut env,
+        "android.settings.REQUEST_IGNORE_BATTERY_OPTIMIZATIONS",
+        Some(&format!("package:{}", package)),
+    )
+}
+
+/// Open the system settings screen listing which apps are exempt from battery
+/// optimizations.
+///
+/// This starts an activity with the
+/// `Settings.ACTION_IGNORE_BATTERY_OPTIMIZATION_SETTINGS` intent, where the
+/// user can find the app and exempt it themselves. Unlike
+/// [`request_ignore_battery_optimizations`], this does not require any
+/// permission.
+pub fn open_battery_optimization_settings() -> Result<()> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    start_activity(
+        &mut env,
+        "android.settings.IGNORE_BATTERY_OPTIMIZATION_SETTINGS",
+        None,
+    )
+}
+
+/// How location features behave while battery saver is on.
+///
+/// New modes may be added in future Android versions, so code matching on this
+/// should include a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LocationPowerSaveMode {
+    /// Location is not affected by battery saver.
+    NoChange,
+
+    /// GPS based location providers are disabled while the screen is off.
+    GpsDisabledWhenScreenOff,
+
+    /// All location providers are disabled while the screen is off.
+    AllDisabledWhenScreenOff,
+
+    /// Location is only provided to apps in the foreground.
+    ForegroundOnly,
+
+    /// Location requests are throttled while the screen is off.
+    ThrottleRequestsWhenScreenOff,
+
+    /// A mode not known to this crate.
+    Unknown,
+}
+
+impl LocationPowerSaveMode {
+    fn from_raw(mode: i32) -> Self {
+        match mode {
+            0 => Self::NoChange,
+            1 => Self::GpsDisabledWhenScreenOff,
+            2 => Self::AllDisabledWhenScreenOff,
+            3 => Self::ForegroundOnly,
+            4 => Self::ThrottleRequestsWhenScreenOff,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Get how location features currently behave because of battery saver.
+///
+/// This wraps `PowerManager.getLocationPowerSaveMode()`. If battery saver is
+/// off, this returns [`LocationPowerSaveMode::NoChange`].
+///
+/// This was added in API level 28; on older devices this always returns
+/// [`LocationPowerSaveMode::NoChange`].
+pub fn location_power_save_mode() -> Result<LocationPowerSaveMode> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let power_manager = power_manager(&mut env)?;
+
+    let result = catch_exceptions(&mut env, |env| {
+        env.call_method(&power_manager, "getLocationPowerSaveMode", "()I", &[])?
+            .i()
+    });
+
+    match result {
+        Ok(mode) => Ok(LocationPowerSaveMode::from_raw(mode)),
+        Err(e) if e.is_method_not_found() => Ok(LocationPowerSaveMode::NoChange),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get the platform's prediction of how long the battery will last before it
+/// is empty, based on current usage.
+///
+/// This wraps `PowerManager.getBatteryDischargePrediction()`. Returns `None`
+/// if no prediction is available, such as while the device is charging. This
+/// was added in API level 31; on older devices this always returns `None`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use android_wakelock::power;
+///
+/// let long_job_ok = power::battery_discharge_prediction()?
+///     .map_or(true, |remaining| remaining > Duration::from_secs(2 * 60 * 60));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn battery_discharge_prediction() -> Result<Option<Duration>> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let power_manager = power_manager(&mut env)?;
+
+    let result = catch_exceptions(&mut env, |env| {
+        let prediction = env
+            .call_method(
+                &power_manager,
+                "getBatteryDischargePrediction",
+                "()Ljava/time/Duration;",
+                &[],
+            )?
+            .l()?;
+
+        if prediction.is_null() {
+            return Ok(None);
+        }
+
+        let millis = env.call_method(&prediction, "toMillis", "()J", &[])?.j()?;
+
+        Ok(Some(Duration::from_millis(millis.max(0) as u64)))
+    });
+
+    match result {
+        Err(e) if e.is_method_not_found() => Ok(None),
+        result => result,
+    }
+}
+
+/// Returns true if the prediction returned by
+/// [`battery_discharge_prediction`] is personalized to the user's usage
+/// patterns, rather than based on the device's typical usage.
+///
+/// This wraps `PowerManager.isBatteryDischargePredictionPersonalized()`. This
+/// was added in API level 31; on older devices this always returns false.
+pub fn is_battery_discharge_prediction_personalized() -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let power_manager = power_manager(&mut env)?;
+
+    let result = catch_exceptions(&mut env, |env| {
+        env.call_method(
+            &power_manager,
+            "isBatteryDischargePredictionPersonalized",
+            "()Z",
+            &[],
+        )?
+        .z()
+    });
+
+    match result {
+        Err(e) if e.is_method_not_found() => Ok(false),
+        result => result,
+    }
+}
+
+/// Returns true if the device supports sustained performance mode.
+///
+/// This wraps `PowerManager.isSustainedPerformanceModeSupported()`. In
+/// sustained performance mode, the device provides a consistent level of
+/// performance for long periods of time instead of bursting and then
+/// throttling, which suits long-running work such as games or encoders.
+/// Enable it for an activity with
+/// [`screen::set_sustained_performance_mode`][crate::screen::set_sustained_performance_mode].
+///
+/// This was added in API level 24; on older devices this always returns
+/// false.
+pub fn is_sustained_performance_mode_supported() -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let power_manager = power_manager(&mut env)?;
+
+    sustained_performance_mode_supported(&mut env, &power_manager)
+}
+
+pub(crate) fn sustained_performance_mode_supported(
+    env: &mut JNIEnv<'_>,
+    power_manager: &JObject<'_>,
+) -> Result<bool> {
+    let result = catch_exceptions(env, |env| {
+        env.call_method(
+            power_manager,
+            "isSustainedPerformanceModeSupported",
+            "()Z",
+            &[],
+        )?
+        .z()
+    });
+
+    match result {
+        Err(e) if e.is_method_not_found() => Ok(false),
+        result => result,
+    }
+}
+
+/// Returns true if Low Power Standby is enabled on the device.
+///
+/// This wraps `PowerManager.isLowPowerStandbyEnabled()`. While Low Power
+/// Standby is active, apps that are not exempt lose network access and wake
+/// locks held by them are disabled, so holding a partial wake lock to keep a
+/// network connection alive has no effect. See
+/// [`is_exempt_from_low_power_standby`].
+///
+/// Low Power Standby was added in API level 33; on older devices this always
+/// returns false.
+pub fn is_low_power_standby_enabled() -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    if sys::api_level_with(&mut env)? < 33 {
+        return Ok(false);
+    }
+
+    let power_manager = power_manager(&mut env)?;
+
+    catch_exceptions(&mut env, |env| {
+        env.call_method(&power_manager, "isLowPowerStandbyEnabled", "()Z", &[])?
+            .z()
+    })
+}
+
+/// Returns true if the current app is exempt from Low Power Standby
+/// restrictions.
+///
+/// This wraps `PowerManager.isExemptFromLowPowerStandby()`. This was added in
+/// API level 34; on older devices an error of kind [`ErrorKind::Unsupported`]
+/// is returned, since exemptions cannot be queried there.
+pub fn is_exempt_from_low_power_standby() -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    if sys::api_level_with(&mut env)? < 34 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "Low Power Standby exemptions can only be queried on API level 34 and higher",
+        ));
+    }
+
+    let power_manager = power_manager(&mut env)?;
+
+    catch_exceptions(&mut env, |env| {
+        env.call_method(&power_manager, "isExemptFromLowPowerStandby", "()Z", &[])?
+            .z()
+    })
+}
+
+/// Returns true if the device is currently in idle mode, also known as Doze.
+///
+/// This wraps `PowerManager.isDeviceIdleMode()`. While the device is idle,
+/// wake locks held by apps are ignored, and background work only runs during
+/// short maintenance windows. See [`doze::DozeMonitor`][crate::doze::DozeMonitor]
+/// for detecting these windows.
+///
+/// Doze was added in API level 23; on older devices this always returns
+/// false.
+pub fn is_device_idle_mode() -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    device_idle_mode(&mut env)
+}
+
+pub(crate) fn device_idle_mode(env: &mut JNIEnv<'_>) -> Result<bool> {
+    let power_manager = power_manager(env)?;
+
+    let result = catch_exceptions(env, |env| {
+        env.call_method(&power_manager, "isDeviceIdleMode", "()Z", &[])?
+            .z()
+    });
+
+    match result {
+        Err(e) if e.is_method_not_found() => Ok(false),
+        result => result,
+    }
+}
+
+/// Start an activity for an intent with the given action and data URI from the
+/// app context.
+fn start_activity(env: &mut JNIEnv<'_>, action: &str, data: Option<&str>) -> Result<()> {
+    // Intent.FLAG_ACTIVITY_NEW_TASK, required when starting an activity from
+    // outside of an activity context.
+    const FLAG_ACTIVITY_NEW_TASK: i32 = 0x10000000;
+
+    let ctx = ndk_context::android_context();
+    let action = env.new_string(action)?;
+    let data = data.map(|data| env.new_string(data)).transpose()?;
+
+    catch_exceptions(env, |env| {
+        let intent = env.new_object(
+            "android/content/Intent",
+            "(Ljava/lang/String;)V",
+            &[JValue::from(&action)],
+        )?;
+
+        if let Some(data) = &data {
+            let uri = env
+                .call_static_method(
+                    "android/net/Uri",
+                    "parse",
+                    "(Ljava/lang/String;)Landroid/net/Uri;",
+                    &[JValue::from(data)],
+                )?
+                .l()?;
+
+            env.call_method(
+                &intent,
+                "setData",
+                "(Landroid/net/Uri;)Landroid/content/Intent;",
+                &[JValue::from(&uri)],
+            )?;
+        }
+
+        env.call_method(
+            &intent,
+            "addFlags",
+            "(I)Landroid/content/Intent;",
+            &[JValue::from(FLAG_ACTIVITY_NEW_TASK)],
+        )?;
+
+        env.call_method(
+            unsafe { JObject::from_raw(ctx.context().cast()) },
+            "startActivity",
+            "(Landroid/content/Intent;)V",
+            &[JValue::from(&intent)],
+        )?
+        .v()
+    })
+}
@@ -0,0 +1,188 @@
+//! Rendering statistics in the Prometheus text exposition format.
+
+use std::fmt::{self, Write};
+
+use crate::stats::{Latency, StatsSnapshot};
+
+/// Quantiles reported for JNI call latency.
+const QUANTILES: &[f64] = &[0.5, 0.9, 0.99];
+
+pub(crate) fn render(snapshot: &StatsSnapshot) -> String {
+    let mut out = String::new();
+
+    // Writing to a string cannot fail.
+    let _ = write_snapshot(&mut out, snapshot);
+
+    out
+}
+
+fn write_snapshot(out: &mut String, snapshot: &StatsSnapshot) -> fmt::Result {
+    let tags = snapshot.tags();
+
+    header(
+        out,
+        "android_wakelock_acquires_total",
+        "counter",
+        "Number of times a guard acquired the wake lock",
+    )?;
+    for stats in tags {
+        writeln!(
+            out,
+            "android_wakelock_acquires_total{{tag={}}} {}",
+            Label(stats.tag()),
+            stats.acquires()
+        )?;
+    }
+
+    header(
+        out,
+        "android_wakelock_releases_total",
+        "counter",
+        "Number of times a guard released the wake lock",
+    )?;
+    for stats in tags {
+        writeln!(
+            out,
+            "android_wakelock_releases_total{{tag={}}} {}",
+            Label(stats.tag()),
+            stats.releases()
+        )?;
+    }
+
+    header(
+        out,
+        "android_wakelock_held",
+        "gauge",
+        "Number of guards currently holding the wake lock",
+    )?;
+    for stats in tags {
+        writeln!(
+            out,
+            "android_wakelock_held{{tag={}}} {}",
+            Label(stats.tag()),
+            stats.active_guards()
+        )?;
+    }
+
+    header(
+        out,
+        "android_wakelock_hold_duration_seconds",
+        "histogram",
+        "Time guards held the wake lock",
+    )?;
+    for stats in tags {
+        let tag = Label(stats.tag());
+        let mut cumulative = 0;
+
+        for (bound, count) in stats.histogram().buckets() {
+            cumulative += count;
+
+            match bound {
+                Some(bound) => writeln!(
+                    out,
+                    "android_wakelock_hold_duration_seconds_bucket{{tag={},le=\"{}\"}} {}",
+                    tag,
+                    bound.as_secs_f64(),
+                    cumulative
+                )?,
+                None => writeln!(
+                    out,
+                    "android_wakelock_hold_duration_seconds_bucket{{tag={},le=\"+Inf\"}} {}",
+                    tag, cumulative
+                )?,
+            }
+        }
+
+        writeln!(
+            out,
+            "android_wakelock_hold_duration_seconds_sum{{tag={}}} {}",
+            tag,
+            stats.histogram().sum().as_secs_f64()
+        )?;
+        writeln!(
+            out,
+            "android_wakelock_hold_duration_seconds_count{{tag={}}} {}",
+            tag, cumulative
+        )?;
+    }
+
+    header(
+        out,
+        "android_wakelock_longest_hold_seconds",
+        "gauge",
+        "Longest time a guard held the wake lock",
+    )?;
+    for stats in tags {
+        writeln!(
+            out,
+            "android_wakelock_longest_hold_seconds{{tag={}}} {}",
+            Label(stats.tag()),
+            stats.longest_held().as_secs_f64()
+        )?;
+    }
+
+    let latency = snapshot.jni_latency();
+
+    header(
+        out,
+        "android_wakelock_jni_call_duration_seconds",
+        "summary",
+        "Latency of JNI calls made for wake locks",
+    )?;
+    write_latency(out, "attach", latency.attach())?;
+    write_latency(out, "acquire", latency.acquire())?;
+    write_latency(out, "release", latency.release())?;
+    write_latency(out, "is_held", latency.is_held())
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} {}", name, kind)
+}
+
+fn write_latency(out: &mut String, call: &str, latency: &Latency) -> fmt::Result {
+    for quantile in QUANTILES {
+        if let Some(value) = latency.percentile(quantile * 100.0) {
+            writeln!(
+                out,
+                "android_wakelock_jni_call_duration_seconds{{call=\"{}\",quantile=\"{}\"}} {}",
+                call,
+                quantile,
+                value.as_secs_f64()
+            )?;
+        }
+    }
+
+    writeln!(
+        out,
+        "android_wakelock_jni_call_duration_seconds_sum{{call=\"{}\"}} {}",
+        call,
+        latency.total().as_secs_f64()
+    )?;
+    writeln!(
+        out,
+        "android_wakelock_jni_call_duration_seconds_count{{call=\"{}\"}} {}",
+        call,
+        latency.count()
+    )
+}
+
+/// Formats a label value in quotes, escaping it as required by the format.
+struct Label<'a>(&'a str);
+
+impl fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+
+        f.write_str("\"")
+    }
+}
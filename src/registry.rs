@@ -0,0 +1,26 @@
+//! Tracking every wake lock alive in the process, for operations that apply
+//! to all of them.
+
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use crate::Inner;
+
+static WAKE_LOCKS: Mutex<Vec<Weak<Inner>>> = Mutex::new(Vec::new());
+
+fn lock() -> MutexGuard<'static, Vec<Weak<Inner>>> {
+    WAKE_LOCKS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Add a newly created wake lock.
+pub(crate) fn register(inner: &Arc<Inner>) {
+    let mut wake_locks = lock();
+
+    // Wake locks are not removed when dropped, so clean up here instead.
+    wake_locks.retain(|inner| inner.strong_count() > 0);
+    wake_locks.push(Arc::downgrade(inner));
+}
+
+/// Get all wake locks that are still alive, in the order they were created.
+pub(crate) fn wake_locks() -> Vec<Arc<Inner>> {
+    lock().iter().filter_map(Weak::upgrade).collect()
+}
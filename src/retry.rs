@@ -0,0 +1,117 @@
+use std::{cell::Cell, thread, time::Duration};
+
+use crate::Result;
+
+/// A policy for retrying operations that fail with transient errors.
+///
+/// Some errors are not caused by the wake lock API itself, but by the
+/// environment it is called in, and will often go away if the call is simply
+/// tried again. Examples include failing to attach the current thread to the
+/// JVM, or a pending exception left behind by unrelated Java code on the same
+/// thread. Such an exception is only cleared while a retry policy is set, and
+/// is otherwise left pending for the code that threw it. A retry policy can be
+/// set with [`Builder::retry`][crate::Builder::retry] to retry such errors
+/// automatically with exponential backoff, both when building the wake lock and
+/// when acquiring and releasing it.
+///
+/// Errors thrown by the wake lock API itself, such as a missing permission,
+/// are never retried.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use android_wakelock::{RetryPolicy, WakeLock};
+///
+/// let wake_lock = WakeLock::builder("myapp:mytag")
+///     .retry(RetryPolicy::new(3).initial_backoff(Duration::from_millis(20)))
+///     .build()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Create a policy that retries a failed operation up to `max_retries`
+    /// times.
+    ///
+    /// By default the first retry happens after 10 milliseconds, and the delay
+    /// doubles with every following retry up to a maximum of 1 second.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Set the delay before the first retry.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the maximum delay between retries.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Run the given operation, retrying it according to this policy if it
+    /// fails with a transient error.
+    pub(crate) fn run<T, F>(&self, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let _retrying = Retrying(RETRYING.with(|retrying| retrying.replace(true)));
+        let mut backoff = self.initial_backoff;
+        let mut retries = 0;
+
+        loop {
+            match f() {
+                Err(e) if e.is_transient() && retries < self.max_retries => {
+                    retries += 1;
+
+                    debug!(
+                        "transient error, retrying in {:?} ({}/{}): {}",
+                        backoff, retries, self.max_retries, e
+                    );
+
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+thread_local! {
+    static RETRYING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns true if the current thread is running an operation under a retry
+/// policy, which allows it to clear exceptions left pending by other code.
+pub(crate) fn is_retrying() -> bool {
+    RETRYING.with(Cell::get)
+}
+
+/// Restores the previous retrying state of the current thread when dropped.
+struct Retrying(bool);
+
+impl Drop for Retrying {
+    fn drop(&mut self) {
+        RETRYING.with(|retrying| retrying.set(self.0));
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Retry up to 3 times with the default backoff.
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
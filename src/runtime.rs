@@ -0,0 +1,301 @@
+//! Integration with async runtimes.
+//!
+//! Releases scheduled with [`Guard::release_after_on`] and similar methods
+//! spawn a background task and wait on a timer through the small [`Spawner`]
+//! and [`Timer`] traits, so that they can be used with any async runtime.
+//! Other async utilities do not need a runtime at all: the idle timeout of
+//! `WakeLockStreamExt::with_wake_lock` from the `stream` feature runs on the
+//! crate's own timer thread, so that it works the same without any runtime
+//! integration. Implementations for popular runtimes are provided
+//! behind features:
+//!
+//! - `Tokio` with the `tokio` feature
+//! - `AsyncStd` with the `async-std` feature
+//! - `Smol` with the `smol` feature
+//!
+//! For other runtimes, implement the traits yourself.
+
+use std::{
+    future::{self, Future},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Poll, Waker},
+    time::Duration,
+};
+
+use crate::{hook, Guard, Result};
+
+/// A boxed future used by the runtime traits.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// Spawns background tasks on an async runtime.
+pub trait Spawner {
+    /// Spawn the given future as a detached background task.
+    fn spawn(&self, future: BoxFuture<()>);
+}
+
+/// Provides timers from an async runtime.
+pub trait Timer {
+    /// Returns a future that completes after the given duration has elapsed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<()>;
+}
+
+/// The [Tokio](https://tokio.rs) runtime.
+///
+/// Tasks are spawned onto the current runtime, so this must be used from
+/// within a Tokio runtime context.
+///
+/// Requires the `tokio` feature.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tokio;
+
+#[cfg(feature = "tokio")]
+impl Spawner for Tokio {
+    fn spawn(&self, future: BoxFuture<()>) {
+        tokio::spawn(future);
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Timer for Tokio {
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// The [async-std](https://async.rs) runtime.
+///
+/// Requires the `async-std` feature.
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStd;
+
+#[cfg(feature = "async-std")]
+impl Spawner for AsyncStd {
+    fn spawn(&self, future: BoxFuture<()>) {
+        async_std::task::spawn(future);
+    }
+}
+
+#[cfg(feature = "async-std")]
+impl Timer for AsyncStd {
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// The [smol](https://github.com/smol-rs/smol) runtime, using its global
+/// executor.
+///
+/// Requires the `smol` feature.
+#[cfg(feature = "smol")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Smol;
+
+#[cfg(feature = "smol")]
+impl Spawner for Smol {
+    fn spawn(&self, future: BoxFuture<()>) {
+        smol::spawn(future).detach();
+    }
+}
+
+#[cfg(feature = "smol")]
+impl Timer for Smol {
+    fn sleep(&self, duration: Duration) -> BoxFuture<()> {
+        Box::pin(async move {
+            smol::Timer::after(duration).await;
+        })
+    }
+}
+
+impl Guard {
+    /// Release the wake lock after the given duration has elapsed, using the
+    /// current Tokio runtime's timer.
+    ///
+    /// This is equivalent to calling
+    /// [`release_after_on`][Guard::release_after_on] with [`Tokio`].
+    ///
+    /// Requires the `tokio` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let wake_lock = android_wakelock::partial("myapp:mytag")?;
+    ///
+    /// // Keep the device awake for at most 30 more seconds.
+    /// let handle = wake_lock.acquire_async().await?.release_after(Duration::from_secs(30));
+    ///
+    /// // Finished early, so release now.
+    /// handle.release_now().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub fn release_after(self, duration: Duration) -> ReleaseHandle {
+        self.release_after_on(&Tokio, duration)
+    }
+
+    /// Release the wake lock after the given duration has elapsed, using the
+    /// given async runtime to spawn the timer.
+    ///
+    /// Returns a [`ReleaseHandle`] which can be used to cancel the scheduled
+    /// release or to release early. Dropping the handle does not cancel the
+    /// release.
+    ///
+    /// The release is performed on the crate's background thread, so that the
+    /// runtime's threads are neither blocked nor used for JNI calls. Any error
+    /// releasing the wake lock once the timer fires is passed to the hook set
+    /// with [`set_error_hook`][crate::set_error_hook].
+    pub fn release_after_on<R>(self, runtime: &R, duration: Duration) -> ReleaseHandle
+    where
+        R: Spawner + Timer + ?Sized,
+    {
+        let sleep = runtime.sleep(duration);
+
+        self.release_when(runtime, sleep)
+    }
+
+    /// Release the wake lock as soon as the given cancellation token is
+    /// cancelled, using the current Tokio runtime to wait for cancellation.
+    ///
+    /// The release happens on a separate task, so the wake lock is released
+    /// promptly even if the task owning the guard is stuck waiting on something
+    /// else. The task hands the release to the crate's background thread and
+    /// awaits it, so it never blocks the runtime. Returns a [`ReleaseHandle`]
+    /// which can be used to cancel the scheduled release or to release early.
+    /// Dropping the handle does not cancel the release.
+    ///
+    /// Any error releasing the wake lock once the token is cancelled is passed
+    /// to the hook set with [`set_error_hook`][crate::set_error_hook].
+    ///
+    /// Requires the `tokio-util` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use tokio_util::sync::CancellationToken;
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let wake_lock = android_wakelock::partial("myapp:mytag")?;
+    /// let token = CancellationToken::new();
+    ///
+    /// let handle = wake_lock.acquire_async().await?.release_on_cancel(token.clone());
+    ///
+    /// // Elsewhere, shutting down releases the wake lock.
+    /// token.cancel();
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio-util")]
+    pub fn release_on_cancel(self, token: tokio_util::sync::CancellationToken) -> ReleaseHandle {
+        self.release_when(&Tokio, Box::pin(token.cancelled_owned()))
+    }
+
+    /// Release the wake lock once the given future completes, or stop waiting
+    /// for it as soon as the returned handle takes the guard.
+    fn release_when<S>(self, spawner: &S, mut future: BoxFuture<()>) -> ReleaseHandle
+    where
+        S: Spawner + ?Sized,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            guard: Some(self),
+            waker: None,
+        }));
+        let task_slot = slot.clone();
+
+        spawner.spawn(Box::pin(async move {
+            let guard = future::poll_fn(|cx| {
+                if future.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(task_slot.lock().unwrap().guard.take());
+                }
+
+                let mut slot = task_slot.lock().unwrap();
+
+                // The handle was cancelled or released early, so there is
+                // nothing left to wait for.
+                if slot.guard.is_none() {
+                    return Poll::Ready(None);
+                }
+
+                slot.waker = Some(cx.waker().clone());
+
+                Poll::Pending
+            })
+            .await;
+
+            if let Some(guard) = guard {
+                let inner = guard.inner.clone();
+
+                if let Err(e) = guard.release_on_worker().await {
+                    hook::report_error(&inner.tag, &e);
+                }
+            }
+        }));
+
+        ReleaseHandle { slot }
+    }
+}
+
+/// A handle to a release scheduled with [`Guard::release_after_on`] or a
+/// similar method.
+#[derive(Debug)]
+pub struct ReleaseHandle {
+    slot: Arc<Mutex<Slot>>,
+}
+
+/// State shared between a [`ReleaseHandle`] and its scheduled release.
+#[derive(Debug)]
+struct Slot {
+    /// The guard waiting to be released, or `None` if it was already released.
+    guard: Option<Guard>,
+
+    /// Wakes the scheduled release once the guard is taken by the handle.
+    waker: Option<Waker>,
+}
+
+impl ReleaseHandle {
+    /// Returns true if the scheduled release has already happened.
+    pub fn is_released(&self) -> bool {
+        self.slot.lock().unwrap().guard.is_none()
+    }
+
+    /// Cancel the scheduled release, returning the guard if it has not been
+    /// released yet. The task waiting for the release stops right away.
+    pub fn cancel(self) -> Option<Guard> {
+        let (guard, waker) = {
+            let mut slot = self.slot.lock().unwrap();
+
+            (slot.guard.take(), slot.waker.take())
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        guard
+    }
+
+    /// Release the wake lock immediately instead of waiting for the timer.
+    ///
+    /// Like the scheduled release, this is performed on the crate's
+    /// background thread without blocking the current task. Does nothing if
+    /// the scheduled release has already happened.
+    pub async fn release_now(self) -> Result<()> {
+        match self.cancel() {
+            Some(guard) => guard.release_on_worker().await,
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,538 @@
+//! Keeping the screen on and waking it without a wake lock.
+//!
+//! Screen-level wake locks such as [`Level::ScreenDim`][crate::Level::ScreenDim]
+//! are deprecated. The officially recommended alternative is to set
+//! `WindowManager.LayoutParams.FLAG_KEEP_SCREEN_ON` on the window of the
+//! activity that should keep the screen on, which is managed correctly by the
+//! platform as the user moves between apps and does not require any special
+//! permission. Similarly, [`set_turn_screen_on`] and [`set_show_when_locked`]
+//! replace the deprecated
+//! [`Builder::acquire_causes_wakeup`][crate::Builder::acquire_causes_wakeup]
+//! flag for waking the screen.
+//!
+//! The deprecated [`Level::ScreenBright`][crate::Level::ScreenBright] wake
+//! lock additionally forced the screen to full brightness. The equivalent is
+//! [`keep_screen_bright`], which overrides the window's brightness on top of
+//! keeping the screen on.
+//!
+//! Window flags may only be changed on the thread that created the window,
+//! which is normally the app's main (UI) thread. The functions in this module
+//! return an error of kind [`ErrorKind::WrongThread`] when called from any
+//! other thread. Use [`run_on_main_looper`][crate::run_on_main_looper] to
+//! call them from elsewhere.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # fn example(activity: &jni::objects::JObject<'_>) -> Result<(), Box<dyn std::error::Error>> {
+//! // Keep the screen on while this activity is visible.
+//! let guard = android_wakelock::screen::keep_screen_on(activity)?;
+//!
+//! // Allow the screen to turn off again.
+//! guard.clear()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use jni::{
+    objects::{GlobalRef, JObject, JValue},
+    JNIEnv, JavaVM,
+};
+
+use crate::{
+    callback, catch_exceptions,
+    consts::{BRIGHTNESS_OVERRIDE_FULL, FLAG_KEEP_SCREEN_ON},
+    hook, java_vm, run_on_main_looper, sys, system_service, Error, ErrorKind, Result,
+};
+
+/// Get the activity of the current Android app as provided by
+/// [`ndk_context`].
+///
+/// For apps based on `NativeActivity`, this is the activity itself, and can be
+/// passed to the other functions in this module. For other apps it may be a
+/// different kind of context, in which case the activity should be obtained
+/// some other way.
+pub fn current_activity<'local>() -> JObject<'local> {
+    let ctx = ndk_context::android_context();
+
+    unsafe { JObject::from_raw(ctx.context().cast()) }
+}
+
+/// Keep the screen on while the given activity's window is visible.
+///
+/// Sets `FLAG_KEEP_SCREEN_ON` on the activity's window, and returns a guard
+/// that clears it again when dropped. Must be called on the UI thread.
+pub fn keep_screen_on(activity: &JObject<'_>) -> Result<ScreenOnGuard> {
+    keep_screen_on_with(activity, None)
+}
+
+/// Keep the screen on at full brightness while the given activity's window is
+/// visible.
+///
+/// In addition to setting `FLAG_KEEP_SCREEN_ON` like [`keep_screen_on`], this
+/// overrides `WindowManager.LayoutParams.screenBrightness` to full brightness,
+/// emulating the deprecated [`Level::ScreenBright`][crate::Level::ScreenBright]
+/// wake lock the supported way. The returned guard restores the previous
+/// brightness and clears the flag when dropped. Must be called on the UI
+/// thread.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::screen;
+///
+/// // Show a QR code at full brightness so that it can be scanned easily.
+/// let guard = screen::keep_screen_bright(&screen::current_activity())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn keep_screen_bright(activity: &JObject<'_>) -> Result<ScreenOnGuard> {
+    keep_screen_on_with(activity, Some(BRIGHTNESS_OVERRIDE_FULL))
+}
+
+fn keep_screen_on_with(activity: &JObject<'_>, brightness: Option<f32>) -> Result<ScreenOnGuard> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    ensure_ui_thread(&mut env)?;
+
+    let (window, previous_brightness) = catch_exceptions(&mut env, |env| {
+        let window = env
+            .call_method(activity, "getWindow", "()Landroid/view/Window;", &[])?
+            .l()?;
+
+        let previous_brightness = brightness
+            .map(|brightness| set_brightness(env, &window, brightness))
+            .transpose()?;
+
+        env.call_method(
+            &window,
+            "addFlags",
+            "(I)V",
+            &[JValue::from(FLAG_KEEP_SCREEN_ON)],
+        )?;
+
+        Ok((window, previous_brightness))
+    })?;
+
+    let window = env.new_global_ref(window)?;
+
+    drop(env);
+
+    debug!("set FLAG_KEEP_SCREEN_ON");
+
+    Ok(ScreenOnGuard {
+        window,
+        vm,
+        previous_brightness,
+        cleared: false,
+    })
+}
+
+/// Override the brightness of the given window, returning the previous value.
+fn set_brightness(
+    env: &mut JNIEnv<'_>,
+    window: &JObject<'_>,
+    brightness: f32,
+) -> jni::errors::Result<f32> {
+    let params = env
+        .call_method(
+            window,
+            "getAttributes",
+            "()Landroid/view/WindowManager$LayoutParams;",
+            &[],
+        )?
+        .l()?;
+
+    let previous = env.get_field(&params, "screenBrightness", "F")?.f()?;
+
+    env.set_field(&params, "screenBrightness", "F", JValue::Float(brightness))?;
+    env.call_method(
+        window,
+        "setAttributes",
+        "(Landroid/view/WindowManager$LayoutParams;)V",
+        &[JValue::from(&params)],
+    )?;
+
+    debug!("set window brightness to {} (was {})", brightness, previous);
+
+    Ok(previous)
+}
+
+/// Set whether the screen should turn on when the given activity is resumed.
+///
+/// This wraps `Activity.setTurnScreenOn(boolean)`, the modern replacement for
+/// [`Builder::acquire_causes_wakeup`][crate::Builder::acquire_causes_wakeup].
+/// It is typically combined with [`set_show_when_locked`] so that the activity
+/// is shown on top of the lock screen, as in alarm or incoming call screens.
+///
+/// Requires API level 27 or higher; on older versions an error of kind
+/// [`ErrorKind::Unsupported`] is returned. Must be called on the UI thread.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::screen;
+///
+/// let activity = screen::current_activity();
+///
+/// screen::set_turn_screen_on(&activity, true)?;
+/// screen::set_show_when_locked(&activity, true)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn set_turn_screen_on(activity: &JObject<'_>, turn_screen_on: bool) -> Result<()> {
+    set_activity_flag(activity, "setTurnScreenOn", turn_screen_on)
+}
+
+/// Set whether the given activity should be shown on top of the lock screen.
+///
+/// This wraps `Activity.setShowWhenLocked(boolean)`. See
+/// [`set_turn_screen_on`] for more information.
+///
+/// Requires API level 27 or higher; on older versions an error of kind
+/// [`ErrorKind::Unsupported`] is returned. Must be called on the UI thread.
+pub fn set_show_when_locked(activity: &JObject<'_>, show_when_locked: bool) -> Result<()> {
+    set_activity_flag(activity, "setShowWhenLocked", show_when_locked)
+}
+
+fn set_activity_flag(activity: &JObject<'_>, method: &str, value: bool) -> Result<()> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    ensure_ui_thread(&mut env)?;
+
+    catch_exceptions(&mut env, |env| {
+        env.call_method(activity, method, "(Z)V", &[JValue::from(value)])?
+            .v()
+    })
+    .map_err(|e| {
+        if e.is_method_not_found() {
+            Error::new(
+                ErrorKind::Unsupported,
+                format!("Activity.{} requires API level 27", method),
+            )
+        } else {
+            e
+        }
+    })?;
+
+    debug!("called Activity.{}({})", method, value);
+
+    Ok(())
+}
+
+/// Enable or disable sustained performance mode for the given activity's
+/// window.
+///
+/// This wraps `Window.setSustainedPerformanceMode(boolean)`. While the window
+/// is visible, the device provides a consistent level of performance for long
+/// periods of time instead of bursting and then throttling. Check whether the
+/// device supports this first with
+/// [`power::is_sustained_performance_mode_supported`][crate::power::is_sustained_performance_mode_supported].
+///
+/// Requires API level 24 or higher; on older versions an error of kind
+/// [`ErrorKind::Unsupported`] is returned. Must be called on the UI thread.
+pub fn set_sustained_performance_mode(activity: &JObject<'_>, enable: bool) -> Result<()> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    ensure_ui_thread(&mut env)?;
+
+    catch_exceptions(&mut env, |env| {
+        let window = env
+            .call_method(activity, "getWindow", "()Landroid/view/Window;", &[])?
+            .l()?;
+
+        env.call_method(
+            &window,
+            "setSustainedPerformanceMode",
+            "(Z)V",
+            &[JValue::from(enable)],
+        )?
+        .v()
+    })
+    .map_err(|e| {
+        if e.is_method_not_found() {
+            Error::new(
+                ErrorKind::Unsupported,
+                "Window.setSustainedPerformanceMode requires API level 24",
+            )
+        } else {
+            e
+        }
+    })?;
+
+    debug!("called Window.setSustainedPerformanceMode({})", enable);
+
+    Ok(())
+}
+
+/// Ask the system to dismiss the keyguard (lock screen) so that the given
+/// activity can be interacted with.
+///
+/// This wraps `KeyguardManager.requestDismissKeyguard(Activity,
+/// KeyguardDismissCallback)`. If the device is secured with a PIN, pattern, or
+/// password, the user is prompted to authenticate first. Together with
+/// [`set_turn_screen_on`] and [`set_show_when_locked`] this covers the typical
+/// incoming call flow of waking the screen and showing the app's UI.
+///
+/// The given callback is called once with the outcome of the request, on the
+/// UI thread. If the system never reports an outcome, such as when the
+/// activity is destroyed first, the callback is never called. This uses one of
+/// the crate's Java support classes. See the [crate-level
+/// documentation][crate#platform-support] for details.
+///
+/// Requires API level 26 or higher; on older versions an error of kind
+/// [`ErrorKind::Unsupported`] is returned. Must be called on the UI thread.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::screen::{self, KeyguardDismissResult};
+///
+/// let activity = screen::current_activity();
+///
+/// screen::set_turn_screen_on(&activity, true)?;
+/// screen::set_show_when_locked(&activity, true)?;
+///
+/// if screen::is_keyguard_locked()? {
+///     screen::request_dismiss_keyguard(&activity, |result| {
+///         if result != KeyguardDismissResult::Succeeded {
+///             // Keep showing the app's UI on top of the lock screen...
+///         }
+///     })?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn request_dismiss_keyguard<F>(activity: &JObject<'_>, callback: F) -> Result<()>
+where
+    F: FnOnce(KeyguardDismissResult) + Send + 'static,
+{
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    ensure_ui_thread(&mut env)?;
+
+    if sys::api_level_with(&mut env)? < 26 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "KeyguardManager.requestDismissKeyguard requires API level 26",
+        ));
+    }
+
+    let class = callback::load_class(&mut env, DISMISS_CALLBACK_CLASS)?;
+    let keyguard_manager = system_service(&mut env, "keyguard")?;
+    let callback = Mutex::new(Some(callback));
+
+    // The handle is only known once registered, but the outcome cannot be
+    // reported before the request is made below.
+    let handle = Arc::new(AtomicI64::new(0));
+    let handle_id = callback::register({
+        let handle = handle.clone();
+
+        move |_, value, _| {
+            callback::unregister(handle.load(Ordering::SeqCst));
+
+            let result = match value {
+                0 => KeyguardDismissResult::Succeeded,
+                1 => KeyguardDismissResult::Cancelled,
+                _ => KeyguardDismissResult::Error,
+            };
+
+            debug!("keyguard dismissal finished: {:?}", result);
+
+            if let Some(callback) = callback.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                callback(result);
+            }
+        }
+    });
+    handle.store(handle_id, Ordering::SeqCst);
+
+    let requested = catch_exceptions(&mut env, |env| {
+        let dismiss_callback = env.new_object(&class, "(J)V", &[JValue::from(handle_id)])?;
+
+        env.call_method(
+            &keyguard_manager,
+            "requestDismissKeyguard",
+            "(Landroid/app/Activity;Landroid/app/KeyguardManager$KeyguardDismissCallback;)V",
+            &[JValue::from(activity), JValue::from(&dismiss_callback)],
+        )?
+        .v()
+    });
+
+    if let Err(e) = requested {
+        callback::unregister(handle_id);
+        return Err(e);
+    }
+
+    debug!("requested keyguard dismissal");
+
+    Ok(())
+}
+
+const DISMISS_CALLBACK_CLASS: &str = "rs/android_wakelock/KeyguardDismissCallback";
+
+/// The outcome of a request made with [`request_dismiss_keyguard`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyguardDismissResult {
+    /// The keyguard was dismissed.
+    Succeeded,
+
+    /// The user cancelled dismissing the keyguard, for example by not
+    /// authenticating.
+    Cancelled,
+
+    /// The keyguard could not be dismissed, for example because the activity
+    /// is not in the foreground.
+    Error,
+}
+
+/// Returns true if the keyguard (lock screen) is currently showing or the
+/// device is locked.
+///
+/// This wraps `KeyguardManager.isKeyguardLocked()`.
+pub fn is_keyguard_locked() -> Result<bool> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let keyguard_manager = system_service(&mut env, "keyguard")?;
+
+    catch_exceptions(&mut env, |env| {
+        env.call_method(&keyguard_manager, "isKeyguardLocked", "()Z", &[])?
+            .z()
+    })
+}
+
+/// A guard that keeps the screen on while held, returned by
+/// [`keep_screen_on`].
+///
+/// The flag is cleared when the guard is dropped. If the guard is dropped on
+/// another thread, the flag is cleared on the UI thread instead using
+/// [`run_on_main_looper`][crate::run_on_main_looper]. Errors clearing the flag
+/// on drop are passed to the hook set with
+/// [`set_error_hook`][crate::set_error_hook] instead of causing a panic. Use
+/// [`clear`][ScreenOnGuard::clear] to handle such errors directly.
+pub struct ScreenOnGuard {
+    window: GlobalRef,
+    vm: JavaVM,
+
+    /// The brightness to restore, if it was overridden.
+    previous_brightness: Option<f32>,
+
+    cleared: bool,
+}
+
+impl ScreenOnGuard {
+    /// Clear the flag, allowing the screen to turn off again, and restore the
+    /// previous brightness if it was overridden. Must be called on the UI
+    /// thread.
+    pub fn clear(mut self) -> Result<()> {
+        self.cleared = true;
+        self.clear_flag()
+    }
+
+    fn clear_flag(&self) -> Result<()> {
+        clear_window(&self.vm, &self.window, self.previous_brightness)
+    }
+}
+
+fn clear_window(vm: &JavaVM, window: &GlobalRef, previous_brightness: Option<f32>) -> Result<()> {
+    let mut env = vm.attach_current_thread()?;
+
+    ensure_ui_thread(&mut env)?;
+
+    catch_exceptions(&mut env, |env| {
+        if let Some(brightness) = previous_brightness {
+            set_brightness(env, window, brightness)?;
+        }
+
+        env.call_method(
+            window,
+            "clearFlags",
+            "(I)V",
+            &[JValue::from(FLAG_KEEP_SCREEN_ON)],
+        )?
+        .v()
+    })?;
+
+    debug!("cleared FLAG_KEEP_SCREEN_ON");
+
+    Ok(())
+}
+
+impl fmt::Debug for ScreenOnGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScreenOnGuard")
+            .field("window", &self.window)
+            .field("previous_brightness", &self.previous_brightness)
+            .finish()
+    }
+}
+
+impl Drop for ScreenOnGuard {
+    fn drop(&mut self) {
+        if self.cleared {
+            return;
+        }
+
+        match self.clear_flag() {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::WrongThread => {
+                let window = self.window.clone();
+                let previous_brightness = self.previous_brightness;
+
+                let posted = run_on_main_looper(move || {
+                    if let Err(e) =
+                        java_vm().and_then(|vm| clear_window(&vm, &window, previous_brightness))
+                    {
+                        hook::report_error("FLAG_KEEP_SCREEN_ON", &e);
+                    }
+                });
+
+                if let Err(e) = posted {
+                    hook::report_error("FLAG_KEEP_SCREEN_ON", &e);
+                }
+            }
+            Err(e) => hook::report_error("FLAG_KEEP_SCREEN_ON", &e),
+        }
+    }
+}
+
+/// Return an error if the current thread is not the app's main thread.
+pub(crate) fn ensure_ui_thread(env: &mut JNIEnv<'_>) -> Result<()> {
+    let is_ui_thread = catch_exceptions(env, |env| {
+        let current = env
+            .call_static_method(
+                "android/os/Looper",
+                "myLooper",
+                "()Landroid/os/Looper;",
+                &[],
+            )?
+            .l()?;
+        let main = env
+            .call_static_method(
+                "android/os/Looper",
+                "getMainLooper",
+                "()Landroid/os/Looper;",
+                &[],
+            )?
+            .l()?;
+
+        env.is_same_object(&current, &main)
+    })?;
+
+    if is_ui_thread {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::WrongThread,
+            "window flags can only be changed on the UI thread",
+        ))
+    }
+}
@@ -0,0 +1,729 @@
+//! Statistics about how wake locks are used, collected per tag.
+//!
+//! Every guard acquiring and releasing a wake lock is counted towards the
+//! wake lock's tag. Statistics for a tag include all wake locks with that tag,
+//! and are kept for the lifetime of the process, even after the wake locks are
+//! dropped. Use [`snapshot`] to retrieve them.
+//!
+//! Besides totals, a [`Histogram`] of hold durations is kept for each tag, to
+//! find the long tail of holds that averages hide. Its buckets can be set with
+//! [`set_histogram_buckets`].
+//!
+//! The latency of the JNI calls made to acquire, release and check wake locks,
+//! and of attaching threads to the JVM to make them, is measured as well, and
+//! included in snapshots as [`JniLatency`].
+//!
+//! No statistics are collected with the `no-instrumentation` feature.
+//!
+//! To push statistics into an app's own analytics pipeline periodically, set a
+//! [`Reporter`] with [`set_reporter`]. With the `prometheus` feature, a
+//! snapshot can also be rendered for scraping with
+//! `StatsSnapshot::to_prometheus`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use android_wakelock::stats;
+//!
+//! for tag in stats::snapshot().tags() {
+//!     println!(
+//!         "{}: {} holds, {:?} in total, longest {:?}",
+//!         tag.tag(),
+//!         tag.releases(),
+//!         tag.total_held(),
+//!         tag.longest_held(),
+//!     );
+//! }
+//! ```
+
+#[cfg(feature = "audit")]
+use std::{backtrace::Backtrace, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    panic::Location,
+    sync::{mpsc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::registry;
+
+/// Bucket bounds used for hold duration histograms unless set with
+/// [`set_histogram_buckets`].
+pub const DEFAULT_HISTOGRAM_BUCKETS: &[Duration] = &[
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+    Duration::from_secs(10 * 60),
+    Duration::from_secs(60 * 60),
+];
+
+/// The number of most recent samples of each JNI call's latency that
+/// percentiles are computed from.
+const LATENCY_SAMPLES: usize = 1024;
+
+static TAGS: Mutex<Tags> = Mutex::new(Tags {
+    buckets: None,
+    tags: None,
+});
+
+struct Tags {
+    /// Bucket bounds set with [`set_histogram_buckets`], if any.
+    buckets: Option<Vec<Duration>>,
+
+    tags: Option<HashMap<String, TagState>>,
+}
+
+#[derive(Clone, Debug)]
+struct TagState {
+    acquires: u64,
+    releases: u64,
+    total_held: Duration,
+    longest_held: Duration,
+    histogram: Histogram,
+}
+
+impl TagState {
+    fn to_stats(&self, tag: &str, active_guards: usize) -> TagStats {
+        TagStats {
+            tag: tag.to_owned(),
+            acquires: self.acquires,
+            releases: self.releases,
+            active_guards,
+            total_held: self.total_held,
+            longest_held: self.longest_held,
+            histogram: self.histogram.clone(),
+        }
+    }
+}
+
+fn tags() -> MutexGuard<'static, Tags> {
+    TAGS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+static LATENCY: Mutex<[Samples; 4]> = Mutex::new([const { Samples::new() }; 4]);
+
+/// A JNI call whose latency is measured.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum JniCall {
+    Attach,
+    Acquire,
+    Release,
+    IsHeld,
+}
+
+/// The most recent latency samples of a single JNI call.
+struct Samples {
+    count: u64,
+    total: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl Samples {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            total: Duration::ZERO,
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn record(&mut self, elapsed: Duration) {
+        if self.recent.len() == LATENCY_SAMPLES {
+            self.recent.pop_front();
+        }
+
+        self.recent.push_back(elapsed);
+        self.count += 1;
+        self.total += elapsed;
+    }
+
+    fn to_latency(&self) -> Latency {
+        let mut samples = self.recent.iter().copied().collect::<Vec<_>>();
+
+        samples.sort();
+
+        Latency {
+            count: self.count,
+            total: self.total,
+            samples,
+        }
+    }
+}
+
+/// Set the upper bounds of the buckets used for hold duration histograms.
+///
+/// Bounds are sorted, and duplicates are removed. Holds longer than the
+/// largest bound are counted in an additional, unbounded bucket. Since
+/// existing counts cannot be redistributed, changing the buckets clears the
+/// histograms of all tags, so this is best called once at startup. Defaults to
+/// [`DEFAULT_HISTOGRAM_BUCKETS`].
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// android_wakelock::stats::set_histogram_buckets([
+///     Duration::from_secs(1),
+///     Duration::from_secs(30),
+///     Duration::from_secs(5 * 60),
+/// ]);
+/// ```
+pub fn set_histogram_buckets<I>(bounds: I)
+where
+    I: IntoIterator<Item = Duration>,
+{
+    let mut bounds = bounds.into_iter().collect::<Vec<_>>();
+
+    bounds.sort();
+    bounds.dedup();
+
+    let mut tags = tags();
+
+    for state in tags.tags.iter_mut().flat_map(HashMap::values_mut) {
+        state.histogram = Histogram::new(&bounds);
+    }
+
+    tags.buckets = Some(bounds);
+}
+
+/// Get the current statistics of all tags.
+pub fn snapshot() -> StatsSnapshot {
+    let mut active = HashMap::<String, usize>::new();
+
+    // Collected first, so that both locks are not held at the same time.
+    for inner in registry::wake_locks() {
+        *active.entry(inner.tag.clone()).or_default() += inner.guards.len();
+    }
+
+    let tags = tags();
+    let mut snapshot = tags
+        .tags
+        .iter()
+        .flatten()
+        .map(|(tag, state)| state.to_stats(tag, active.get(tag).copied().unwrap_or(0)))
+        .collect::<Vec<_>>();
+
+    snapshot.sort_by(|a, b| a.tag.cmp(&b.tag));
+    drop(tags);
+
+    let latency = LATENCY.lock().unwrap_or_else(|e| e.into_inner());
+
+    StatsSnapshot {
+        tags: snapshot,
+        jni_latency: JniLatency {
+            attach: latency[JniCall::Attach as usize].to_latency(),
+            acquire: latency[JniCall::Acquire as usize].to_latency(),
+            release: latency[JniCall::Release as usize].to_latency(),
+            is_held: latency[JniCall::IsHeld as usize].to_latency(),
+        },
+    }
+}
+
+/// Get the current statistics of the given tag, or `None` if no wake lock
+/// with the tag has been acquired yet.
+///
+/// This is cheaper than taking a full [`snapshot`] when only a single tag is
+/// of interest, such as for a subsystem reporting on its own wake lock in a
+/// health check.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// if let Some(stats) = android_wakelock::stats_for_tag("myapp:sync") {
+///     if stats.longest_held() > Duration::from_secs(10 * 60) {
+///         println!("sync held its wake lock for too long");
+///     }
+/// }
+/// ```
+pub fn stats_for_tag(tag: &str) -> Option<TagStats> {
+    let active = registry::wake_locks()
+        .iter()
+        .filter(|inner| inner.tag == tag)
+        .map(|inner| inner.guards.len())
+        .sum();
+
+    tags()
+        .tags
+        .as_ref()?
+        .get(tag)
+        .map(|state| state.to_stats(tag, active))
+}
+
+/// Receives snapshots of the statistics periodically, set with
+/// [`set_reporter`].
+///
+/// This allows pushing wake lock statistics into an app's own crash reporting
+/// or analytics pipeline without the crate depending on it. Closures taking a
+/// [`StatsSnapshot`] implement this trait.
+///
+/// # Examples
+///
+/// ```
+/// use android_wakelock::stats::{Reporter, StatsSnapshot};
+///
+/// struct Analytics;
+///
+/// impl Reporter for Analytics {
+///     fn report(&mut self, snapshot: &StatsSnapshot) {
+///         for tag in snapshot.tags() {
+///             println!("{} held for {:?}", tag.tag(), tag.total_held());
+///         }
+///     }
+/// }
+/// ```
+pub trait Reporter: Send {
+    /// Report the given snapshot of the statistics.
+    fn report(&mut self, snapshot: &StatsSnapshot);
+}
+
+impl<F> Reporter for F
+where
+    F: FnMut(&StatsSnapshot) + Send,
+{
+    fn report(&mut self, snapshot: &StatsSnapshot) {
+        self(snapshot)
+    }
+}
+
+/// Sender for the current reporter's thread, which stops once it is dropped.
+static REPORTER: Mutex<Option<mpsc::Sender<()>>> = Mutex::new(None);
+
+/// Report a [`snapshot`] of the statistics to the given reporter at the given
+/// interval.
+///
+/// The reporter is called on a dedicated thread, so it may block, for example
+/// to upload the statistics. The first report is made once the interval has
+/// elapsed. Setting a reporter replaces any previously set reporter, which
+/// is not called anymore afterwards.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// android_wakelock::stats::set_reporter(
+///     |snapshot: &android_wakelock::stats::StatsSnapshot| {
+///         println!("{:?}", snapshot);
+///     },
+///     Duration::from_secs(15 * 60),
+/// );
+/// # android_wakelock::stats::remove_reporter();
+/// ```
+pub fn set_reporter<R>(mut reporter: R, interval: Duration)
+where
+    R: Reporter + 'static,
+{
+    let (sender, receiver) = mpsc::channel::<()>();
+
+    thread::Builder::new()
+        .name(String::from("android-wakelock-reporter"))
+        .spawn(move || {
+            // Runs until the sender is dropped by replacing or removing the
+            // reporter.
+            while let Err(mpsc::RecvTimeoutError::Timeout) = receiver.recv_timeout(interval) {
+                reporter.report(&snapshot());
+            }
+        })
+        .expect("failed to spawn wake lock reporter thread");
+
+    *REPORTER.lock().unwrap_or_else(|e| e.into_inner()) = Some(sender);
+}
+
+/// Remove the reporter set with [`set_reporter`], if any.
+pub fn remove_reporter() {
+    REPORTER.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// Record that a guard acquired a wake lock with the given tag.
+pub(crate) fn acquired(tag: &str) {
+    if cfg!(feature = "no-instrumentation") {
+        return;
+    }
+
+    with_tag(tag, |state| state.acquires += 1);
+}
+
+/// Record that a guard released a wake lock with the given tag after holding
+/// it for the given duration.
+pub(crate) fn released(tag: &str, held: Duration) {
+    if cfg!(feature = "no-instrumentation") {
+        return;
+    }
+
+    with_tag(tag, |state| {
+        state.releases += 1;
+        state.total_held += held;
+        state.longest_held = state.longest_held.max(held);
+        state.histogram.record(held);
+    });
+}
+
+/// Run the given JNI call, recording how long it took.
+pub(crate) fn timed<T>(call: JniCall, f: impl FnOnce() -> T) -> T {
+    if cfg!(feature = "no-instrumentation") {
+        return f();
+    }
+
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+
+    LATENCY.lock().unwrap_or_else(|e| e.into_inner())[call as usize].record(elapsed);
+
+    result
+}
+
+fn with_tag(tag: &str, f: impl FnOnce(&mut TagState)) {
+    let mut tags = tags();
+    let Tags { buckets, tags } = &mut *tags;
+    let tags = tags.get_or_insert_with(HashMap::new);
+
+    match tags.get_mut(tag) {
+        Some(state) => f(state),
+        None => {
+            let mut state = TagState {
+                acquires: 0,
+                releases: 0,
+                total_held: Duration::ZERO,
+                longest_held: Duration::ZERO,
+                histogram: Histogram::new(buckets.as_deref().unwrap_or(DEFAULT_HISTOGRAM_BUCKETS)),
+            };
+
+            f(&mut state);
+            tags.insert(tag.to_owned(), state);
+        }
+    }
+}
+
+/// Statistics of all tags at a point in time, returned by [`snapshot`].
+#[derive(Clone, Debug)]
+pub struct StatsSnapshot {
+    tags: Vec<TagStats>,
+    jni_latency: JniLatency,
+}
+
+impl StatsSnapshot {
+    /// Get the statistics of every tag that has been acquired at least once,
+    /// sorted by tag.
+    pub fn tags(&self) -> &[TagStats] {
+        &self.tags
+    }
+
+    /// Get the statistics of the given tag, if it has been acquired at least
+    /// once.
+    pub fn tag(&self, tag: &str) -> Option<&TagStats> {
+        self.tags
+            .binary_search_by(|stats| stats.tag.as_str().cmp(tag))
+            .ok()
+            .map(|i| &self.tags[i])
+    }
+
+    /// Get the latency of the JNI calls made for all wake locks.
+    pub fn jni_latency(&self) -> &JniLatency {
+        &self.jni_latency
+    }
+
+    /// Render the statistics in the Prometheus text exposition format, for
+    /// serving from an app's own debug HTTP endpoint. Requires the
+    /// `prometheus` feature.
+    ///
+    /// The following metrics are included, all but the last labeled with the
+    /// wake lock's `tag`:
+    ///
+    /// - `android_wakelock_acquires_total`: counter of guards that acquired a
+    ///   wake lock with the tag.
+    /// - `android_wakelock_releases_total`: counter of guards that released a
+    ///   wake lock with the tag.
+    /// - `android_wakelock_held`: gauge of guards currently holding a wake lock
+    ///   with the tag.
+    /// - `android_wakelock_hold_duration_seconds`: histogram of how long
+    ///   guards held a wake lock with the tag, with the buckets set with
+    ///   [`set_histogram_buckets`].
+    /// - `android_wakelock_longest_hold_seconds`: gauge of the longest time a
+    ///   guard held a wake lock with the tag.
+    /// - `android_wakelock_jni_call_duration_seconds`: summary of the latency
+    ///   of JNI calls, labeled with the `call`, one of `attach`, `acquire`,
+    ///   `release`, or `is_held`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let body = android_wakelock::stats::snapshot().to_prometheus();
+    ///
+    /// assert!(body.contains("# TYPE android_wakelock_acquires_total counter"));
+    /// ```
+    #[cfg(feature = "prometheus")]
+    pub fn to_prometheus(&self) -> String {
+        crate::prometheus::render(self)
+    }
+}
+
+/// The latency of the JNI calls made for all wake locks, as part of a
+/// [`StatsSnapshot`].
+///
+/// Calls made to acquire and release wake locks first attach the calling
+/// thread to the JVM, which is measured separately. Attaching is cheap if the
+/// thread is already attached, such as the crate's background thread, but may
+/// be considerably slower than the call itself otherwise.
+///
+/// # Examples
+///
+/// ```no_run
+/// let snapshot = android_wakelock::stats::snapshot();
+/// let latency = snapshot.jni_latency();
+///
+/// println!(
+///     "acquire p99: {:?}, attach p99: {:?}",
+///     latency.acquire().percentile(99.0),
+///     latency.attach().percentile(99.0),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct JniLatency {
+    attach: Latency,
+    acquire: Latency,
+    release: Latency,
+    is_held: Latency,
+}
+
+impl JniLatency {
+    /// Get the latency of attaching the current thread to the JVM before
+    /// making calls.
+    pub fn attach(&self) -> &Latency {
+        &self.attach
+    }
+
+    /// Get the latency of `PowerManager.WakeLock.acquire` calls.
+    pub fn acquire(&self) -> &Latency {
+        &self.acquire
+    }
+
+    /// Get the latency of `PowerManager.WakeLock.release` calls.
+    pub fn release(&self) -> &Latency {
+        &self.release
+    }
+
+    /// Get the latency of `PowerManager.WakeLock.isHeld` calls.
+    pub fn is_held(&self) -> &Latency {
+        &self.is_held
+    }
+}
+
+/// The latency of a single kind of JNI call.
+///
+/// Percentiles are computed from the most recent 1024 calls, so that they
+/// reflect current behavior.
+#[derive(Clone, Debug)]
+pub struct Latency {
+    count: u64,
+    total: Duration,
+
+    /// The most recent samples, sorted.
+    samples: Vec<Duration>,
+}
+
+impl Latency {
+    /// Get the total number of calls made.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Get the total time spent in all calls made.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Get the latency that the given percentage of recent calls did not
+    /// exceed, such as `50.0` for the median, or `None` if no calls were made.
+    ///
+    /// Percentages outside of `0.0..=100.0` are clamped.
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        let last = self.samples.len().checked_sub(1)?;
+        let rank = (percent.clamp(0.0, 100.0) / 100.0 * self.samples.len() as f64).ceil() as usize;
+
+        Some(self.samples[rank.saturating_sub(1).min(last)])
+    }
+
+    /// Get the longest latency of recent calls, or `None` if no calls were
+    /// made.
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.last().copied()
+    }
+}
+
+/// Statistics of all wake locks with a given tag.
+#[derive(Clone, Debug)]
+pub struct TagStats {
+    tag: String,
+    acquires: u64,
+    releases: u64,
+    active_guards: usize,
+    total_held: Duration,
+    longest_held: Duration,
+    histogram: Histogram,
+}
+
+impl TagStats {
+    /// Get the tag these statistics are for.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Get the number of times a guard acquired a wake lock with this tag.
+    pub fn acquires(&self) -> u64 {
+        self.acquires
+    }
+
+    /// Get the number of times a guard released a wake lock with this tag.
+    pub fn releases(&self) -> u64 {
+        self.releases
+    }
+
+    /// Get the number of guards currently holding a wake lock with this tag.
+    pub fn active_guards(&self) -> usize {
+        self.active_guards
+    }
+
+    /// Get the total time released guards held a wake lock with this tag.
+    /// Guards that are still holding it are not included.
+    pub fn total_held(&self) -> Duration {
+        self.total_held
+    }
+
+    /// Get the longest time a released guard held a wake lock with this tag.
+    pub fn longest_held(&self) -> Duration {
+        self.longest_held
+    }
+
+    /// Get the histogram of how long released guards held a wake lock with
+    /// this tag.
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+}
+
+/// A histogram of hold durations, with buckets set with
+/// [`set_histogram_buckets`].
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    bounds: Vec<Duration>,
+
+    /// Count for each bound, followed by the count of longer holds.
+    counts: Vec<u64>,
+
+    /// Total duration of all counted holds.
+    sum: Duration,
+}
+
+impl Histogram {
+    fn new(bounds: &[Duration]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: Duration::ZERO,
+        }
+    }
+
+    fn record(&mut self, held: Duration) {
+        let i = self.bounds.partition_point(|bound| *bound < held);
+
+        self.counts[i] += 1;
+        self.sum += held;
+    }
+
+    /// Get the buckets of the histogram in ascending order, each with its
+    /// inclusive upper bound and the number of holds longer than the previous
+    /// bucket's bound and at most as long as its own. The last bucket has no
+    /// upper bound, and counts holds longer than all other bounds.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        self.bounds
+            .iter()
+            .copied()
+            .map(Some)
+            .chain(Some(None))
+            .zip(self.counts.iter().copied())
+    }
+
+    /// Get the total number of holds counted.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Get the total duration of all holds counted. Unlike
+    /// [`TagStats::total_held`], this is cleared along with the counts when the
+    /// buckets are changed.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+}
+
+/// The guards currently holding a single wake lock.
+#[derive(Debug, Default)]
+pub(crate) struct ActiveGuards(Mutex<Vec<ActiveGuard>>);
+
+/// A guard currently holding a wake lock.
+#[derive(Clone, Debug)]
+pub(crate) struct ActiveGuard {
+    pub(crate) id: u64,
+    pub(crate) acquired_at: Instant,
+
+    /// When the system releases the guard's reference, if it was acquired
+    /// with a timeout.
+    pub(crate) deadline: Option<Instant>,
+
+    /// Where the guard was acquired.
+    pub(crate) caller: &'static Location<'static>,
+
+    /// Backtrace of where the guard was acquired.
+    #[cfg(feature = "audit")]
+    pub(crate) backtrace: Arc<Backtrace>,
+}
+
+impl ActiveGuards {
+    fn lock(&self) -> MutexGuard<'_, Vec<ActiveGuard>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn insert(&self, guard: ActiveGuard) {
+        self.lock().push(guard);
+    }
+
+    pub(crate) fn remove(&self, id: u64) {
+        let mut guards = self.lock();
+
+        if let Some(i) = guards.iter().position(|guard| guard.id == id) {
+            guards.remove(i);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Get when the longest-held guard acquired the wake lock, if any.
+    pub(crate) fn held_since(&self) -> Option<Instant> {
+        self.lock().first().map(|guard| guard.acquired_at)
+    }
+
+    /// Log where each guard was acquired, after the wake lock they hold was
+    /// released from under them.
+    #[cfg(feature = "audit")]
+    pub(crate) fn warn_backtraces(&self, tag: &str) {
+        for guard in self.snapshot() {
+            warn!(
+                "guard {} of wake lock \"{}\" still alive, acquired at {}:\n{}",
+                guard.id, tag, guard.caller, guard.backtrace
+            );
+        }
+    }
+
+    /// Get the guards in the order they acquired the wake lock.
+    pub(crate) fn snapshot(&self) -> Vec<ActiveGuard> {
+        self.lock().clone()
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    fmt,
+    future::Future,
+    panic::Location,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures_core::Stream;
+
+use crate::{hook, timer, worker::Task, Guard, WakeLock};
+
+/// Extension trait for keeping the device awake while a stream is producing
+/// items.
+///
+/// Requires the `stream` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use android_wakelock::WakeLockStreamExt;
+/// # use futures_core::Stream;
+///
+/// # fn example(messages: impl Stream<Item = String>) -> Result<(), Box<dyn std::error::Error>> {
+/// let wake_lock = android_wakelock::partial("myapp:push")?;
+///
+/// // Hold the wake lock while messages are arriving, and for up to 5 seconds
+/// // after the last one.
+/// let messages = messages.with_wake_lock(&wake_lock, Duration::from_secs(5));
+/// # Ok(())
+/// # }
+/// ```
+pub trait WakeLockStreamExt: Stream + Sized {
+    /// Hold the given wake lock while this stream is yielding items.
+    ///
+    /// The wake lock is acquired when the stream produces an item, before the
+    /// item is yielded, and is held until no new items have been produced for
+    /// the given `idle` duration. The wake lock is also released when the
+    /// stream ends or the returned stream is dropped. The idle timeout is
+    /// tracked on the crate's own timer thread, so no async runtime is needed.
+    ///
+    /// All JNI calls are made on the crate's background thread. If the wake
+    /// lock cannot be acquired, the error is passed to the hook set with
+    /// [`set_error_hook`][crate::set_error_hook] and the item is yielded
+    /// anyway.
+    #[track_caller]
+    fn with_wake_lock(self, wake_lock: &WakeLock, idle: Duration) -> WithWakeLockStream<Self> {
+        WithWakeLockStream {
+            stream: self,
+            wake_lock: WakeLock {
+                inner: wake_lock.inner.clone(),
+            },
+            caller: Location::caller(),
+            idle,
+            hold: Arc::new(Mutex::new(Hold {
+                guard: None,
+                last_item: Instant::now(),
+                timer_pending: false,
+            })),
+            acquire: None,
+            item: None,
+        }
+    }
+}
+
+impl<S: Stream> WakeLockStreamExt for S {}
+
+/// Stream returned by [`WakeLockStreamExt::with_wake_lock`].
+#[must_use = "streams do nothing unless polled"]
+pub struct WithWakeLockStream<S: Stream> {
+    stream: S,
+    wake_lock: WakeLock,
+
+    /// Where the stream was created, recorded as the call site of its guards.
+    caller: &'static Location<'static>,
+
+    idle: Duration,
+    hold: Arc<Mutex<Hold>>,
+
+    /// The pending acquire, if an item arrived while the lock was not held.
+    acquire: Option<Task<crate::Result<Guard>>>,
+
+    /// An item waiting to be yielded once the wake lock is acquired.
+    item: Option<S::Item>,
+}
+
+/// State shared with the idle timer.
+struct Hold {
+    guard: Option<Guard>,
+    last_item: Instant,
+    timer_pending: bool,
+}
+
+impl<S: Stream> WithWakeLockStream<S> {
+    /// Record that an item was produced, and make sure the idle timer is
+    /// running.
+    fn touch(&self) {
+        let mut hold = self.hold.lock().unwrap();
+
+        hold.last_item = Instant::now();
+
+        if !hold.timer_pending {
+            hold.timer_pending = true;
+            schedule_idle_check(self.hold.clone(), hold.last_item + self.idle, self.idle);
+        }
+    }
+}
+
+/// Release the held guard if no items have been produced for the idle
+/// duration, otherwise check again later.
+fn schedule_idle_check(hold: Arc<Mutex<Hold>>, deadline: Instant, idle: Duration) {
+    timer::schedule(deadline, move || {
+        let mut state = hold.lock().unwrap();
+        let deadline = state.last_item + idle;
+
+        if deadline <= Instant::now() {
+            state.timer_pending = false;
+            state.guard = None;
+        } else {
+            drop(state);
+            schedule_idle_check(hold, deadline, idle);
+        }
+    });
+}
+
+impl<S: Stream> Stream for WithWakeLockStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // SAFETY: The inner stream is never moved out of `self`, and it is only
+        // ever accessed through a pinned reference. All other fields are not
+        // structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if let Some(acquire) = this.acquire.as_mut() {
+            let result = match Pin::new(acquire).poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            this.acquire = None;
+
+            match result {
+                Ok(guard) => this.hold.lock().unwrap().guard = Some(guard),
+                Err(e) => hook::report_error(&this.wake_lock.inner.tag, &e),
+            }
+
+            this.touch();
+
+            return Poll::Ready(this.item.take());
+        }
+
+        // SAFETY: See above.
+        let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+
+        match stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                if this.hold.lock().unwrap().guard.is_some() {
+                    this.touch();
+                    return Poll::Ready(Some(item));
+                }
+
+                // Acquire the wake lock before handing out the item.
+                this.item = Some(item);
+                this.acquire = Some(this.wake_lock.acquire_task_at(this.caller));
+                cx.waker().wake_by_ref();
+
+                Poll::Pending
+            }
+            Poll::Ready(None) => {
+                this.hold.lock().unwrap().guard = None;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S: Stream> Drop for WithWakeLockStream<S> {
+    fn drop(&mut self) {
+        if let Ok(mut hold) = self.hold.lock() {
+            hold.guard = None;
+        }
+    }
+}
+
+impl<S: Stream> fmt::Debug for WithWakeLockStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WithWakeLockStream")
+            .field("wake_lock", &self.wake_lock)
+            .field("idle", &self.idle)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,93 @@
+//! Information about the device and the Android version it is running.
+//!
+//! Values are read from `android.os.Build` the first time any of them is
+//! requested, and cached for the remainder of the process.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use android_wakelock::sys;
+//!
+//! if sys::api_level()? >= 34 {
+//!     // Android 14 or higher...
+//! }
+//!
+//! println!("running on a {} {}", sys::manufacturer()?, sys::model()?);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::sync::OnceLock;
+
+use jni::JNIEnv;
+
+use crate::{catch_exceptions, java_string, java_vm, Result};
+
+#[derive(Debug)]
+struct BuildInfo {
+    api_level: i32,
+    manufacturer: String,
+    model: String,
+}
+
+static BUILD_INFO: OnceLock<BuildInfo> = OnceLock::new();
+
+/// Get the API level of the Android version the device is running, from
+/// `Build.VERSION.SDK_INT`.
+pub fn api_level() -> Result<i32> {
+    Ok(build_info()?.api_level)
+}
+
+/// Get the manufacturer of the device, from `Build.MANUFACTURER`.
+pub fn manufacturer() -> Result<&'static str> {
+    Ok(&build_info()?.manufacturer)
+}
+
+/// Get the end-user-visible name of the device model, from `Build.MODEL`.
+pub fn model() -> Result<&'static str> {
+    Ok(&build_info()?.model)
+}
+
+/// Get the API level using an already attached environment.
+pub(crate) fn api_level_with(env: &mut JNIEnv<'_>) -> Result<i32> {
+    Ok(build_info_with(env)?.api_level)
+}
+
+fn build_info() -> Result<&'static BuildInfo> {
+    if let Some(info) = BUILD_INFO.get() {
+        return Ok(info);
+    }
+
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    build_info_with(&mut env)
+}
+
+fn build_info_with(env: &mut JNIEnv<'_>) -> Result<&'static BuildInfo> {
+    if let Some(info) = BUILD_INFO.get() {
+        return Ok(info);
+    }
+
+    // Errors are not cached, so reading is attempted again next time.
+    let info = catch_exceptions(env, |env| {
+        let api_level = env
+            .get_static_field("android/os/Build$VERSION", "SDK_INT", "I")?
+            .i()?;
+        let manufacturer = env
+            .get_static_field("android/os/Build", "MANUFACTURER", "Ljava/lang/String;")?
+            .l()?;
+        let manufacturer = java_string(env, manufacturer)?.unwrap_or_default();
+        let model = env
+            .get_static_field("android/os/Build", "MODEL", "Ljava/lang/String;")?
+            .l()?;
+        let model = java_string(env, model)?.unwrap_or_default();
+
+        Ok(BuildInfo {
+            api_level,
+            manufacturer,
+            model,
+        })
+    })?;
+
+    Ok(BUILD_INFO.get_or_init(|| info))
+}
@@ -0,0 +1,102 @@
+//! A wake lock backend that uses the kernel's `/sys/power/wake_lock`
+//! interface directly instead of going through the framework.
+//!
+//! Writing a name to `/sys/power/wake_lock` acquires a kernel wake lock with
+//! that name, optionally followed by a timeout in nanoseconds, and writing the
+//! name to `/sys/power/wake_unlock` releases it. Kernel wake locks are not
+//! reference counted, so the references held by guards are counted by the
+//! crate, and the kernel wake lock is updated whenever the counts change.
+
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    time::Instant,
+};
+
+use crate::{Error, ErrorKind, Inner, Object, Result};
+
+const WAKE_LOCK: &str = "/sys/power/wake_lock";
+const WAKE_UNLOCK: &str = "/sys/power/wake_unlock";
+
+impl Inner {
+    /// Record an acquired reference and update the kernel wake lock.
+    pub(crate) fn acquire_sysfs(
+        &self,
+        name: &str,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Instant>> {
+        let mut object = self.object();
+
+        match deadline {
+            Some(deadline) => object.deadlines.push(deadline),
+            None => object.held += 1,
+        }
+
+        if let Err(e) = update(name, &mut object) {
+            object.released(deadline);
+            return Err(e);
+        }
+
+        Ok(deadline)
+    }
+
+    /// Record a released reference and update the kernel wake lock.
+    pub(crate) fn release_sysfs(&self, name: &str, deadline: Option<Instant>) -> Result<()> {
+        let mut object = self.object();
+
+        if deadline.is_none() && object.held == 0 {
+            return Err(Error::new(
+                ErrorKind::UnderLocked,
+                format!("wake lock \"{}\" is not held", self.tag),
+            ));
+        }
+
+        object.released(deadline);
+        update(name, &mut object)
+    }
+}
+
+/// Returns true if the kernel wake lock is currently held according to the
+/// references recorded for it.
+pub(crate) fn is_held(object: &Object) -> bool {
+    let now = Instant::now();
+
+    object.held > 0 || object.deadlines.iter().any(|deadline| *deadline > now)
+}
+
+/// Update the kernel wake lock to match the references currently held.
+fn update(name: &str, object: &mut Object) -> Result<()> {
+    let now = Instant::now();
+
+    object.deadlines.retain(|deadline| *deadline > now);
+
+    if object.held > 0 {
+        write(WAKE_LOCK, name)
+    } else if let Some(deadline) = object.deadlines.iter().max() {
+        let timeout = (*deadline - now).as_nanos().max(1);
+
+        write(WAKE_LOCK, &format!("{} {}", name, timeout))
+    } else {
+        write(WAKE_UNLOCK, name)
+    }
+}
+
+fn write(path: &str, contents: &str) -> Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| {
+            let kind = match e.kind() {
+                io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+                io::ErrorKind::NotFound => ErrorKind::Unsupported,
+                _ => ErrorKind::Other,
+            };
+
+            Error::new(kind, format!("failed to write to {}: {}", path, e))
+        })?;
+
+    trace!("wrote \"{}\" to {}", contents, path);
+
+    Ok(())
+}
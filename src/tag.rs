@@ -0,0 +1,78 @@
+//! Hygiene checks for wake lock tags.
+
+use crate::{Error, ErrorKind, Result};
+
+/// Tags longer than this are unlikely to be intentional and make debugging
+/// output harder to read.
+const MAX_LEN: usize = 128;
+
+/// Shortest run of digits that is considered to look like a timestamp.
+const TIMESTAMP_DIGITS: usize = 8;
+
+/// Check that a tag follows the recommended naming conventions.
+///
+/// Tags that cannot work as intended are rejected, while tags that merely make
+/// debugging harder or may leak personal information only cause a warning to
+/// be logged.
+pub(crate) fn validate(tag: &str) -> Result<()> {
+    if tag.trim().is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidConfig,
+            "wake lock tag must not be empty",
+        ));
+    }
+
+    // The platform uses tags wrapped in asterisks for its own wake locks, such
+    // as `*alarm*` and `*job*/...`.
+    if tag.starts_with('*') {
+        return Err(Error::new(
+            ErrorKind::InvalidConfig,
+            format!(
+                "wake lock tag \"{}\" collides with tags reserved by the platform",
+                tag
+            ),
+        ));
+    }
+
+    if tag.len() > MAX_LEN {
+        warn!("wake lock tag \"{}\" is longer than {} bytes", tag, MAX_LEN);
+    }
+
+    if has_digit_run(tag, TIMESTAMP_DIGITS) {
+        warn!(
+            "wake lock tag \"{}\" looks like it contains a timestamp or other variable data; use a constant tag instead",
+            tag
+        );
+    }
+
+    if looks_like_email(tag) {
+        warn!(
+            "wake lock tag \"{}\" looks like it contains an email address; never include personally identifiable information in tags",
+            tag
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns true if the string contains at least `len` consecutive ASCII
+/// digits.
+fn has_digit_run(s: &str, len: usize) -> bool {
+    s.as_bytes()
+        .split(|b| !b.is_ascii_digit())
+        .any(|run| run.len() >= len)
+}
+
+/// Returns true if the string contains something shaped like `user@host.tld`.
+fn looks_like_email(s: &str) -> bool {
+    s.split(|c: char| c.is_whitespace() || c == ':' || c == '/')
+        .any(|word| match word.split_once('@') {
+            Some((user, host)) => {
+                !user.is_empty()
+                    && host
+                        .split_once('.')
+                        .is_some_and(|(name, tld)| !name.is_empty() && !tld.is_empty())
+            }
+            None => false,
+        })
+}
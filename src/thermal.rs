@@ -0,0 +1,295 @@
+//! Queries about the thermal state of the device.
+//!
+//! Apps holding wake locks to perform heavy computation should back off when
+//! the device is getting hot and starting to throttle, both to avoid degrading
+//! the user experience and because throttled work takes longer, keeping the
+//! wake lock held for longer.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use android_wakelock::thermal::{self, ThermalStatus};
+//!
+//! if thermal::current_thermal_status()? >= ThermalStatus::Severe {
+//!     // Postpone heavy work until the device has cooled down...
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    fmt,
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use jni::{
+    objects::{GlobalRef, JValue},
+    JavaVM,
+};
+
+use crate::{
+    callback, catch_exceptions, hook, java_vm, power_manager, sys, Error, ErrorKind, Result,
+};
+
+/// The thermal status of the device, in increasing order of severity.
+///
+/// Statuses can be compared to check whether the device is at least as hot as
+/// a given status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ThermalStatus {
+    /// Not under throttling.
+    None,
+
+    /// Light throttling where the user experience is not impacted.
+    Light,
+
+    /// Moderate throttling where the user experience is not largely impacted.
+    Moderate,
+
+    /// Severe throttling where the user experience is largely impacted.
+    Severe,
+
+    /// The platform has done everything to reduce power.
+    Critical,
+
+    /// Key components in the platform are shutting down due to thermal
+    /// conditions, and device functionality is limited.
+    Emergency,
+
+    /// The device needs to shut down immediately.
+    Shutdown,
+}
+
+impl ThermalStatus {
+    fn from_raw(status: i32) -> Self {
+        match status {
+            1 => Self::Light,
+            2 => Self::Moderate,
+            3 => Self::Severe,
+            4 => Self::Critical,
+            5 => Self::Emergency,
+            6 => Self::Shutdown,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Get the current thermal status of the device.
+///
+/// This wraps `PowerManager.getCurrentThermalStatus()`. This was added in API
+/// level 29; on older devices this always returns [`ThermalStatus::None`].
+pub fn current_thermal_status() -> Result<ThermalStatus> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let power_manager = power_manager(&mut env)?;
+
+    let result = catch_exceptions(&mut env, |env| {
+        env.call_method(&power_manager, "getCurrentThermalStatus", "()I", &[])?
+            .i()
+    });
+
+    match result {
+        Ok(status) => Ok(ThermalStatus::from_raw(status)),
+        Err(e) if e.is_method_not_found() => Ok(ThermalStatus::None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get an estimate of the device's thermal headroom in the given number of
+/// seconds from now.
+///
+/// This wraps `PowerManager.getThermalHeadroom(int)`. A value of `1.0`
+/// corresponds to the point at which the device reaches
+/// [`ThermalStatus::Severe`] throttling; values above it mean throttling is
+/// expected to be even more severe. `forecast_seconds` must be between 0
+/// and 60.
+///
+/// Returns `None` if the device does not support thermal headroom
+/// predictions, or if they are not available right now, for example because
+/// the method was called too frequently. This was added in API level 30; on
+/// older devices this always returns `None`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::thermal;
+///
+/// match thermal::thermal_headroom(10)? {
+///     Some(headroom) if headroom > 0.9 => {
+///         // Close to throttling, scale the work down...
+///     }
+///     _ => {}
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn thermal_headroom(forecast_seconds: i32) -> Result<Option<f32>> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+    let power_manager = power_manager(&mut env)?;
+
+    let result = catch_exceptions(&mut env, |env| {
+        env.call_method(
+            &power_manager,
+            "getThermalHeadroom",
+            "(I)F",
+            &[JValue::from(forecast_seconds)],
+        )?
+        .f()
+    });
+
+    match result {
+        Ok(headroom) if headroom.is_nan() => Ok(None),
+        Ok(headroom) => Ok(Some(headroom)),
+        Err(e) if e.is_method_not_found() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Subscribe to changes of the device's thermal status.
+///
+/// This registers an `OnThermalStatusChangedListener` with
+/// `PowerManager.addThermalStatusListener`, and returns a subscription that
+/// receives every new status as it changes. The platform reports the current
+/// status right after subscribing. The listener is removed again when the
+/// subscription is dropped.
+///
+/// Since listeners can only be implemented in Java, this uses the
+/// `rs.android_wakelock.ThermalStatusListener` class embedded in the crate,
+/// unless the app includes it itself. On devices older than API level 29, an
+/// error of kind [`ErrorKind::Unsupported`][crate::ErrorKind::Unsupported] is
+/// returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::thermal::{self, ThermalStatus};
+///
+/// let subscription = thermal::subscribe()?;
+///
+/// for status in subscription.iter() {
+///     if status >= ThermalStatus::Severe {
+///         // Back off from heavy work...
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn subscribe() -> Result<ThermalSubscription> {
+    let vm = java_vm()?;
+    let mut env = vm.attach_current_thread()?;
+
+    if sys::api_level_with(&mut env)? < 29 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "thermal status listeners require API level 29",
+        ));
+    }
+
+    let class = callback::load_class(&mut env, LISTENER_CLASS)?;
+    let power_manager = power_manager(&mut env)?;
+    let (sender, receiver) = mpsc::channel();
+
+    let handle = callback::register(move |_, status, _| {
+        let _ = sender.send(ThermalStatus::from_raw(status));
+    });
+
+    let listener = catch_exceptions(&mut env, |env| {
+        let listener = env.new_object(&class, "(J)V", &[JValue::from(handle)])?;
+
+        env.call_method(
+            &power_manager,
+            "addThermalStatusListener",
+            "(Landroid/os/PowerManager$OnThermalStatusChangedListener;)V",
+            &[JValue::from(&listener)],
+        )?;
+
+        env.new_global_ref(listener)
+    });
+
+    let listener = match listener {
+        Ok(listener) => listener,
+        Err(e) => {
+            callback::unregister(handle);
+            return Err(e);
+        }
+    };
+
+    drop(env);
+
+    Ok(ThermalSubscription {
+        receiver,
+        listener,
+        handle,
+        vm,
+    })
+}
+
+const LISTENER_CLASS: &str = "rs/android_wakelock/ThermalStatusListener";
+
+/// A subscription to thermal status changes, returned by [`subscribe`].
+///
+/// The listener is removed when the subscription is dropped.
+pub struct ThermalSubscription {
+    receiver: Receiver<ThermalStatus>,
+    listener: GlobalRef,
+    handle: i64,
+    vm: JavaVM,
+}
+
+impl ThermalSubscription {
+    /// Wait for the next thermal status change.
+    pub fn recv(&self) -> Option<ThermalStatus> {
+        self.receiver.recv().ok()
+    }
+
+    /// Wait for the next thermal status change for at most the given timeout.
+    ///
+    /// Returns `None` if the status did not change before the timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<ThermalStatus> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Get the next thermal status change if one has already arrived, without
+    /// waiting.
+    pub fn try_recv(&self) -> Option<ThermalStatus> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Returns an iterator that waits for thermal status changes.
+    pub fn iter(&self) -> impl Iterator<Item = ThermalStatus> + '_ {
+        self.receiver.iter()
+    }
+
+    fn remove_listener(&self) -> Result<()> {
+        let mut env = self.vm.attach_current_thread()?;
+        let power_manager = power_manager(&mut env)?;
+
+        catch_exceptions(&mut env, |env| {
+            env.call_method(
+                &power_manager,
+                "removeThermalStatusListener",
+                "(Landroid/os/PowerManager$OnThermalStatusChangedListener;)V",
+                &[JValue::from(&self.listener)],
+            )?
+            .v()
+        })
+    }
+}
+
+impl fmt::Debug for ThermalSubscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThermalSubscription")
+            .field("listener", &self.listener)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ThermalSubscription {
+    fn drop(&mut self) {
+        callback::unregister(self.handle);
+
+        if let Err(e) = self.remove_listener() {
+            hook::report_error("thermal status listener", &e);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! A minimal background timer owned by the crate for running delayed jobs.
+//!
+//! The timer thread is spawned lazily the first time it is needed and lives for
+//! the remainder of the process. Jobs should be short, since they are run
+//! directly on the timer thread.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    panic::{self, AssertUnwindSafe},
+    sync::{Condvar, Mutex, OnceLock},
+    thread,
+    time::Instant,
+};
+
+use crate::hook;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Schedule a job to be run on the timer thread at the given deadline.
+pub(crate) fn schedule<F>(deadline: Instant, f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let timer = timer();
+    let mut queue = timer.queue.lock().unwrap();
+
+    queue.sequence += 1;
+
+    let entry = Entry {
+        deadline,
+        sequence: queue.sequence,
+        job: Box::new(f),
+    };
+
+    queue.entries.push(Reverse(entry));
+    timer.condvar.notify_one();
+}
+
+fn timer() -> &'static Timer {
+    static TIMER: OnceLock<Timer> = OnceLock::new();
+
+    TIMER.get_or_init(|| {
+        thread::Builder::new()
+            .name(String::from("android-wakelock-timer"))
+            .spawn(run)
+            .expect("failed to spawn wake lock timer thread");
+
+        Timer {
+            queue: Mutex::new(Queue {
+                entries: BinaryHeap::new(),
+                sequence: 0,
+            }),
+            condvar: Condvar::new(),
+        }
+    })
+}
+
+fn run() {
+    let timer = timer();
+    let mut queue = timer.queue.lock().unwrap();
+
+    loop {
+        let now = Instant::now();
+
+        match queue.entries.peek() {
+            Some(Reverse(entry)) if entry.deadline <= now => {
+                let Reverse(entry) = queue.entries.pop().unwrap();
+
+                // Run the job without holding the lock, so that it may schedule
+                // further jobs. A panicking job must not stop the timer, since
+                // later jobs would never run.
+                drop(queue);
+
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(entry.job)) {
+                    hook::report_panic("", "a job on the timer thread", payload);
+                }
+
+                queue = timer.queue.lock().unwrap();
+            }
+            Some(Reverse(entry)) => {
+                let timeout = entry.deadline - now;
+                queue = timer.condvar.wait_timeout(queue, timeout).unwrap().0;
+            }
+            None => {
+                queue = timer.condvar.wait(queue).unwrap();
+            }
+        }
+    }
+}
+
+struct Timer {
+    queue: Mutex<Queue>,
+    condvar: Condvar,
+}
+
+struct Queue {
+    entries: BinaryHeap<Reverse<Entry>>,
+
+    /// Used to keep jobs with the same deadline in submission order.
+    sequence: u64,
+}
+
+struct Entry {
+    deadline: Instant,
+    sequence: u64,
+    job: Job,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.deadline, self.sequence).cmp(&(other.deadline, other.sequence))
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    fmt,
+    future::Future,
+    panic::Location,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+};
+
+use crate::{hook, worker, Guard, Inner, WakeLock};
+
+/// Keeps the device awake while any tracked job is running.
+///
+/// Jobs are registered with [`track`][TaskTracker::track], and the wake lock is
+/// held as long as at least one tracked job has not yet completed or been
+/// dropped. This models "keep the CPU awake while any background work exists"
+/// directly, without having to coordinate guards between jobs.
+///
+/// Trackers are cheap to clone, and all clones share the same set of jobs.
+/// Tracking does not depend on any particular async runtime, and all JNI calls
+/// are made on the crate's background thread. Errors acquiring or releasing the
+/// wake lock are passed to the hook set with
+/// [`set_error_hook`][crate::set_error_hook].
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::TaskTracker;
+///
+/// # async fn sync_contacts() {}
+/// # async fn sync_photos() {}
+/// # fn spawn(_: impl std::future::Future<Output = ()> + Send + 'static) {}
+/// let wake_lock = android_wakelock::partial("myapp:background")?;
+/// let tracker = TaskTracker::new(&wake_lock);
+///
+/// // The wake lock is held until both jobs have finished.
+/// spawn(tracker.track(sync_contacts()));
+/// spawn(tracker.track(sync_photos()));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone)]
+pub struct TaskTracker {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    inner: Arc<Inner>,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// Number of tracked jobs still running.
+    running: usize,
+
+    /// Held whenever at least one job is running.
+    guard: Option<Guard>,
+}
+
+impl TaskTracker {
+    /// Create a new tracker that holds the given wake lock while any tracked
+    /// job is running.
+    pub fn new(wake_lock: &WakeLock) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                inner: wake_lock.inner.clone(),
+                state: Mutex::new(State {
+                    running: 0,
+                    guard: None,
+                }),
+            }),
+        }
+    }
+
+    /// Track the given job, keeping the device awake until it completes or is
+    /// dropped.
+    ///
+    /// The job counts as running from the moment this is called, so the wake
+    /// lock is acquired even before the returned future is first polled.
+    #[track_caller]
+    pub fn track<F: Future>(&self, future: F) -> Tracked<F> {
+        self.shared.start(Location::caller());
+
+        Tracked {
+            future,
+            shared: Some(self.shared.clone()),
+        }
+    }
+
+    /// Returns the number of tracked jobs that are still running.
+    pub fn len(&self) -> usize {
+        self.shared.lock().running
+    }
+
+    /// Returns true if there are no tracked jobs running.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Debug for TaskTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskTracker")
+            .field("tag", &self.shared.inner.tag)
+            .field("running", &self.len())
+            .finish()
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Count a new job as running, acquiring the guard on the worker thread
+    /// for the job tracked at the given location if it is the first one.
+    fn start(self: &Arc<Self>, location: &'static Location<'static>) {
+        let mut state = self.lock();
+
+        state.running += 1;
+
+        if state.running == 1 && state.guard.is_none() {
+            let shared = self.clone();
+
+            worker::execute(move || {
+                let inner = &shared.inner;
+
+                match inner
+                    .permit()
+                    .and_then(|permit| inner.acquire_on_worker(permit, location))
+                {
+                    Ok(guard) => {
+                        let mut state = shared.lock();
+
+                        // Jobs may have finished while the acquire was in
+                        // flight, or an earlier acquire may have already
+                        // succeeded. In either case the new guard is not
+                        // needed.
+                        if state.running > 0 && state.guard.is_none() {
+                            state.guard = Some(guard);
+                        }
+                    }
+                    Err(e) => hook::report_error(&inner.tag, &e),
+                }
+            });
+        }
+    }
+
+    fn finish(&self) {
+        let mut state = self.lock();
+
+        state.running -= 1;
+
+        if state.running == 0 {
+            // Guard is released on the worker thread.
+            drop(state.guard.take());
+        }
+    }
+}
+
+/// Future returned by [`TaskTracker::track`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Tracked<F> {
+    future: F,
+
+    /// The tracker this job belongs to, or `None` once the job has finished.
+    shared: Option<Arc<Shared>>,
+}
+
+impl<F: Future> Future for Tracked<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: The inner future is never moved out of `self`, and it is only
+        // ever accessed through a pinned reference. All other fields are
+        // `Unpin`.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        match future.poll(cx) {
+            Poll::Ready(output) => {
+                if let Some(shared) = this.shared.take() {
+                    shared.finish();
+                }
+
+                Poll::Ready(output)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F> Drop for Tracked<F> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            shared.finish();
+        }
+    }
+}
+
+impl<F> fmt::Debug for Tracked<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracked")
+            .field("finished", &self.shared.is_none())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,387 @@
+//! Keeping the Wi-Fi radio awake with Wi-Fi locks.
+//!
+//! A wake lock keeps the CPU running, but does not prevent the Wi-Fi radio from
+//! going to sleep when the screen is off, which can stall network transfers
+//! performed while holding one. A [`WifiLock`] wraps
+//! `WifiManager.createWifiLock` to keep Wi-Fi active, and is used the same way
+//! as a [`WakeLock`][crate::WakeLock]: acquiring it returns a [`Guard`] that
+//! releases it again when dropped.
+//!
+//! Like wake locks, acquiring a Wi-Fi lock requires the
+//! `android.permission.WAKE_LOCK` permission.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use android_wakelock::wifi::WifiLock;
+//!
+//! let wake_lock = android_wakelock::partial("myapp:download")?;
+//! let wifi_lock = WifiLock::builder("myapp:download").build()?;
+//!
+//! // Keep both the CPU and Wi-Fi awake during the download.
+//! let _wake_guard = wake_lock.acquire()?;
+//! let _wifi_guard = wifi_lock.acquire()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{fmt, sync::Arc};
+
+use jni::{
+    objects::{GlobalRef, JValue},
+    JNIEnv, JavaVM,
+};
+
+use crate::{
+    catch_exceptions, hook, java_vm, set_work_source, sys, system_service, tag, ApiFallback, Error,
+    ErrorKind, Result, WorkSource,
+};
+
+/// The mode of a Wi-Fi lock, determining how the Wi-Fi radio behaves while it
+/// is held.
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum WifiMode {
+    /// Keep the Wi-Fi radio on and associated with an access point. This is
+    /// the default.
+    ///
+    /// On API level 29 and higher the platform treats this the same as
+    /// [`WifiMode::HighPerf`].
+    #[default]
+    Full = 1,
+
+    /// Keep the Wi-Fi radio on, and disable power saving on it to provide
+    /// the highest throughput. Useful for large transfers such as streaming
+    /// media.
+    ///
+    /// From API level 34 the platform ignores this mode, and recommends
+    /// [`WifiMode::LowLatency`] instead.
+    HighPerf = 3,
+
+    /// Keep the Wi-Fi radio on with the lowest latency, at the cost of
+    /// higher power usage. Only takes effect while the app is in the
+    /// foreground and the screen is on, making it suitable for real-time
+    /// audio, video calls, and games.
+    ///
+    /// Requires API level 29 or higher; see
+    /// [`Builder::api_fallback`] for what happens on older versions.
+    LowLatency = 4,
+}
+
+impl WifiMode {
+    /// Get the minimum API level the mode is available on.
+    fn min_api_level(self) -> i32 {
+        match self {
+            Self::Full => 1,
+            Self::HighPerf => 12,
+            Self::LowLatency => 29,
+        }
+    }
+}
+
+/// A builder for configuring and creating a Wi-Fi lock.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    tag: String,
+    mode: WifiMode,
+    work_source: Option<WorkSource>,
+    validate_tag: bool,
+    api_fallback: ApiFallback,
+}
+
+impl Builder {
+    /// Set the mode of the Wi-Fi lock. The default is [`WifiMode::Full`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use android_wakelock::{
+    ///     wifi::{WifiLock, WifiMode},
+    ///     ApiFallback,
+    /// };
+    ///
+    /// // Use the lowest latency mode where available, and the high
+    /// // performance mode on older devices.
+    /// let wifi_lock = WifiLock::builder("myapp:voice")
+    ///     .mode(WifiMode::LowLatency)
+    ///     .api_fallback(ApiFallback::Degrade)
+    ///     .build()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn mode(mut self, mode: WifiMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set what to do when the mode is not supported on the device's API
+    /// level.
+    ///
+    /// By default, building fails with an error of kind
+    /// [`ErrorKind::Unsupported`]. With [`ApiFallback::Degrade`],
+    /// [`WifiMode::LowLatency`] falls back to [`WifiMode::HighPerf`] instead.
+    pub fn api_fallback(mut self, fallback: ApiFallback) -> Self {
+        self.api_fallback = fallback;
+        self
+    }
+
+    /// Attribute the battery usage of the Wi-Fi lock to the apps in the given
+    /// work source instead of the calling app.
+    ///
+    /// As with [`Builder::work_source`][crate::Builder::work_source] for wake
+    /// locks, this requires the `android.permission.UPDATE_DEVICE_STATS`
+    /// permission.
+    pub fn work_source(mut self, work_source: WorkSource) -> Self {
+        self.work_source = Some(work_source);
+        self
+    }
+
+    /// Do not validate the tag when building the Wi-Fi lock.
+    ///
+    /// Tags are validated the same way as for wake locks; see
+    /// [`Builder::skip_tag_validation`][crate::Builder::skip_tag_validation].
+    pub fn skip_tag_validation(mut self) -> Self {
+        self.validate_tag = false;
+        self
+    }
+
+    /// Get the tag the Wi-Fi lock will be created with.
+    pub fn get_tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Get the mode the Wi-Fi lock will be created with.
+    pub fn get_mode(&self) -> WifiMode {
+        self.mode
+    }
+
+    /// Creates a new Wi-Fi lock with the specified options.
+    pub fn build(&self) -> Result<WifiLock> {
+        if self.validate_tag {
+            tag::validate(&self.tag)?;
+        }
+
+        let vm = java_vm()?;
+        let mut env = vm.attach_current_thread()?;
+        let mode = self.resolve_mode(&mut env)?;
+        let wifi_lock = self.create(&mut env, mode)?;
+
+        drop(env);
+
+        Ok(WifiLock {
+            inner: Arc::new(Inner {
+                wifi_lock,
+                vm,
+                tag: self.tag.clone(),
+                mode,
+            }),
+        })
+    }
+
+    /// Get the mode to create the Wi-Fi lock with on the device's API level.
+    fn resolve_mode(&self, env: &mut JNIEnv<'_>) -> Result<WifiMode> {
+        let api_level = sys::api_level_with(env)?;
+
+        if api_level >= self.mode.min_api_level() {
+            return Ok(self.mode);
+        }
+
+        let reason = format!(
+            "Wi-Fi lock mode {:?} requires API level {} (tag \"{}\")",
+            self.mode,
+            self.mode.min_api_level(),
+            self.tag
+        );
+
+        match self.api_fallback {
+            ApiFallback::Degrade => {
+                warn!("{}, falling back to {:?}", reason, WifiMode::HighPerf);
+
+                Ok(WifiMode::HighPerf)
+            }
+            _ => Err(Error::new(ErrorKind::Unsupported, reason)),
+        }
+    }
+
+    fn create(&self, env: &mut JNIEnv<'_>, mode: WifiMode) -> Result<GlobalRef> {
+        let wifi_manager = system_service(env, "wifi")?;
+        let tag = env.new_string(&self.tag)?;
+
+        let wifi_lock = catch_exceptions(env, |env| {
+            env.call_method(
+                &wifi_manager,
+                "createWifiLock",
+                "(ILjava/lang/String;)Landroid/net/wifi/WifiManager$WifiLock;",
+                &[JValue::from(mode as i32), JValue::from(&tag)],
+            )?
+            .l()
+        })?;
+
+        let wifi_lock = env.new_global_ref(wifi_lock)?;
+
+        if let Some(work_source) = &self.work_source {
+            set_work_source(env, &wifi_lock, work_source)?;
+        }
+
+        debug!("created Wi-Fi lock \"{}\"", self.tag);
+
+        Ok(wifi_lock)
+    }
+}
+
+/// A Wi-Fi lock keeps the Wi-Fi radio awake while it is held.
+///
+/// Like wake locks, Wi-Fi locks are reference counted, and can be shared
+/// between threads, for example in an `Arc`. See [`WifiLock::acquire`].
+#[derive(Debug)]
+pub struct WifiLock {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    wifi_lock: GlobalRef,
+    vm: JavaVM,
+    tag: String,
+    mode: WifiMode,
+}
+
+impl Inner {
+    fn release(&self) -> Result<()> {
+        let mut env = self.vm.attach_current_thread()?;
+
+        catch_exceptions(&mut env, |env| {
+            env.call_method(&self.wifi_lock, "release", "()V", &[])?.v()
+        })
+        .map_err(|e| {
+            // Thrown by the platform when releasing more times than acquired.
+            if e.exception_class() == Some("java.lang.RuntimeException")
+                && e.exception_message()
+                    .is_some_and(|message| message.starts_with("WifiLock under-locked"))
+            {
+                Error::under_locked(e)
+            } else {
+                e
+            }
+        })?;
+
+        debug!("released Wi-Fi lock \"{}\"", self.tag);
+
+        Ok(())
+    }
+}
+
+impl WifiLock {
+    /// Create a new builder with the given tag for configuring and creating a
+    /// Wi-Fi lock.
+    pub fn builder<T: Into<String>>(tag: T) -> Builder {
+        Builder {
+            tag: tag.into(),
+            mode: WifiMode::Full,
+            work_source: None,
+            validate_tag: true,
+            api_fallback: ApiFallback::Error,
+        }
+    }
+
+    /// Get the tag of this Wi-Fi lock.
+    pub fn tag(&self) -> &str {
+        &self.inner.tag
+    }
+
+    /// Get the mode of this Wi-Fi lock. This may differ from the mode it was
+    /// configured with if it was not supported and
+    /// [`ApiFallback::Degrade`] was used.
+    pub fn mode(&self) -> WifiMode {
+        self.inner.mode
+    }
+
+    /// Returns true if the Wi-Fi lock has outstanding references not yet
+    /// released.
+    pub fn is_held(&self) -> Result<bool> {
+        let mut env = self.inner.vm.attach_current_thread()?;
+
+        catch_exceptions(&mut env, |env| {
+            env.call_method(&self.inner.wifi_lock, "isHeld", "()Z", &[])?
+                .z()
+        })
+    }
+
+    /// Acquire the Wi-Fi lock, keeping the Wi-Fi radio awake until the
+    /// returned [`Guard`] is released.
+    ///
+    /// Wi-Fi locks are reference counted, so the radio stays awake until all
+    /// acquired references have been released.
+    pub fn acquire(&self) -> Result<Guard> {
+        let mut env = self.inner.vm.attach_current_thread()?;
+
+        catch_exceptions(&mut env, |env| {
+            env.call_method(&self.inner.wifi_lock, "acquire", "()V", &[])?
+                .v()
+        })
+        .map_err(|e| {
+            if e.exception_class() == Some("java.lang.SecurityException") {
+                Error::missing_permission(e, false)
+            } else {
+                e
+            }
+        })?;
+
+        debug!("acquired Wi-Fi lock \"{}\"", self.inner.tag);
+
+        Ok(Guard {
+            inner: self.inner.clone(),
+            released: false,
+        })
+    }
+}
+
+/// A guard for an acquired Wi-Fi lock.
+///
+/// The Wi-Fi lock is released automatically when the guard is dropped. Errors
+/// releasing it on drop are passed to the hook set with
+/// [`set_error_hook`][crate::set_error_hook]; use [`release`][Guard::release]
+/// to handle them directly.
+pub struct Guard {
+    inner: Arc<Inner>,
+    released: bool,
+}
+
+impl Guard {
+    /// Releases the Wi-Fi lock, returning an error if the underlying API threw
+    /// an exception.
+    pub fn release(mut self) -> Result<()> {
+        self.released = true;
+        self.inner.release()
+    }
+
+    /// Attribute the battery usage of the Wi-Fi lock to the apps in the given
+    /// work source from now on, replacing any work source set previously.
+    ///
+    /// This works the same as
+    /// [`Guard::update_work_source`][crate::Guard::update_work_source] for
+    /// wake locks, and applies to the Wi-Fi lock as a whole.
+    pub fn update_work_source(&self, work_source: &WorkSource) -> Result<()> {
+        let mut env = self.inner.vm.attach_current_thread()?;
+
+        set_work_source(&mut env, &self.inner.wifi_lock, work_source)
+    }
+}
+
+impl fmt::Debug for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Guard")
+            .field("wifi_lock", &self.inner.wifi_lock)
+            .field("tag", &self.inner.tag)
+            .finish()
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if !self.released {
+            if let Err(e) = self.inner.release() {
+                hook::report_error(&self.inner.tag, &e);
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use jni::{
+    objects::{JObject, JValue},
+    JNIEnv,
+};
+
+/// A set of apps to attribute a wake lock's battery usage to.
+///
+/// By default, the battery cost of holding a wake lock is attributed to the app
+/// that acquired it. System and other privileged apps that hold wake locks on
+/// behalf of other apps can use a work source to attribute the cost to those
+/// apps instead with [`Builder::work_source`][crate::Builder::work_source], or
+/// with [`Guard::update_work_source`][crate::Guard::update_work_source] to
+/// change the attribution of a wake lock that is already held. Wi-Fi locks
+/// support the same with [`wifi::Builder::work_source`][crate::wifi::Builder::work_source]
+/// and [`wifi::Guard::update_work_source`][crate::wifi::Guard::update_work_source].
+///
+/// Setting a work source requires the `android.permission.UPDATE_DEVICE_STATS`
+/// permission, which is only granted to system apps.
+///
+/// # Examples
+///
+/// ```no_run
+/// use android_wakelock::{WakeLock, WorkSource};
+///
+/// # let client_uid = 10123;
+/// let wake_lock = WakeLock::builder("myservice:client")
+///     .work_source(WorkSource::new().add(client_uid))
+///     .build()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WorkSource {
+    entries: Vec<(u32, Option<String>)>,
+}
+
+impl WorkSource {
+    /// Create a new empty work source.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the app with the given uid to this work source.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, uid: u32) -> Self {
+        self.entries.push((uid, None));
+        self
+    }
+
+    /// Add the app with the given uid and package name to this work source.
+    pub fn add_named<T: Into<String>>(mut self, uid: u32, package: T) -> Self {
+        self.entries.push((uid, Some(package.into())));
+        self
+    }
+
+    /// Returns the number of apps in this work source.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if this work source contains no apps.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Construct the equivalent `android.os.WorkSource` Java object.
+    pub(crate) fn to_java<'local>(
+        &self,
+        env: &mut JNIEnv<'local>,
+    ) -> jni::errors::Result<JObject<'local>> {
+        let work_source = env.new_object("android/os/WorkSource", "()V", &[])?;
+
+        for (uid, package) in &self.entries {
+            let uid = JValue::from(*uid as i32);
+
+            match package {
+                Some(package) => {
+                    let package = env.new_string(package)?;
+
+                    env.call_method(
+                        &work_source,
+                        "add",
+                        "(ILjava/lang/String;)Z",
+                        &[uid, JValue::from(&package)],
+                    )?;
+                }
+                None => {
+                    env.call_method(&work_source, "add", "(I)Z", &[uid])?;
+                }
+            }
+        }
+
+        Ok(work_source)
+    }
+}
+
+impl From<u32> for WorkSource {
+    fn from(uid: u32) -> Self {
+        Self::new().add(uid)
+    }
+}
+
+impl FromIterator<u32> for WorkSource {
+    fn from_iter<I: IntoIterator<Item = u32>>(uids: I) -> Self {
+        Self {
+            entries: uids.into_iter().map(|uid| (uid, None)).collect(),
+        }
+    }
+}
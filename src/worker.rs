@@ -0,0 +1,181 @@
+//! A dedicated background thread owned by the crate for performing JNI calls
+//! off of the caller's thread.
+//!
+//! The worker thread is spawned lazily the first time it is needed and lives
+//! for the remainder of the process. Jobs are executed one at a time in the
+//! order they were submitted.
+//!
+//! A job that panics does not take the thread down with it. The panic is
+//! either passed on to whoever waits for the job's result, or reported to the
+//! error hook if nobody does.
+
+use std::{
+    cell::Cell,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{mpsc, Arc, Condvar, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use crate::hook;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+thread_local! {
+    /// Whether the current thread is the worker thread.
+    static IS_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Submit a job to be executed on the worker thread, returning a handle that
+/// can be used to wait for its result.
+///
+/// If called from a job already running on the worker thread, the job is run
+/// right away instead, since waiting for it to be picked up from the queue
+/// would block the worker thread forever.
+pub(crate) fn run<T, F>(f: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (task, complete) = pending_result();
+
+    if IS_WORKER.get() {
+        complete(panic::catch_unwind(AssertUnwindSafe(f)));
+    } else {
+        execute(move || complete(panic::catch_unwind(AssertUnwindSafe(f))));
+    }
+
+    task
+}
+
+/// Create a task that is completed by calling the returned function, for jobs
+/// that run somewhere other than the worker thread.
+pub(crate) fn pending<T>() -> (Task<T>, impl FnOnce(T) + Send + 'static)
+where
+    T: Send + 'static,
+{
+    let (task, complete) = pending_result();
+
+    (task, move |value| complete(Ok(value)))
+}
+
+/// Create a task that is completed with either a value, or the payload of a
+/// panic that is resumed in whoever waits for the task.
+fn pending_result<T>() -> (Task<T>, impl FnOnce(thread::Result<T>) + Send + 'static)
+where
+    T: Send + 'static,
+{
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            value: None,
+            waker: None,
+        }),
+        condvar: Condvar::new(),
+    });
+    let task = Task {
+        shared: shared.clone(),
+    };
+
+    let complete = move |value| {
+        let mut state = shared.state.lock().unwrap();
+
+        state.value = Some(value);
+        shared.condvar.notify_all();
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    };
+
+    (task, complete)
+}
+
+/// Submit a job to be executed on the worker thread without waiting for it to
+/// complete.
+pub(crate) fn execute<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    static SENDER: OnceLock<mpsc::Sender<Job>> = OnceLock::new();
+
+    let sender = SENDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+
+        thread::Builder::new()
+            .name(String::from("android-wakelock"))
+            .spawn(move || {
+                IS_WORKER.set(true);
+
+                for job in receiver {
+                    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                        hook::report_panic("", "a job on the background thread", payload);
+                    }
+                }
+            })
+            .expect("failed to spawn wake lock worker thread");
+
+        sender
+    });
+
+    // The receiver is never dropped since the worker thread never exits, even
+    // if a job panics, so this cannot fail.
+    let _ = sender.send(Box::new(f));
+}
+
+/// A handle to a job running on the worker thread.
+///
+/// The result can be retrieved either by awaiting the task as a future, or by
+/// blocking the current thread with [`Task::wait`].
+#[derive(Debug)]
+pub(crate) struct Task<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Task<T> {
+    /// Block the current thread until the job completes and return its result.
+    ///
+    /// If the job panicked, the panic is resumed on the current thread.
+    pub(crate) fn wait(self) -> T {
+        let mut state = self.shared.state.lock().unwrap();
+
+        loop {
+            if let Some(value) = state.value.take() {
+                return value.unwrap_or_else(|payload| panic::resume_unwind(payload));
+            }
+
+            state = self.shared.condvar.wait(state).unwrap();
+        }
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        match state.value.take() {
+            Some(value) => {
+                Poll::Ready(value.unwrap_or_else(|payload| panic::resume_unwind(payload)))
+            }
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct State<T> {
+    value: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}